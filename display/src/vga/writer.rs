@@ -3,7 +3,8 @@ use vga::color::Color;
 use vga::color::ColorVariant;
 use core::fmt;
 
-const VGA_ADDRESS: usize = 0xb8000;
+/// Physical address of vga text buffer
+pub const VGA_ADDRESS: usize = 0xb8000;
 const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
 
@@ -14,9 +15,16 @@ pub struct Writer {
 
 impl Writer {
     pub fn new() -> Writer {
+        Writer::from_address(VGA_ADDRESS)
+    }
+
+    /// Creates writer for vga text buffer mapped at provided virtual address
+    /// # Arguments
+    /// * `buffer_address` - virtual address of vga text buffer
+    pub fn from_address(buffer_address: usize) -> Writer {
         Writer {
             column_position: 0,
-            chars: unsafe { &mut (*(VGA_ADDRESS as *mut _)) },
+            chars: unsafe { &mut (*(buffer_address as *mut _)) },
         }
     }
 
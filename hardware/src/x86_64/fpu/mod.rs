@@ -0,0 +1,64 @@
+use ::x86_64::registers;
+
+/// Size of FXSAVE area
+pub const FPU_STATE_SIZE : usize = 512;
+
+// default control word: every floating point exception is masked
+const DEFAULT_CONTROL_WORD : u16 = 0x037f;
+// default SSE control and status register: every SIMD exception is masked
+const DEFAULT_MXCSR : u32 = 0x1f80;
+
+/// x87 FPU and SSE state saved by FXSAVE instruction
+#[repr(C)]
+#[repr(align(16))]
+pub struct FpuState {
+    area : [u8; FPU_STATE_SIZE]
+}
+
+impl FpuState {
+
+    /// Creates state of freshly initialized FPU
+    pub fn new() -> Self {
+        let mut area = [0; FPU_STATE_SIZE];
+
+        area[0] = DEFAULT_CONTROL_WORD as u8;
+        area[1] = (DEFAULT_CONTROL_WORD >> 8) as u8;
+
+        for i in 0 .. 4 {
+            area[24 + i] = (DEFAULT_MXCSR >> (8 * i)) as u8;
+        }
+
+        FpuState {
+            area
+        }
+    }
+
+    /// Saves current FPU state (FXSAVE)
+    /// # Safety
+    /// FPU must be usable, e.g. task switched flag must be clear, otherwise device not available fault is raised
+    #[inline(always)]
+    pub unsafe fn save(&mut self) {
+        asm!("fxsave ($0)" :: "r" (self.area.as_mut_ptr()) : "memory" : "volatile");
+    }
+
+    /// Loads FPU state (FXRSTOR)
+    /// # Safety
+    /// FPU must be usable, e.g. task switched flag must be clear, otherwise device not available fault is raised
+    #[inline(always)]
+    pub unsafe fn restore(&self) {
+        asm!("fxrstor ($0)" :: "r" (self.area.as_ptr()) : "memory" : "volatile");
+    }
+}
+
+/// Sets task switched flag (CR0.TS), the next FPU or SSE instruction raises device not available fault.
+/// Used to save FPU state lazily, only when a switched in process actually uses FPU.
+#[inline(always)]
+pub unsafe fn set_task_switched() {
+    registers::cr0_write(registers::cr0() | registers::TASK_SWITCHED);
+}
+
+/// Clears task switched flag (CR0.TS), FPU instructions are executed again
+#[inline(always)]
+pub unsafe fn clear_task_switched() {
+    asm!("clts" :::: "volatile");
+}
@@ -0,0 +1,136 @@
+use core::mem;
+
+/// Maximum number of 8 byte entries of descriptor table, task state segment descriptor takes 2 entries
+pub const MAX_GDT_ENTRIES : usize = 8;
+
+// 64 bit code segment, the same as the one set up by boot code
+const KERNEL_CODE_SEGMENT : u64 = (1 << 43) | (1 << 44) | (1 << 47) | (1 << 53);
+
+// available 64 bit task state segment
+const TSS_AVAILABLE_TYPE : u64 = 0b1001 << 40;
+const SEGMENT_PRESENT : u64 = 1 << 47;
+
+/// Task state segment. In long mode it only holds stacks that processor switches to on interrupts.
+#[repr(C, packed)]
+pub struct TaskStateSegment {
+    reserved_0 : u32,
+    // stacks loaded on privilege level change
+    privilege_stack_table : [u64; 3],
+    reserved_1 : u64,
+    // stacks loaded by interrupt entries with non zero stack index
+    interrupt_stack_table : [u64; 7],
+    reserved_2 : u64,
+    reserved_3 : u16,
+    io_map_base : u16
+}
+
+impl TaskStateSegment {
+
+    /// Creates segment without stacks
+    pub const fn new() -> Self {
+        TaskStateSegment {
+            reserved_0 : 0,
+            privilege_stack_table : [0; 3],
+            reserved_1 : 0,
+            interrupt_stack_table : [0; 7],
+            reserved_2 : 0,
+            reserved_3 : 0,
+            // no io permission map, base points past the end of the segment
+            io_map_base : 104
+        }
+    }
+
+    /// Sets stack that is loaded by interrupt entries with provided stack index
+    /// # Arguments
+    /// * `stack_index` - stack index of interrupt entry, from 1 to 7
+    /// * `stack_top` - top of the stack, must be 16 bytes aligned
+    pub fn set_interrupt_stack(&mut self, stack_index : usize, stack_top : u64) {
+        assert!(stack_index >= 1 && stack_index <= 7, "Interrupt stack index {} is out of range", stack_index);
+
+        // fields of packed struct are copied, references to them may be unaligned
+        let mut stacks = self.interrupt_stack_table;
+        stacks[stack_index - 1] = stack_top;
+
+        self.interrupt_stack_table = stacks;
+    }
+}
+
+/// Global descriptor table that replaces the one set up by boot code, it also describes task state segment
+pub struct GlobalDescriptorTable {
+    entries : [u64; MAX_GDT_ENTRIES],
+    length : usize
+}
+
+impl GlobalDescriptorTable {
+
+    /// Creates table with single zero entry
+    pub const fn new() -> Self {
+        GlobalDescriptorTable {
+            entries : [0; MAX_GDT_ENTRIES],
+            length : 1
+        }
+    }
+
+    /// Adds 64 bit kernel code segment
+    /// # Returns
+    /// selector of the segment
+    pub fn add_kernel_code_segment(&mut self) -> u16 {
+        self.push(KERNEL_CODE_SEGMENT)
+    }
+
+    /// Adds descriptor of task state segment
+    /// # Returns
+    /// selector of the segment, see `load_task_register`
+    pub fn add_task_state_segment(&mut self, segment : &'static TaskStateSegment) -> u16 {
+        let base = segment as *const _ as u64;
+        let limit = (mem::size_of::<TaskStateSegment>() - 1) as u64;
+
+        let low = (limit & 0xffff) |
+            ((base & 0xff_ffff) << 16) |
+            TSS_AVAILABLE_TYPE |
+            SEGMENT_PRESENT |
+            (((limit >> 16) & 0xf) << 48) |
+            (((base >> 24) & 0xff) << 56);
+
+        let selector = self.push(low);
+        self.push(base >> 32);
+
+        selector
+    }
+
+    /// Loads table into global descriptor table register (GDTR)
+    /// # Safety
+    /// Code segment selector that is currently loaded must describe the same segment in the new table
+    pub unsafe fn load(&'static self) {
+        let pointer = DescriptorTablePointer {
+            limit : (self.length * mem::size_of::<u64>() - 1) as u16,
+            base : self.entries.as_ptr() as u64
+        };
+
+        asm!("lgdt ($0)" :: "r" (&pointer) : "memory");
+    }
+
+    fn push(&mut self, entry : u64) -> u16 {
+        assert!(self.length < MAX_GDT_ENTRIES, "Global descriptor table is full");
+
+        let index = self.length;
+
+        self.entries[index] = entry;
+        self.length += 1;
+
+        (index * mem::size_of::<u64>()) as u16
+    }
+}
+
+/// Loads task state segment selector into task register (TR)
+/// # Safety
+/// Selector must point to task state segment descriptor of loaded global descriptor table
+pub unsafe fn load_task_register(selector : u16) {
+    asm!("ltr $0" :: "r" (selector) : "memory" : "volatile");
+}
+
+#[repr(C, packed)]
+struct DescriptorTablePointer {
+    limit : u16,
+    base : u64
+}
@@ -4,6 +4,9 @@ pub type InterruptHandler                               = extern "x86-interrupt"
 /// Interrupt handler prototype that also contains error code.
 pub type InterruptHandlerWithErrorCode  = extern "x86-interrupt" fn (&mut InterruptStackFrameValue, u64);
 
+/// Naked interrupt entry prototype, entry saves registers itself and returns with `iretq`.
+pub type NakedInterruptHandler                     = unsafe extern "C" fn ();
+
 /// Interrupt meta info that is placed on stack by processor.
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -18,4 +21,52 @@ pub struct InterruptStackFrameValue {
     pub stack_pointer: u64,
     /// The stack segment descriptor at the time of the interrupt (often zero in 64-bit mode).
     pub stack_segment: u64,
-}
\ No newline at end of file
+}
+
+/// General purpose registers in the order they are placed on stack by naked interrupt entry,
+/// `rax` is pushed first and `r15` is pushed last.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct GeneralPurposeRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// Full state of interrupted code on stack of naked interrupt entry. Registers are restored from it
+/// on interrupt return, so changed values resume different code.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct InterruptedContext {
+    /// Registers pushed by interrupt entry
+    pub registers: GeneralPurposeRegisters,
+    /// Meta info pushed by processor
+    pub frame: InterruptStackFrameValue,
+}
+bitflags! {
+    /// Page fault error code that is placed on stack by processor.
+    pub struct PageFaultErrorCode : u64 {
+        /// Fault was caused by protection violation, otherwise by non present page
+        const PROTECTION_VIOLATION = 1 << 0;
+        /// Fault was caused by write access, otherwise by read
+        const CAUSED_BY_WRITE =      1 << 1;
+        /// Fault happened in user mode
+        const USER_MODE =            1 << 2;
+        /// Page table entry has reserved bit set
+        const MALFORMED_TABLE =      1 << 3;
+        /// Fault was caused by instruction fetch
+        const INSTRUCTION_FETCH =    1 << 4;
+    }
+}
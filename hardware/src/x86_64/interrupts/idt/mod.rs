@@ -1,4 +1,4 @@
-use ::x86_64::interrupts::handler::{InterruptHandler, InterruptHandlerWithErrorCode};
+use ::x86_64::interrupts::handler::{InterruptHandler, InterruptHandlerWithErrorCode, NakedInterruptHandler};
 use ::x86_64::interrupts::pic::PIC_1_OFFSET;
 use core::marker::PhantomData;
 use core::ops::{Index, IndexMut};
@@ -66,6 +66,26 @@ impl<HandlerFunc> InterruptTableEntry<HandlerFunc> {
         result
     }
 
+    /// Creates entry for naked interrupt entry, e.g. entry that saves every register of interrupted code
+    pub fn create_present_naked_entry(handler : NakedInterruptHandler) -> Self {
+        let mut result = InterruptTableEntry::<HandlerFunc>::new(handler as u64);
+        result.options.set_present();
+
+        result
+    }
+
+    /// Makes processor switch to stack of task state segment before calling the handler,
+    /// e.g. page fault handler must not use stack that overflowed into guard page.
+    /// # Arguments
+    /// `stack_index` - index of interrupt stack table entry, from 1 to 7
+    pub fn with_stack_index(mut self, stack_index : u16) -> Self {
+        assert!(stack_index >= 1 && stack_index <= 7, "Interrupt stack index {} is out of range", stack_index);
+
+        self.options.set_stack_index(stack_index);
+
+        self
+    }
+
     /// Creates empty table entry.
     /// This entry is not visible to controller and doesnt point to valid handler function, it is used only for initial table initialization.
     const fn empty() -> Self {
@@ -188,6 +208,18 @@ impl InterruptTable {
         self[idx] = entry
     }
 
+    /// Creates entry for naked interrupt entry denoted by idx.
+    /// # Arguments
+    /// `idx` - handler index
+    /// `handler` - naked interrupt entry, must save registers it uses and return with `iretq`
+    /// # Panic
+    ///  Panics if `idx` is out of range or points to reserved entry.
+    pub fn set_naked_interrupt_handler(&mut self, idx : usize, handler : NakedInterruptHandler) {
+        let entry = InterruptTableEntry::create_present_naked_entry(handler);
+
+        self[idx] = entry
+    }
+
     /// Creates a pointer for this table. Used only for `load_table` function.
     pub(crate) fn pointer(&self) -> InterruptTablePointer {
         use core::mem;
@@ -266,6 +298,11 @@ impl InterruptOptions {
         self.value = flags.bits();
     }
 
+    /// Sets index of interrupt stack table entry (bits 0 - 2), 0 means current stack is used
+    pub fn set_stack_index(&mut self, stack_index : u16) {
+        self.value = (self.value & !0b111) | (stack_index & 0b111);
+    }
+
     /// Sets this entry as hidden. No interrupts will get handled for that handler.
     pub fn set_unused(&mut self) {
         let mut flags = self.flags();
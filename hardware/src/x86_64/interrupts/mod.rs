@@ -3,6 +3,7 @@ pub mod handler;
 pub mod pic;
 
 use ::x86_64::interrupts::idt::InterruptTable;
+use ::x86_64::registers;
 
 /// Tells the processor to stop handling interrupts
 #[inline(always)]
@@ -20,6 +21,49 @@ pub fn enable_interrupts() {
     }
 }
 
+/// Determines if processor handles interrupts, e.g. interrupts are disabled inside interrupt handler
+#[inline(always)]
+pub fn are_enabled() -> bool {
+    // interrupt flag, bit 9 of RFLAGS
+    registers::rflags() & (1 << 9) != 0
+}
+
+/// Executes action with disabled interrupts, previous interrupt flag is restored afterwards,
+/// so sections can be nested and used inside interrupt handlers.
+/// # Arguments
+/// * `action` - code that must not be interrupted, e.g. modification of data used by interrupt handler
+#[inline(always)]
+pub fn without_interrupts<F, R>(action : F) -> R where F : FnOnce() -> R {
+    let enabled = are_enabled();
+
+    disable_interrupts();
+
+    let result = action();
+
+    if enabled {
+        enable_interrupts();
+    }
+
+    result
+}
+
+/// Stops processor until the next interrupt
+#[inline(always)]
+pub fn halt() {
+    unsafe {
+        asm!("hlt" :::: "volatile");
+    }
+}
+
+/// Enables interrupt handling and stops processor until the next interrupt. Interrupt that is pending
+/// after `sti` is handled only once processor halts, so it can't be missed between enabling and halting
+#[inline(always)]
+pub fn enable_and_halt() {
+    unsafe {
+        asm!("sti; hlt" :::: "volatile");
+    }
+}
+
 /// Loads interrupt table address into interrupt descriptor table address register (IDTR).
 /// This should be done before calling `enable_interrupts`, otherwise no interrupts will get handled and processor will restart.
 #[inline(always)]
@@ -1,3 +1,5 @@
 pub mod tlb;
 pub mod registers;
-pub mod interrupts;
\ No newline at end of file
+pub mod interrupts;
+pub mod fpu;
+pub mod gdt;
\ No newline at end of file
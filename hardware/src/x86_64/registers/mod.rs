@@ -26,24 +26,160 @@ pub unsafe fn cr3_write(val: u64) {
     asm!("mov $0, %cr3" :: "r" (val) : "memory");
 }
 
+/// Returns flags register value (RFLAGS)
+#[inline(always)]
+pub fn rflags() -> u64 {
+    let mut result : u64 = 0;
+
+    unsafe { asm!("pushfq; popq $0" : "=r" (result) :: "memory") }
+
+    result
+}
+
 #[inline(always)]
 pub unsafe fn rflags_write(val : u64) { asm!("pushq $0; popfq" :: "r"(val) : "memory" "flags") }
 
-/// Updates stack pointer register (ESP) with provided value
+/// Updates stack pointer register (RSP) with provided value
 /// # Arguments
 /// * `val` - new value of stack pointer
 /// # Safety
 /// You should definitely know what you are doing if you are changing stack pointer.
 /// Incorrect stack value can lead to silent overwrites of other memory areas or unexpected page fault.
 #[inline(always)]
-pub unsafe fn sp_write(val : u32) { asm!("mov $0, %esp" :: "r" (val) : "memory") }
+pub unsafe fn sp_write(val : u64) { asm!("mov $0, %rsp" :: "r" (val) : "memory") }
+
+/// Returns stack pointer register value (RSP)
+#[inline(always)]
+pub fn sp_read() -> u64 {
+    let mut result : u64 = 0;
+
+    unsafe { asm!("mov %rsp, $0" : "=r" (result)) }
 
-/// Returns stack pointer register value (ESP)
+    result
+}
+
+/// Returns frame pointer register value (RBP).
+/// Value points to the saved frame pointer of the caller only if code is compiled with frame pointers
 #[inline(always)]
-pub fn sp_read() -> u32 {
-    let mut result : u32 = 0;
+pub fn rbp_read() -> u64 {
+    let mut result : u64 = 0;
 
-    unsafe { asm!("mov %esp, $0" : "=r" (result)) }
+    unsafe { asm!("mov %rbp, $0" : "=r" (result)) }
 
     result
-}
\ No newline at end of file
+}
+
+/// Extended feature enable register (EFER) address
+pub const IA32_EFER : u32 = 0xC0000080;
+
+bitflags! {
+    /// Flags of the extended feature enable register (EFER)
+    pub struct EferFlags : u64 {
+        const SYSTEM_CALL_EXTENSIONS = 1 << 0;
+        const LONG_MODE_ENABLE =       1 << 8;
+        const LONG_MODE_ACTIVE =       1 << 10;
+        const NO_EXECUTE_ENABLE =      1 << 11;
+    }
+}
+
+bitflags! {
+    /// Flags of the control register CR0
+    pub struct Cr0Flags : u64 {
+        const PROTECTED_MODE_ENABLE = 1 << 0;
+        const MONITOR_COPROCESSOR =   1 << 1;
+        const EMULATE_COPROCESSOR =   1 << 2;
+        const TASK_SWITCHED =         1 << 3;
+        const NUMERIC_ERROR =         1 << 5;
+        const WRITE_PROTECT =         1 << 16;
+        const ALIGNMENT_MASK =        1 << 18;
+        const NOT_WRITE_THROUGH =     1 << 29;
+        const CACHE_DISABLE =         1 << 30;
+        const PAGING =                1 << 31;
+    }
+}
+
+/// Reads model specific register (MSR)
+/// # Arguments
+/// * `msr` - address of the register
+/// # Safety
+/// Reading non existing register produces general protection fault
+#[inline(always)]
+pub unsafe fn rdmsr(msr : u32) -> u64 {
+    let low : u32;
+    let high : u32;
+
+    asm!("rdmsr" : "={eax}" (low), "={edx}" (high) : "{ecx}" (msr) : "memory" : "volatile");
+
+    ((high as u64) << 32) | (low as u64)
+}
+
+/// Writes model specific register (MSR)
+/// # Arguments
+/// * `msr` - address of the register
+/// * `value` - new value of the register
+/// # Safety
+/// Writing non existing register or invalid value produces general protection fault
+#[inline(always)]
+pub unsafe fn wrmsr(msr : u32, value : u64) {
+    let low = value as u32;
+    let high = (value >> 32) as u32;
+
+    asm!("wrmsr" :: "{ecx}" (msr), "{eax}" (low), "{edx}" (high) : "memory" : "volatile");
+}
+
+/// Returns extended feature enable register flags (EFER)
+pub fn efer() -> EferFlags {
+    EferFlags::from_bits_truncate(unsafe { rdmsr(IA32_EFER) })
+}
+
+/// Updates extended feature enable register (EFER)
+/// # Safety
+/// Clearing long mode flags or no execute flag while NO_EXECUTE page table entries are present
+/// breaks the system.
+pub unsafe fn efer_write(flags : EferFlags) {
+    // preserve reserved bits
+    let reserved = rdmsr(IA32_EFER) & !EferFlags::all().bits();
+
+    wrmsr(IA32_EFER, reserved | flags.bits());
+}
+
+/// Returns control register CR0 flags
+#[inline(always)]
+pub fn cr0() -> Cr0Flags {
+    let ret: u64;
+    unsafe { asm!("mov %cr0, $0" : "=r" (ret)) };
+    Cr0Flags::from_bits_truncate(ret)
+}
+
+/// Updates control register CR0
+/// # Safety
+/// Clearing protected mode or paging flags breaks the system.
+#[inline(always)]
+pub unsafe fn cr0_write(flags : Cr0Flags) {
+    let mut value: u64;
+    asm!("mov %cr0, $0" : "=r" (value));
+
+    // preserve reserved bits
+    value = (value & !Cr0Flags::all().bits()) | flags.bits();
+
+    asm!("mov $0, %cr0" :: "r" (value) : "memory");
+}
+
+/// Returns page fault linear address register value (CR2)
+#[inline(always)]
+pub fn cr2() -> u64 {
+    let ret: u64;
+    unsafe { asm!("mov %cr2, $0" : "=r" (ret)) };
+    ret
+}
+
+/// Enables NO_EXECUTE page table entry flag support (EFER.NXE).
+/// Without it NO_EXECUTE flag is reserved and page table entries with it produce page fault.
+pub unsafe fn enable_nxe_bit() {
+    efer_write(efer() | NO_EXECUTE_ENABLE);
+}
+
+/// Enables write protection (CR0.WP), after that kernel code can't write to read only pages.
+pub unsafe fn enable_write_protect_bit() {
+    cr0_write(cr0() | WRITE_PROTECT);
+}
@@ -0,0 +1,399 @@
+use core::fmt;
+use core::ops;
+use frame::{PhysFrame, PhysFrameRange, FRAME_SIZE};
+use paging::page::{Page, PageRange};
+
+/// Bits 48-63 of canonical virtual address must be copies of bit 47, x86-64 spec
+const VIRTUAL_ADDRESS_BITS : usize = 48;
+
+/// Physical addresses are limited to 52 bits, x86-64 spec
+const PHYSICAL_ADDRESS_MASK : usize = 0x000fffff_ffffffff;
+
+/// Virtual address of the kernel image (P4 entry 510). Kernel is linked at KERNEL_VIRTUAL_BASE + its physical address,
+/// must be kept in sync with `testos/src/linker.ld` and `testos/src/boot.asm`.
+pub const KERNEL_VIRTUAL_BASE : usize = 0xFFFF_FF00_0000_0000;
+
+/// Virtual address of the window that maps all physical memory (starts at P4 entry 256),
+/// physical address X is accessible at PHYSICAL_MEMORY_OFFSET + X.
+pub const PHYSICAL_MEMORY_OFFSET : usize = 0xFFFF_8000_0000_0000;
+
+/// Returns virtual address of the physical memory window that maps provided physical address
+/// # Arguments
+/// * `address` - physical address
+pub fn physical_to_virtual(address : PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET + address.as_usize())
+}
+
+/// Translates address of the kernel image or address of the physical memory window to
+/// physical address without page table walk. Other addresses are treated as identity mapped
+/// and are returned as is.
+/// # Arguments
+/// * `address` - virtual address
+pub fn to_physical(address : usize) -> usize {
+    if address >= KERNEL_VIRTUAL_BASE {
+        address - KERNEL_VIRTUAL_BASE
+    }
+    else if address >= PHYSICAL_MEMORY_OFFSET {
+        address - PHYSICAL_MEMORY_OFFSET
+    }
+    else {
+        address
+    }
+}
+
+/// Aligns address upwards to `align` (returns first aligned address that is bigger or equal to `address`).
+/// # Arguments
+/// * `address` - address to align
+/// * `align` - alignment, must be a power of 2
+pub fn align_up(address : usize, align : usize) -> usize {
+    assert!(align.is_power_of_two(), "Alignment {} is not a power of 2", align);
+
+    let mask = align - 1;
+
+    if address & mask == 0 {
+        address
+    }
+    else {
+        (address | mask) + 1
+    }
+}
+
+/// Aligns address downwards to `align` (returns first aligned address that is lower or equal to `address`).
+/// # Arguments
+/// * `address` - address to align
+/// * `align` - alignment, must be a power of 2
+pub fn align_down(address : usize, align : usize) -> usize {
+    assert!(align.is_power_of_two(), "Alignment {} is not a power of 2", align);
+
+    address & !(align - 1)
+}
+
+/// Virtual memory address. Always canonical, e.g. bits 48-63 are copies of bit 47.
+#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Copy, Debug)]
+pub struct VirtAddr(usize);
+
+impl VirtAddr {
+
+    /// Creates virtual address.
+    /// # Arguments
+    /// * `address` - raw address value
+    /// # Panic
+    /// Panics if address is not canonical
+    pub fn new(address : usize) -> VirtAddr {
+        assert!(VirtAddr::is_canonical(address), "Virtual address {:#x} is not canonical", address);
+
+        VirtAddr(address)
+    }
+
+    /// Creates virtual address, returns None if address is not canonical.
+    /// # Arguments
+    /// * `address` - raw address value
+    pub fn try_new(address : usize) -> Option<VirtAddr> {
+        if VirtAddr::is_canonical(address) {
+            Some(VirtAddr(address))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Creates virtual address by sign extending bit 47 to bits 48-63.
+    /// # Arguments
+    /// * `address` - raw address value, bits 48-63 are ignored
+    pub fn new_truncate(address : usize) -> VirtAddr {
+        let shift = 64 - VIRTUAL_ADDRESS_BITS;
+
+        VirtAddr((((address << shift) as isize) >> shift) as usize)
+    }
+
+    pub const fn zero() -> VirtAddr {
+        VirtAddr(0)
+    }
+
+    /// Determines if address is canonical, e.g. bits 48-63 are copies of bit 47
+    /// # Arguments
+    /// * `address` - raw address value
+    pub fn is_canonical(address : usize) -> bool {
+        let upper_bits = address >> (VIRTUAL_ADDRESS_BITS - 1);
+
+        upper_bits == 0 || upper_bits == (usize::max_value() >> (VIRTUAL_ADDRESS_BITS - 1))
+    }
+
+    pub fn as_usize(&self) -> usize {
+        self.0
+    }
+
+    pub fn as_ptr<T>(&self) -> *const T {
+        self.0 as *const T
+    }
+
+    pub fn as_mut_ptr<T>(&self) -> *mut T {
+        self.0 as *mut T
+    }
+
+    pub fn align_up(&self, align : usize) -> VirtAddr {
+        VirtAddr::new_truncate(align_up(self.0, align))
+    }
+
+    pub fn align_down(&self, align : usize) -> VirtAddr {
+        VirtAddr(align_down(self.0, align))
+    }
+
+    pub fn is_aligned(&self, align : usize) -> bool {
+        align_down(self.0, align) == self.0
+    }
+
+    /// Offset of this address inside its 4 KiB page
+    pub fn page_offset(&self) -> usize {
+        self.0 % FRAME_SIZE
+    }
+}
+
+impl ops::Add<usize> for VirtAddr {
+    type Output = VirtAddr;
+
+    fn add(self, rhs : usize) -> VirtAddr {
+        VirtAddr::new(self.0 + rhs)
+    }
+}
+
+impl ops::AddAssign<usize> for VirtAddr {
+    fn add_assign(&mut self, rhs : usize) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Sub<usize> for VirtAddr {
+    type Output = VirtAddr;
+
+    fn sub(self, rhs : usize) -> VirtAddr {
+        VirtAddr::new(self.0 - rhs)
+    }
+}
+
+impl ops::Sub<VirtAddr> for VirtAddr {
+    type Output = usize;
+
+    fn sub(self, rhs : VirtAddr) -> usize {
+        self.0 - rhs.0
+    }
+}
+
+impl fmt::Display for VirtAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Physical memory address. Always fits into 52 bits.
+#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Copy, Debug)]
+pub struct PhysAddr(usize);
+
+impl PhysAddr {
+
+    /// Creates physical address.
+    /// # Arguments
+    /// * `address` - raw address value
+    /// # Panic
+    /// Panics if address cannot be packed in 52 bits
+    pub fn new(address : usize) -> PhysAddr {
+        assert!(address & !PHYSICAL_ADDRESS_MASK == 0, "Physical address {:#x} cannot be packed in 52 bits", address);
+
+        PhysAddr(address)
+    }
+
+    /// Creates physical address, returns None if address cannot be packed in 52 bits.
+    /// # Arguments
+    /// * `address` - raw address value
+    pub fn try_new(address : usize) -> Option<PhysAddr> {
+        if address & !PHYSICAL_ADDRESS_MASK == 0 {
+            Some(PhysAddr(address))
+        }
+        else {
+            None
+        }
+    }
+
+    pub const fn zero() -> PhysAddr {
+        PhysAddr(0)
+    }
+
+    /// Returns highest physical address that can be packed in 52 bits
+    pub const fn max() -> PhysAddr {
+        PhysAddr(PHYSICAL_ADDRESS_MASK)
+    }
+
+    pub fn as_usize(&self) -> usize {
+        self.0
+    }
+
+    pub fn align_up(&self, align : usize) -> PhysAddr {
+        PhysAddr::new(align_up(self.0, align))
+    }
+
+    pub fn align_down(&self, align : usize) -> PhysAddr {
+        PhysAddr(align_down(self.0, align))
+    }
+
+    pub fn is_aligned(&self, align : usize) -> bool {
+        align_down(self.0, align) == self.0
+    }
+}
+
+impl ops::Add<usize> for PhysAddr {
+    type Output = PhysAddr;
+
+    fn add(self, rhs : usize) -> PhysAddr {
+        PhysAddr::new(self.0 + rhs)
+    }
+}
+
+impl ops::AddAssign<usize> for PhysAddr {
+    fn add_assign(&mut self, rhs : usize) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Sub<usize> for PhysAddr {
+    type Output = PhysAddr;
+
+    fn sub(self, rhs : usize) -> PhysAddr {
+        PhysAddr::new(self.0 - rhs)
+    }
+}
+
+impl ops::Sub<PhysAddr> for PhysAddr {
+    type Output = usize;
+
+    fn sub(self, rhs : PhysAddr) -> usize {
+        self.0 - rhs.0
+    }
+}
+
+impl fmt::Display for PhysAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Inclusive range of virtual addresses
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct VirtAddrRange {
+    start_address : VirtAddr,
+    end_address : VirtAddr
+}
+
+impl VirtAddrRange {
+
+    /// Creates range
+    /// # Arguments
+    /// * `start_address` - first address of the range
+    /// * `end_address` - last address of the range (inclusive)
+    pub fn new(start_address : VirtAddr, end_address : VirtAddr) -> VirtAddrRange {
+        assert!(start_address <= end_address, "Range end {} is below range start {}", end_address, start_address);
+
+        VirtAddrRange {
+            start_address,
+            end_address
+        }
+    }
+
+    /// Creates range of `size` bytes, size must not be 0
+    pub fn with_size(start_address : VirtAddr, size : usize) -> VirtAddrRange {
+        VirtAddrRange::new(start_address, start_address + (size - 1))
+    }
+
+    pub fn start_address(&self) -> VirtAddr {
+        self.start_address
+    }
+
+    pub fn end_address(&self) -> VirtAddr {
+        self.end_address
+    }
+
+    pub fn size(&self) -> usize {
+        self.end_address - self.start_address + 1
+    }
+
+    /// Determines if address belongs to the range
+    pub fn contains(&self, address : VirtAddr) -> bool {
+        address >= self.start_address && address <= self.end_address
+    }
+
+    /// Determines if ranges have at least one common address
+    pub fn overlaps(&self, other : &VirtAddrRange) -> bool {
+        self.start_address <= other.end_address && other.start_address <= self.end_address
+    }
+
+    /// Returns iterator over pages that contain addresses of the range
+    pub fn pages(&self) -> PageRange {
+        Page::range_inclusive(Page::containing_address(self.start_address), Page::containing_address(self.end_address))
+    }
+}
+
+impl fmt::Display for VirtAddrRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {}", self.start_address, self.end_address)
+    }
+}
+
+/// Inclusive range of physical addresses
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct PhysAddrRange {
+    start_address : PhysAddr,
+    end_address : PhysAddr
+}
+
+impl PhysAddrRange {
+
+    /// Creates range
+    /// # Arguments
+    /// * `start_address` - first address of the range
+    /// * `end_address` - last address of the range (inclusive)
+    pub fn new(start_address : PhysAddr, end_address : PhysAddr) -> PhysAddrRange {
+        assert!(start_address <= end_address, "Range end {} is below range start {}", end_address, start_address);
+
+        PhysAddrRange {
+            start_address,
+            end_address
+        }
+    }
+
+    /// Creates range of `size` bytes, size must not be 0
+    pub fn with_size(start_address : PhysAddr, size : usize) -> PhysAddrRange {
+        PhysAddrRange::new(start_address, start_address + (size - 1))
+    }
+
+    pub fn start_address(&self) -> PhysAddr {
+        self.start_address
+    }
+
+    pub fn end_address(&self) -> PhysAddr {
+        self.end_address
+    }
+
+    pub fn size(&self) -> usize {
+        self.end_address - self.start_address + 1
+    }
+
+    /// Determines if address belongs to the range
+    pub fn contains(&self, address : PhysAddr) -> bool {
+        address >= self.start_address && address <= self.end_address
+    }
+
+    /// Determines if ranges have at least one common address
+    pub fn overlaps(&self, other : &PhysAddrRange) -> bool {
+        self.start_address <= other.end_address && other.start_address <= self.end_address
+    }
+
+    /// Returns iterator over frames that contain addresses of the range
+    pub fn frames(&self) -> PhysFrameRange {
+        PhysFrame::range_inclusive(PhysFrame::containing_address(self.start_address), PhysFrame::containing_address(self.end_address))
+    }
+}
+
+impl fmt::Display for PhysAddrRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {}", self.start_address, self.end_address)
+    }
+}
@@ -1,18 +1,17 @@
 use stdx_memory::{MemoryAllocator, ConstantSizeMemoryAllocator, MemoryAllocatorMeta};
 use stdx_memory::collections::array::Array;
 use stdx_memory::collections::double_linked_list::{BuddyMap, UsizeLinkedMap};
-use stdx_memory::collections::frame_bitmap::FrameBitMap;
 use allocator::bump;
-use frame::{Frame, FRAME_SIZE};
-use frame::frame_allocator::FrameAllocator;
+use frame::{PhysFrame, FrameSource, FRAME_SIZE};
+use frame::regions::{MemoryRegion, MemoryRegions};
+use address;
+use address::PhysAddr;
 use stdx::iterator::IteratorExt;
 use allocator::free_list;
 use allocator;
 use stdx::math;
 use stdx::Sequence;
-use paging;
-use paging::page_table;
-use multiboot::multiboot_header::MultibootHeader;
+use core::cmp;
 
 macro_rules! block_sizes {
     ($total_buddy_levels:expr, $starting_block_size:expr) => {{
@@ -44,27 +43,35 @@ macro_rules! block_count_indexed {
     }}
 }
 
+/// Buddy allocator of physical memory. Memory is indexed by physical address starting from 0,
+/// so every block is aligned by its size, memory outside of provided regions is never handed out.
 pub struct BuddyAllocator {
-    allocation_sizes     : Array<usize>,    
-    buddy_free_lists     : Array<BuddyFreeList>,    
+    allocation_sizes     : Array<u8>,
+    // number of additional references to every frame, 0 means frame has single owner
+    shared_references    : Array<u16>,
+    buddy_free_lists     : Array<BuddyFreeList>,
     array_allocator      : bump::BumpAllocator,
     free_list_allocator  : free_list::FreeListAllocator,
-    start_address          : usize,
-    end_address            : usize,
-    memory_start_address: usize,
-    page_tables_allocator :  free_list::FreeListAllocator
+    // allocator returns physical address + address_offset, e.g. address of physical memory window
+    address_offset       : usize,
+    start_address        : usize,
+    end_address          : usize,
+    // frees memory of allocator users when frames run out, see `set_reclaim_handler`
+    reclaim_handler      : Option<fn() -> usize>
 }
 
 impl BuddyAllocator {
 
     pub fn debug_allocation_size(&self) -> usize {
-        self.allocation_sizes[Frame::number_for_address(0)]
+        self.allocation_sizes[0] as usize
     }
 
-    pub fn total_aux_data_structures_size(start_address1 : usize, end_address1 : usize) -> usize {
-        let (start_address, end_address)                           = allocator::align_addresses(start_address1, end_address1);
-        let total_memory                                                        = allocator::total_memory(start_address, end_address);
-        let (total_frames_count, total_buddy_levels)    = BuddyAllocator::frames_and_buddy_levels(start_address, end_address);
+    /// Returns size of memory required by allocator aux data structures
+    /// # Arguments
+    /// * `regions` - physical memory that will be managed by allocator
+    pub fn aux_data_structures_size_for(regions : &MemoryRegions) -> usize {
+        let total_memory                                                        = BuddyAllocator::managed_memory_size(regions);
+        let (total_frames_count, total_buddy_levels)    = BuddyAllocator::frames_and_buddy_levels(total_memory);
         let (array_sizes, buddy_free_lists_size)                = BuddyAllocator::aux_data_structures_size(total_frames_count, total_buddy_levels, total_memory);
 
         let this_aux_structures_size = array_sizes + buddy_free_lists_size;
@@ -72,25 +79,24 @@ impl BuddyAllocator {
         this_aux_structures_size + free_list::FreeListAllocator::aux_data_structures_size_for(buddy_free_lists_size, BuddyMap::cell_size())
     }
 
-    fn frames_and_buddy_levels(start_address : usize, end_address : usize) -> (usize, usize) {
-        assert!(end_address > start_address, "Cannot create allocator when end address <= start address");
-
-        let total_memory = allocator::total_memory(start_address, end_address);
-
-        let total_frames_count = Frame::from_address(total_memory).number();
-
-        assert!(end_address > start_address, "Cannot create allocator when total memory size < FRAME_SIZE (4096)");
+    /// Returns number of frames indexed by allocator, including frames of memory holes
+    /// # Arguments
+    /// * `regions` - physical memory that will be managed by allocator
+    pub fn frames_count_for(regions : &MemoryRegions) -> usize {
+        BuddyAllocator::managed_memory_size(regions) / FRAME_SIZE
+    }
 
-        let total_buddy_levels = BuddyAllocator::total_buddy_levels(total_memory);
+    // memory is indexed from physical address 0 up to the end of the last region
+    fn managed_memory_size(regions : &MemoryRegions) -> usize {
+        let span = regions.span().expect("Cannot create allocator without memory regions");
 
-        (
-            total_frames_count,
-            total_buddy_levels
-        )
+        span.end_address().as_usize() + 1
     }
 
-    fn frames_and_buddy_levels1(total_memory : usize) -> (usize, usize) {
-        let total_frames_count = Frame::from_address(total_memory).number();
+    fn frames_and_buddy_levels(total_memory : usize) -> (usize, usize) {
+        assert!(total_memory >= FRAME_SIZE, "Cannot create allocator when total memory size < FRAME_SIZE (4096)");
+
+        let total_frames_count = total_memory / FRAME_SIZE;
         let total_buddy_levels = BuddyAllocator::total_buddy_levels(total_memory);
 
         (
@@ -102,11 +108,12 @@ impl BuddyAllocator {
     fn aux_data_structures_size(total_frames_count : usize, total_buddy_levels : usize, total_memory : usize) -> (usize, usize) {
 
         // compute max memory size for inner allocators to work with
-        let sizes_array_size                                                   = Array::<usize>::mem_size_for(total_frames_count);
+        let sizes_array_size                                                   = Array::<u8>::mem_size_for(total_frames_count);
+        let shared_references_size                                       = Array::<u16>::mem_size_for(total_frames_count);
         let buddy_free_list_array_size                              = Array::<BuddyFreeList>::mem_size_for(total_buddy_levels);
-        let (buddy_array_size, buddy_free_lists_size) = BuddyAllocator::buddy_free_list_size(total_buddy_levels, total_memory);
+        let (buddy_array_size, buddy_free_lists_size) = BuddyAllocator::buddy_free_list_size(total_frames_count, total_buddy_levels, total_memory);
 
-        let array_sizes = sizes_array_size + buddy_array_size + buddy_free_list_array_size;
+        let array_sizes = sizes_array_size + shared_references_size + buddy_array_size + buddy_free_list_array_size;
 
         (array_sizes, buddy_free_lists_size)
     }
@@ -119,21 +126,39 @@ impl BuddyAllocator {
         }
     }
 
-    pub fn new(start_address : usize, total_memory : usize, end_address : usize) -> Self {
-        let (total_frames_count, total_buddy_levels) = BuddyAllocator::frames_and_buddy_levels1(total_memory);
+    /// Creates allocator of provided physical memory regions, memory between regions is never allocated.
+    /// # Arguments
+    /// * `regions` - physical memory to manage, must not contain aux data structures memory
+    /// * `aux_start_address` - start of zeroed memory of size `aux_data_structures_size_for(regions)`
+    /// * `address_offset` - offset added to physical addresses returned by allocator, e.g. `address::PHYSICAL_MEMORY_OFFSET`
+    pub fn from_regions(regions : &MemoryRegions, aux_start_address : usize, address_offset : usize) -> Self {
+        BuddyAllocator::from_managed_regions(regions, regions, aux_start_address, address_offset)
+    }
+
+    /// Creates allocator that indexes more memory than it sets free, memory that is reserved at the moment
+    /// can be returned to the allocator later by `deallocate_frame`, e.g. frames of multiboot information.
+    /// # Arguments
+    /// * `managed` - physical memory indexed by allocator, free memory included
+    /// * `free` - physical memory that is free right away, must not contain aux data structures memory
+    /// * `aux_start_address` - start of zeroed memory of size `aux_data_structures_size_for(managed)`
+    /// * `address_offset` - offset added to physical addresses returned by allocator, e.g. `address::PHYSICAL_MEMORY_OFFSET`
+    pub fn from_managed_regions(managed : &MemoryRegions, free : &MemoryRegions, aux_start_address : usize, address_offset : usize) -> Self {
+        let total_memory                                                        = BuddyAllocator::managed_memory_size(managed);
+        let (total_frames_count, total_buddy_levels)    = BuddyAllocator::frames_and_buddy_levels(total_memory);
 
         // compute max memory size for inner allocators to work with
         let (array_sizes, buddy_free_lists_size)                = BuddyAllocator::aux_data_structures_size(total_frames_count, total_buddy_levels, total_memory);
 
         // create inner allocators
-        let mut array_allocator     = bump::BumpAllocator::from_address(start_address, array_sizes);
+        let mut array_allocator     = bump::BumpAllocator::from_address(aux_start_address, array_sizes);
         let mut free_list_allocator = free_list::FreeListAllocator::from_size(
             array_allocator.end_address() + 1,
             buddy_free_lists_size,
             BuddyMap::cell_size());
 
         // create allocate/free data structures
-        let allocation_sizes                            = Array::<usize>::new(total_frames_count, &mut array_allocator);
+        let allocation_sizes                            = Array::<u8>::new(total_frames_count, &mut array_allocator);
+        let shared_references                          = Array::<u16>::new_fill_value(total_frames_count, 0, &mut array_allocator);
         let mut buddy_free_lists_array    = Array::<BuddyFreeList>::new(total_buddy_levels, &mut array_allocator);
 
         BuddyAllocator::populate_buddy_free_lists(
@@ -143,26 +168,44 @@ impl BuddyAllocator {
             total_buddy_levels,
             total_memory);
 
-        // set initial block that covers all memory as free
-        let idx = if total_buddy_levels > 0 { total_buddy_levels - 1} else { 0 };
-        buddy_free_lists_array[idx].set_free(0, &mut free_list_allocator);
-
-        // user space memory start
-        let memory_start_address = Frame::address_align_up(free_list_allocator.end_address() + 1);
-
-        // create page tables allocator
-        let page_tables_allocator = free_list::FreeListAllocator::from_size(free_list_allocator.end_address() + 1, total_memory, FRAME_SIZE);
-        let proper_start_address = page_tables_allocator.object_allocation_start_address();
-
-        BuddyAllocator {
+        let mut allocator = BuddyAllocator {
             allocation_sizes,
+            shared_references,
             buddy_free_lists            : buddy_free_lists_array,
-            end_address,
             array_allocator,
             free_list_allocator,
-            start_address : proper_start_address,
-            memory_start_address,
-            page_tables_allocator
+            address_offset,
+            start_address : address_offset,
+            end_address : address_offset + total_memory - 1,
+            reclaim_handler : None
+        };
+
+        // every block is in use from the start, only blocks inside regions are set free
+        for region in free.iter() {
+            allocator.set_region_free(region);
+        }
+
+        allocator
+    }
+
+    // sets free the biggest aligned blocks that fit inside the region
+    fn set_region_free(&mut self, region : MemoryRegion) {
+        let top_level       = self.buddy_free_lists.length() - 1;
+        let mut address     = region.start_address().as_usize();
+        let end_address     = region.end_address().as_usize() + 1;
+
+        while address < end_address {
+            let mut level = top_level;
+
+            while level > 0 && (address % BuddyAllocator::block_size_from_index(level) != 0 ||
+                                address + BuddyAllocator::block_size_from_index(level) > end_address) {
+                level -= 1;
+            }
+
+            let block_index = BuddyAllocator::address_to_index(address, level);
+            self.buddy_free_lists[level].set_free(block_index, &mut self.free_list_allocator);
+
+            address += BuddyAllocator::block_size_from_index(level);
         }
     }
 
@@ -189,11 +232,12 @@ impl BuddyAllocator {
         }
     }
 
-    fn buddy_free_list_size(total_buddy_levels_count : usize, total_memory : usize) -> (usize, usize) {
+    fn buddy_free_list_size(total_frames_count : usize, total_buddy_levels_count : usize, total_memory : usize) -> (usize, usize) {
         let total_block_count = total_block_count!(total_memory, total_buddy_levels_count, FRAME_SIZE);
 
         let array_size      = BuddyMap::mem_size_for_array(total_block_count) + BuddyFreeList::mem_size_for_array(total_block_count);
-        let free_list_size = BuddyMap::mem_size_for_linked_list(total_block_count);
+        // free blocks never overlap, so there can't be more free blocks than frames
+        let free_list_size = BuddyMap::mem_size_for_linked_list(total_frames_count + total_buddy_levels_count);
 
         (
             array_size,
@@ -238,6 +282,16 @@ impl BuddyAllocator {
         address / BuddyAllocator::block_size_from_index(buddy_list_index)
     }
 
+    fn allocation_size_rounded(size : usize) -> usize {
+        let allocation_size_rounded0 = (2 as usize).pow(math::log2_align_up(size) as u32);
+
+        if allocation_size_rounded0 < FRAME_SIZE {
+            FRAME_SIZE
+        } else {
+            allocation_size_rounded0
+        }
+    }
+
     fn split_down(&mut self, start_index : usize, allocation_size : usize) -> Option<(isize, usize)> {
         let mut i = start_index as isize;
         let mut current_level_size = BuddyAllocator::block_size_from_index(start_index);
@@ -293,7 +347,7 @@ impl BuddyAllocator {
 
             // if we encountered top block
             if buddy_list_index == buddy_lists_count - 1 && block_is_in_use {
-                buddy_free_list.set_free(0, &mut self.free_list_allocator);
+                buddy_free_list.set_free(block_index, &mut self.free_list_allocator);
                 break;
             }
             else if buddy_list_index > buddy_lists_count - 1 {
@@ -302,6 +356,14 @@ impl BuddyAllocator {
             
             let buddy_index       = BuddyFreeList::buddy_index(block_index);
 
+            // last block of the level with odd block count has no buddy
+            if buddy_index >= buddy_free_list.length() {
+                if block_is_in_use {
+                    buddy_free_list.set_free(block_index, &mut self.free_list_allocator);
+                }
+                break;
+            }
+
             let block_not_merging = !buddy_free_list.is_merging(block_index);
             let buddy_not_merging = !buddy_free_list.is_merging(buddy_index);
             
@@ -338,11 +400,181 @@ impl BuddyAllocator {
         };
     }
 
-    pub fn allocate_frame(&mut self) -> Option<usize> {
-        self.allocate(FRAME_SIZE)
+    /// Returns size of allocated block that is available from the pointer
+    /// # Arguments
+    /// * `pointer` - address returned by allocator, it is inside the block when address offset is less aligned
+    ///               than the requested alignment, see `allocate_aligned`
+    pub fn allocation_size(&self, pointer : usize) -> usize {
+        let frame_number = self.frame_containing(pointer).number();
+        let block_size   = BuddyAllocator::block_size_from_index(self.allocation_sizes[frame_number] as usize);
+
+        block_size - (pointer - self.address_offset) % block_size
+    }
+
+    /// Grows allocated block in place by taking its free right buddies, block keeps its address.
+    /// # Arguments
+    /// * `pointer` - address returned by allocator
+    /// * `new_size` - requested size of block
+    /// # Returns
+    /// true if block has at least `new_size` bytes, nothing is changed otherwise
+    pub fn grow_in_place(&mut self, pointer : usize, new_size : usize) -> bool {
+        let normalized_pointer = pointer - self.address_offset;
+        let frame_number       = self.frame_containing(pointer).number();
+        let level              = self.allocation_sizes[frame_number] as usize;
+        // pointer may be inside the block, see `allocate_aligned`
+        let block_offset       = normalized_pointer % BuddyAllocator::block_size_from_index(level);
+        let target_level       = BuddyAllocator::index_from_size(BuddyAllocator::allocation_size_rounded(new_size + block_offset));
+
+        if target_level <= level {
+            return true
+        }
+        else if target_level >= self.buddy_free_lists.length() {
+            return false
+        }
+
+        // block must be the left one on every level, and its right buddy must be free
+        let can_grow = (level .. target_level).all(|i| {
+            let block_index     = BuddyAllocator::address_to_index(normalized_pointer, i);
+            let buddy_free_list = &self.buddy_free_lists[i];
+
+            math::is_even(block_index)
+                && block_index + 1 < buddy_free_list.length()
+                && buddy_free_list.is_free(block_index + 1)
+                && !buddy_free_list.is_merging(block_index + 1)
+        });
+
+        if can_grow {
+            for i in level .. target_level {
+                let buddy_index = BuddyAllocator::address_to_index(normalized_pointer, i) + 1;
+
+                self.buddy_free_lists[i].set_in_use(buddy_index, &mut self.free_list_allocator);
+            }
+
+            // block is freed at its new level, as if it was allocated with the new size
+            self.allocation_sizes[frame_number] = target_level as u8;
+        }
+
+        can_grow
+    }
+
+    /// Returns number of buddy levels, level `i` has blocks of `FRAME_SIZE << i` bytes
+    pub fn levels_count(&self) -> usize {
+        self.buddy_free_lists.length()
+    }
+
+    /// Returns number of free blocks of buddy level
+    /// # Arguments
+    /// * `level` - buddy level, must be less than `levels_count()`
+    pub fn free_blocks_count(&self, level : usize) -> usize {
+        self.buddy_free_lists[level].free_blocks_count()
+    }
+
+    /// Sets function that returns cached memory back to allocator, e.g. `SlabAllocator::shrink` of the heap.
+    /// Frame source calls it when it runs out of frames and tries again before it reports that there is no memory.
+    /// # Arguments
+    /// * `handler` - returns number of freed frames
+    pub fn set_reclaim_handler(&mut self, handler : fn() -> usize) {
+        self.reclaim_handler = Some(handler);
+    }
+
+    fn reclaim(&mut self) -> usize {
+        self.reclaim_handler.map_or(0, |handler| handler())
+    }
+
+    /// Returns offset that is added to physical addresses returned by allocator
+    pub fn address_offset(&self) -> usize {
+        self.address_offset
+    }
+
+    /// Returns physical address of memory returned by allocator
+    /// # Arguments
+    /// * `pointer` - address inside allocated block, e.g. address in physical memory window
+    pub fn physical_address(&self, pointer : usize) -> PhysAddr {
+        PhysAddr::new(pointer - self.address_offset)
+    }
+
+    /// Returns frame that holds memory returned by allocator
+    /// # Arguments
+    /// * `pointer` - address inside allocated block, e.g. address in physical memory window
+    pub fn frame_containing(&self, pointer : usize) -> PhysFrame {
+        PhysFrame::containing_address(self.physical_address(pointer))
+    }
+
+    fn frame_index(&self, frame : PhysFrame) -> usize {
+        frame.number()
+    }
+}
+
+impl allocator::SharedFrameAllocator for BuddyAllocator {
+
+    fn share_frame(&mut self, frame : PhysFrame) {
+        allocator::without_preemption(|| {
+            let index = self.frame_index(frame);
+            let references = self.shared_references[index];
+
+            assert!(references < u16::max_value(), "Too many references to frame {}", frame);
+
+            self.shared_references.update(index, references + 1);
+        })
+    }
+
+    fn release_frame(&mut self, frame : PhysFrame) -> bool {
+        allocator::without_preemption(|| {
+            let index = self.frame_index(frame);
+            let references = self.shared_references[index];
+
+            if references == 0 {
+                self.deallocate_frame(frame);
+                true
+            }
+            else {
+                self.shared_references.update(index, references - 1);
+                false
+            }
+        })
+    }
+
+    fn frame_reference_count(&self, frame : PhysFrame) -> usize {
+        self.shared_references[self.frame_index(frame)] as usize + 1
     }
 }
 
+// frames are allocated by processes and by kernel thread, see `allocator::without_preemption`
+impl FrameSource for BuddyAllocator {
+
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate_contiguous(1, 1)
+    }
+
+    fn allocate_contiguous(&mut self, count : usize, align : usize) -> Option<PhysFrame> {
+        allocator::without_preemption(|| {
+            let address_offset = self.address_offset;
+
+            // physical address of the block is aligned by block size regardless of address offset
+            let size = cmp::max(count, align) * FRAME_SIZE;
+            let mut result = self.allocate(size);
+
+            // memory pressure: users of the allocator may hold cached frames
+            if result.is_none() && self.reclaim() > 0 {
+                result = self.allocate(size);
+            }
+
+            result.map(|address| PhysFrame::containing_address(PhysAddr::new(address - address_offset)))
+        })
+    }
+
+    fn deallocate_frame(&mut self, frame : PhysFrame) {
+        allocator::without_preemption(|| {
+            let address = frame.start_address().as_usize() + self.address_offset;
+
+            self.free(address)
+        })
+    }
+
+    fn free_frames_count(&self) -> usize {
+        (0 .. self.levels_count()).map(|level| self.free_blocks_count(level) << level).sum()
+    }
+}
 
 impl MemoryAllocatorMeta for BuddyAllocator {
     fn start_address(&self) -> usize {
@@ -355,8 +587,7 @@ impl MemoryAllocatorMeta for BuddyAllocator {
 
     fn aux_data_structures_size(&self) -> usize {
         self.array_allocator.full_size() +
-            self.free_list_allocator.full_size() +
-            self.page_tables_allocator.aux_data_structures_size()
+            self.free_list_allocator.full_size()
     }
 }
 
@@ -368,12 +599,7 @@ impl MemoryAllocator for BuddyAllocator {
             None
         }
         else {
-            let allocation_size_rounded0 = (2 as usize).pow(math::log2_align_up(size) as u32);
-            let allocation_size_rounded = if allocation_size_rounded0 < FRAME_SIZE { 
-                FRAME_SIZE
-            } else {
-                allocation_size_rounded0
-            };
+            let allocation_size_rounded = BuddyAllocator::allocation_size_rounded(size);
 
             if allocation_size_rounded > self.full_size() {
                 None
@@ -387,19 +613,12 @@ impl MemoryAllocator for BuddyAllocator {
                                 .and_then(|index| self.split_down(index, allocation_size_rounded));
                 
                 if let Some((new_buddy_index, result_address)) = result {
-                    let frame_number = Frame::number_for_address(result_address);
-
-                    self.allocation_sizes[frame_number] = new_buddy_index as usize;
+                    let frame_number = PhysFrame::containing_address(PhysAddr::new(result_address)).number();
 
-                    let result_address = result_address + self.memory_start_address;
+                    self.allocation_sizes[frame_number] = new_buddy_index as u8;
 
-                    // map page frames
-                    let p4_table = paging::p4_table();
-                    let frame_count = Frame::number_for_address(allocation_size_rounded);
-                    let proper_frame_count =  if frame_count == 0 { 1 } else { frame_count };
-                    p4_table.map_pages_1_to_1(result_address, proper_frame_count , page_table::PRESENT | page_table::WRITABLE, &mut self.page_tables_allocator);
-
-                    let debug = self.allocation_sizes[Frame::number_for_address(0)];
+                    // memory is already mapped by physical memory window
+                    let result_address = result_address + self.address_offset;
 
                     Some(result_address)
                 }
@@ -410,22 +629,39 @@ impl MemoryAllocator for BuddyAllocator {
         }
     }
 
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        if self.address_offset % align == 0 {
+            // blocks are aligned by their size, bigger block is picked for bigger alignment
+            return self.allocate(cmp::max(size, align));
+        }
+
+        // address offset has smaller alignment than any block, so aligned address is taken inside
+        // the block that fits alignment padding too. Block is found by any address inside it,
+        // so its size is recorded for the frame of returned address.
+        let block = self.allocate(size + align - 1)?;
+        let result = address::align_up(block, align);
+
+        let block_frame_number = self.frame_containing(block).number();
+        let result_frame_number = self.frame_containing(result).number();
+
+        self.allocation_sizes[result_frame_number] = self.allocation_sizes[block_frame_number];
+
+        Some(result)
+    }
+
     fn free(&mut self, pointer : usize) {
-        let normalized_pointer = pointer - self.memory_start_address;
-        let frame_number       = Frame::number_for_address(normalized_pointer);
-        let buddy_list_index   = self.allocation_sizes[frame_number];
+        let normalized_pointer = pointer - self.address_offset;
+        let frame_number       = self.frame_containing(pointer).number();
+        let buddy_list_index   = self.allocation_sizes[frame_number] as usize;
 
         self.merge_up(normalized_pointer, buddy_list_index);
-
-        // free page frames
-        let p4_table = paging::p4_table();
-        unsafe { p4_table.unmap_pages(pointer, buddy_list_index); }
     }
 }
 
 struct BuddyFreeList {
     buddy_map      : BuddyMap,
     merge_status   : Array<bool>, //change to bitmap to conserve memory    
+    free_blocks_count : usize,
 }
 
 impl BuddyFreeList {
@@ -435,13 +671,18 @@ impl BuddyFreeList {
         let map = UsizeLinkedMap::new(length, memory_allocator);
         BuddyFreeList {
             buddy_map    : BuddyMap(map),
-            merge_status : Array::new(length, memory_allocator)
+            merge_status : Array::new(length, memory_allocator),
+            free_blocks_count : 0
         }
     }        
     
     fn set_free<A>(&mut self, block_index : usize, free_list_allocator : &mut A)
     where A : ConstantSizeMemoryAllocator
     {
+        if self.is_in_use(block_index) {
+            self.free_blocks_count += 1;
+        }
+
         self.buddy_map.add_if_no_key(block_index, free_list_allocator);
         self.merge_status[block_index] = false;
     }
@@ -449,6 +690,10 @@ impl BuddyFreeList {
     fn set_in_use<A>(&mut self, block_index : usize, free_list_allocator : &mut A)
     where A : ConstantSizeMemoryAllocator 
     {
+        if self.is_free(block_index) {
+            self.free_blocks_count -= 1;
+        }
+
         self.buddy_map.0.remove(block_index, free_list_allocator);   
         self.merge_status[block_index] = false;        
     }
@@ -492,6 +737,14 @@ impl BuddyFreeList {
         self.merge_status[block_index] = new_status;
     }
 
+    fn length(&self) -> usize {
+        self.merge_status.length()
+    }
+
+    fn free_blocks_count(&self) -> usize {
+        self.free_blocks_count
+    }
+
     fn mem_size_for_array(length : usize) -> usize {        
         Array::<bool>::mem_size_for(length)
     }
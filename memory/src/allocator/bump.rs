@@ -4,6 +4,7 @@ use stdx_memory::MemoryAllocatorMeta;
 use core::marker;
 use core::mem;
 use core::ptr;
+use address;
 use multiboot::multiboot_header::MultibootHeader;
 use multiboot::multiboot_header::tags::memory_map::*;
 use multiboot::multiboot_header::tags::elf;
@@ -173,6 +174,20 @@ impl MemoryAllocator for BumpAllocator {
         }
     }
 
+    // padding that is skipped to align the block is not returned by free
+    fn allocate_aligned(&mut self, size: usize, align : usize) -> Option<usize> {
+        let result = address::align_up(self.current_pointer, align);
+
+        if result + size > self.pointer_end_address {
+            None
+        }
+        else {
+            self.current_pointer = result + size;
+
+            Some(result)
+        }
+    }
+
     fn free(&mut self, size: usize) {
         self.current_pointer -= size;
     }
@@ -0,0 +1,232 @@
+use stdx_memory::MemoryAllocator;
+use stdx_memory::collections::array::Array;
+use stdx::Sequence;
+use core::cmp;
+use core::fmt;
+use core::ptr;
+
+/// Min size of guard memory before and after every object
+pub const RED_ZONE_SIZE : usize = 32;
+
+/// Number of freed blocks that are kept poisoned before they are returned to allocator
+pub const QUARANTINE_SIZE : usize = 32;
+
+/// Max number of live objects whose red zones are checked by `verify`
+pub const MAX_VERIFIED_OBJECTS : usize = 1024;
+
+/// Value of red zone bytes
+pub const GUARD_BYTE : u8 = 0xFD;
+
+/// Value of freed memory bytes
+pub const POISON_BYTE : u8 = 0xDD;
+
+const LIVE_MAGIC : usize = 0x1157_0B1E_C7AB_CDEF;
+
+// header is placed right before the object, the rest of front red zone is filled with guard bytes
+const HEADER_SIZE : usize = 3 * 8;
+
+/// Kind of detected heap corruption
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// memory around the object was written
+    RedZoneOverwritten,
+    /// freed memory was written
+    UseAfterFree,
+    /// freed object was freed again
+    DoubleFree,
+    /// pointer doesn't point to an object of the allocator
+    InvalidFree,
+}
+
+/// Detected heap corruption
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeapCorruption {
+    pub kind       : CorruptionKind,
+    /// first corrupted byte, or freed pointer
+    pub address    : usize,
+    /// object that owns corrupted memory
+    pub object     : usize,
+    /// start of the block that includes red zones of the object
+    pub block      : usize,
+    /// size of blocks of the slab class or of the block of frames that contains the object
+    pub size_class : usize,
+}
+
+impl fmt::Display for HeapCorruption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at {:#x}, object {:#x} of size class {}", self.kind, self.address, self.object, self.size_class)
+    }
+}
+
+// header of a live object
+#[derive(Clone, Copy, Debug)]
+struct Header {
+    red_zone : usize,
+    size     : usize,
+    magic    : usize,
+}
+
+/// Block that was freed and is kept poisoned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuarantinedBlock {
+    /// pointer that was freed
+    pub pointer : usize,
+    /// start of the block that includes red zones
+    pub block   : usize,
+    /// size of the block that includes red zones
+    pub size    : usize,
+}
+
+/// Red zones and poisoning of heap objects.
+/// Object layout: guard bytes, header, object, guard bytes; red zones are of the same size
+pub struct HeapDebugger {
+    quarantine      : Array<Option<QuarantinedBlock>>,
+    next_quarantine : usize,
+    // pointers of live objects, 0 is an empty slot
+    live_objects    : Array<usize>,
+}
+
+impl HeapDebugger {
+
+    pub fn new<A>(memory_allocator : &mut A) -> Self where A : MemoryAllocator {
+        HeapDebugger {
+            quarantine      : Array::new_fill_default(QUARANTINE_SIZE, memory_allocator),
+            next_quarantine : 0,
+            live_objects    : Array::new_fill_value(MAX_VERIFIED_OBJECTS, 0, memory_allocator),
+        }
+    }
+
+    /// Returns size of red zone for objects of alignment, object stays aligned after the front red zone
+    pub fn red_zone_size(align : usize) -> usize {
+        cmp::max(RED_ZONE_SIZE, align)
+    }
+
+    /// Fills red zones of the block and returns pointer to the object
+    /// # Arguments
+    /// * `block` - start address of memory of `size + 2 * red_zone` bytes
+    /// * `size` - size of object
+    /// * `red_zone` - size of every red zone
+    pub fn guard(&mut self, block : usize, size : usize, red_zone : usize) -> usize {
+        let pointer = block + red_zone;
+
+        unsafe {
+            ptr::write_bytes(block as *mut u8, GUARD_BYTE, red_zone - HEADER_SIZE);
+            ptr::write_bytes((pointer + size) as *mut u8, GUARD_BYTE, red_zone);
+            ptr::write((pointer - HEADER_SIZE) as *mut Header, Header { red_zone, size, magic : LIVE_MAGIC });
+        }
+
+        if let Some(i) = (0 .. self.live_objects.length()).find(|&i| self.live_objects[i] == 0) {
+            self.live_objects[i] = pointer;
+        }
+
+        pointer
+    }
+
+    /// Checks red zones of freed object, poisons it and puts it to quarantine
+    /// # Returns
+    /// block that left quarantine and must be freed, or a corruption with unknown size class
+    pub fn release(&mut self, pointer : usize) -> Result<Option<QuarantinedBlock>, HeapCorruption> {
+        if (0 .. self.quarantine.length()).any(|i| self.quarantine[i].map_or(false, |b| b.pointer == pointer)) {
+            return Err(HeapDebugger::corruption(CorruptionKind::DoubleFree, pointer, pointer, pointer))
+        }
+
+        let header = HeapDebugger::header(pointer);
+
+        if header.magic != LIVE_MAGIC {
+            let kind = if header.magic == HeapDebugger::poison_word() {
+                CorruptionKind::DoubleFree
+            } else {
+                CorruptionKind::InvalidFree
+            };
+
+            return Err(HeapDebugger::corruption(kind, pointer, pointer, pointer))
+        }
+
+        HeapDebugger::check_red_zones(pointer, header)?;
+
+        if let Some(i) = (0 .. self.live_objects.length()).find(|&i| self.live_objects[i] == pointer) {
+            self.live_objects[i] = 0;
+        }
+
+        let block = QuarantinedBlock { pointer, block : pointer - header.red_zone, size : header.size + 2 * header.red_zone };
+
+        unsafe { ptr::write_bytes(block.block as *mut u8, POISON_BYTE, block.size); }
+
+        let evicted = self.quarantine[self.next_quarantine];
+
+        self.quarantine[self.next_quarantine] = Some(block);
+        self.next_quarantine = (self.next_quarantine + 1) % QUARANTINE_SIZE;
+
+        match evicted {
+            Some(evicted) => HeapDebugger::check_poison(evicted).map(|_| Some(evicted)),
+            None => Ok(None)
+        }
+    }
+
+    /// Takes block out of quarantine, quarantine is empty when nothing is returned
+    pub fn take_quarantined(&mut self) -> Result<Option<QuarantinedBlock>, HeapCorruption> {
+        match (0 .. self.quarantine.length()).find(|&i| self.quarantine[i].is_some()) {
+            Some(i) => {
+                let block = self.quarantine[i].take().unwrap();
+
+                HeapDebugger::check_poison(block).map(|_| Some(block))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Checks red zones of live objects and poison of quarantined blocks
+    pub fn verify(&self) -> Result<(), HeapCorruption> {
+        for pointer in (0 .. self.live_objects.length()).map(|i| self.live_objects[i]).filter(|&p| p != 0) {
+            let header = HeapDebugger::header(pointer);
+
+            if header.magic != LIVE_MAGIC {
+                return Err(HeapDebugger::corruption(CorruptionKind::RedZoneOverwritten, pointer - HEADER_SIZE, pointer, pointer))
+            }
+
+            HeapDebugger::check_red_zones(pointer, header)?;
+        }
+
+        for block in (0 .. self.quarantine.length()).filter_map(|i| self.quarantine[i]) {
+            HeapDebugger::check_poison(block)?;
+        }
+
+        Ok(())
+    }
+
+    fn header(pointer : usize) -> Header {
+        unsafe { ptr::read((pointer - HEADER_SIZE) as *const Header) }
+    }
+
+    fn check_red_zones(pointer : usize, header : Header) -> Result<(), HeapCorruption> {
+        // red zone size is read before the guard bytes, overwritten value would point anywhere
+        if header.red_zone < RED_ZONE_SIZE || !header.red_zone.is_power_of_two() {
+            return Err(HeapDebugger::corruption(CorruptionKind::RedZoneOverwritten, pointer - HEADER_SIZE, pointer, pointer))
+        }
+
+        let front_guard = pointer - header.red_zone;
+        let back_guard  = pointer + header.size;
+
+        HeapDebugger::first_byte_other_than(front_guard, header.red_zone - HEADER_SIZE, GUARD_BYTE)
+            .or_else(|| HeapDebugger::first_byte_other_than(back_guard, header.red_zone, GUARD_BYTE))
+            .map_or(Ok(()), |address| Err(HeapDebugger::corruption(CorruptionKind::RedZoneOverwritten, address, pointer, front_guard)))
+    }
+
+    fn check_poison(block : QuarantinedBlock) -> Result<(), HeapCorruption> {
+        HeapDebugger::first_byte_other_than(block.block, block.size, POISON_BYTE)
+            .map_or(Ok(()), |address| Err(HeapDebugger::corruption(CorruptionKind::UseAfterFree, address, block.pointer, block.block)))
+    }
+
+    fn first_byte_other_than(start_address : usize, size : usize, value : u8) -> Option<usize> {
+        (start_address .. start_address + size).find(|&address| unsafe { *(address as *const u8) } != value)
+    }
+
+    fn poison_word() -> usize {
+        (0 .. 8).fold(0, |word, _| (word << 8) | POISON_BYTE as usize)
+    }
+
+    // size class is filled by allocator, debugger doesn't know where the object came from
+    fn corruption(kind : CorruptionKind, address : usize, object : usize, block : usize) -> HeapCorruption {
+        HeapCorruption { kind, address, object, block, size_class : 0 }
+    }
+}
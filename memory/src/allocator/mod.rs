@@ -4,16 +4,43 @@ pub mod bump;
 pub mod free_list;
 
 pub mod slab;
+pub mod zone;
+pub mod statistics;
+#[cfg(feature = "heap_debugging")]
+pub mod debugging;
 
-use frame::Frame;
+use frame::{PhysFrame, FrameSource};
+#[cfg(target_os = "none")]
+use hardware::x86_64::interrupts;
 
-fn align_addresses(start_address1 : usize, end_address1 : usize) -> (usize, usize) {
-    (
-        Frame::address_align_up(start_address1),
-        Frame::address_align_down(end_address1)
-    )
+/// Frame allocator that counts references to frames, frames can be shared between address spaces
+/// (e.g. copy-on-write pages of forked processes). Freshly allocated frame has single reference.
+pub trait SharedFrameAllocator : FrameSource {
+
+    /// Adds reference to allocated frame
+    fn share_frame(&mut self, frame : PhysFrame);
+
+    /// Removes reference to allocated frame, frame is freed when the last reference is removed
+    /// # Returns
+    /// true if frame was freed
+    fn release_frame(&mut self, frame : PhysFrame) -> bool;
+
+    /// Returns number of references to allocated frame
+    fn frame_reference_count(&self, frame : PhysFrame) -> usize;
 }
 
-fn total_memory(start_address : usize, end_address : usize) -> usize {
-    end_address - start_address + 1
-}
\ No newline at end of file
+/// Runs allocator code that must not be interrupted by process switch, heap and frame allocator are shared
+/// by processes and kernel thread, so preempted update would leave their data structures half updated.
+/// Sections can be nested, e.g. frame allocator is used by the heap.
+#[cfg(target_os = "none")]
+#[inline(always)]
+pub fn without_preemption<F, R>(action : F) -> R where F : FnOnce() -> R {
+    interrupts::without_interrupts(action)
+}
+
+/// Host tests run in user mode, which can't disable interrupts and is never preempted by the kernel of this crate
+#[cfg(not(target_os = "none"))]
+#[inline(always)]
+pub fn without_preemption<F, R>(action : F) -> R where F : FnOnce() -> R {
+    action()
+}
@@ -3,10 +3,17 @@ use stdx_memory::collections::array::Array;
 use stdx_memory::collections::immutable::double_linked_list::DoubleLinkedList;
 use stdx_memory::collections::linked_list::LinkedList;
 use stdx_memory::trees::avl;
+use allocator;
 use allocator::bump;
 use allocator::free_list::FreeListAllocator;
 use allocator::buddy::BuddyAllocator;
-use allocator;
+use allocator::statistics::{HeapStatistics, SizeClassStatistics, ReallocStatistics, SIZE_CLASSES_COUNT, MAX_BUDDY_LEVELS};
+#[cfg(feature = "leak_tracking")]
+use allocator::statistics::{AllocationSite, LiveAllocation, MAX_TRACKED_ALLOCATIONS};
+#[cfg(feature = "heap_debugging")]
+use allocator::debugging::{HeapDebugger, HeapCorruption};
+use frame::regions::MemoryRegions;
+use address;
 use stdx::iterator::IteratorExt;
 use stdx::{Iterable,Sequence} ;
 use stdx::math;
@@ -18,13 +25,18 @@ use core::alloc::GlobalAlloc;
 use core::alloc::Layout;
 use core::alloc::AllocErr;
 use core::ptr;
+use core::fmt;
+use core::marker;
 use display::vga::writer::Writer;
-use frame::FRAME_SIZE;
+use frame::{PhysFrame, FRAME_SIZE};
 use core::ops::DerefMut;
 use core::ops::Deref;
 
 const MIN_ALLOCATION_SIZE : usize = 32; //bytes
 
+// frame owner of cells of `ObjectCache`, such objects can be freed only by their cache
+const OBJECT_CACHE_OWNER : u8 = u8::max_value();
+
 type ListOfAllocators = DoubleLinkedList<SlabCell, bump::ConstSizeBumpAllocator>;
 type ProperPtr              = heap::RC<ListOfAllocators, bump::ConstSizeBumpAllocator>;
 
@@ -33,7 +45,9 @@ struct Slab {
     // free data structures
     non_empty : avl::AVLTree<ProperPtr, bump::ConstSizeBumpAllocator>,
     // allocate data structures
-    non_full : Option<ProperPtr>
+    non_full : Option<ProperPtr>,
+    // number of slab cells, every cell uses two frames
+    cells_count : usize
 }
 
 #[repr(C)]
@@ -44,6 +58,9 @@ struct SlabCell {
     tree_cell_allocator : bump::ConstSizeBumpAllocator,
 
     dlist_cell_allocator : bump::ConstSizeBumpAllocator,
+
+    // frame with free list and cells of this slab cell, freed together with working memory frame
+    aux_data_structures_frame : usize,
 }
 
 impl SlabCell {
@@ -72,7 +89,8 @@ impl SlabCell {
         SlabCell {
             value : allocator,
             tree_cell_allocator,
-            dlist_cell_allocator
+            dlist_cell_allocator,
+            aux_data_structures_frame : aux_structures_frame_start_address
         }
     }
 }
@@ -100,12 +118,9 @@ impl cmp::PartialEq for SlabCell {
 }
 
 impl Slab {
-    fn new(allocation_size: usize, frame_allocator: &mut BuddyAllocator) -> Option<Self> {
-        let a = frame_allocator.allocate_frame();
-        let b = frame_allocator.allocate_frame();
-
-        match (a, b) {
-            (Some(working_memory_frame), Some(aux_data_structures_frame)) => {
+    fn new(allocation_size: usize, frames : &mut CellFrames) -> Option<Self> {
+        match frames.allocate() {
+            Some((working_memory_frame, aux_data_structures_frame)) => {
                 let mut slab_cell = SlabCell::new(working_memory_frame, aux_data_structures_frame, allocation_size);
 
                 let mut dlist_alloc = ptr::NonNull::from(&mut slab_cell.dlist_cell_allocator);
@@ -114,22 +129,21 @@ impl Slab {
 
                 let mut slab_cell_boxed = unsafe { DoubleLinkedList::new_rc(slab_cell, dlist_alloc.as_mut()) };
 
-                let chck = (&mut slab_cell_boxed.value_mut().value).allocate(10);
-
                 let tree = unsafe { avl::AVLTree::new(heap::RC::clone(&slab_cell_boxed), tree_cell_alloc.as_mut()) };
 
                 Some(Slab {
                     non_empty: tree,
                     non_full: Some(slab_cell_boxed),
+                    cells_count : 1
                 })
             }
-            _ => None
+            None => None
         }
     }
 
     // slab size is passed here to prevent saving it in slab structure, because slab allocator
     // knows what slabs and of what sizes it has
-    fn allocate(&mut self, size : usize, slab_size : usize, frame_allocator : &mut BuddyAllocator) -> Option<usize> {
+    fn allocate(&mut self, size : usize, slab_size : usize, frames : &mut CellFrames) -> Option<usize> {
         // check if there is any non-full allocators present,
         // if not - create a new one (increase slab size) and allocate from it
         let non_full_allocation_result = self.try_allocate_non_full();
@@ -137,11 +151,9 @@ impl Slab {
         non_full_allocation_result.or_else(|| {
 
             let true_slab_size                 = BuddyAllocator::true_allocation_size_for(slab_size);
-            let a   = frame_allocator.allocate_frame();
-            let b   = frame_allocator.allocate_frame();
 
-            match (a, b) {
-                (Some(working_memory_frame), Some(aux_data_structures_frame)) => {
+            match frames.allocate() {
+                Some((working_memory_frame, aux_data_structures_frame)) => {
                     let mut new_slab_cell = SlabCell::new(working_memory_frame, aux_data_structures_frame, size);
 
                     let mut dlist_alloc = ptr::NonNull::from(&mut new_slab_cell.dlist_cell_allocator);
@@ -160,11 +172,12 @@ impl Slab {
 
                     unsafe { self.non_empty.insert(heap::RC::clone(&new_dlist_cell), tree_cell_alloc.as_mut()); }
                     self.non_full = Some(new_dlist_cell);
+                    self.cells_count += 1;
 
                     result
                 }
                 // if no frames are available - then its out of mem error
-                _ => None
+                None => None
             }
         })
     }
@@ -182,7 +195,7 @@ impl Slab {
                 .unwrap_or(false)
     }
 
-    fn free_from_non_full(&mut self, pointer : usize, frame_allocator : &mut BuddyAllocator) {
+    fn free_from_non_full(&mut self, pointer : usize, frames : &mut CellFrames) {
         let mut allocator_is_empty = false;
 
         // let the allocator perform free
@@ -199,6 +212,7 @@ impl Slab {
         if allocator_is_empty {
             if let Some(head_cell) = self.non_full.take() {
                 let head_start_addr = (&head_cell.value().value).start_address();
+                let aux_data_structures_frame = head_cell.value().aux_data_structures_frame;
 
                 {
                     let prev = head_cell.prev();
@@ -212,13 +226,14 @@ impl Slab {
                 // take head_cell out of a tree (drops the head_cell)
                 self.non_empty.delete_by(head_start_addr, |n| (&n.value().value).start_address());
 
-                // reclaim frame
-                frame_allocator.free(head_start_addr);
+                // reclaim frames
+                frames.free(head_start_addr, aux_data_structures_frame);
+                self.cells_count -= 1;
             }
         }
     }
 
-    fn free_from_non_empty(&mut self, pointer : usize, frame_allocator : &mut BuddyAllocator) {
+    fn free_from_non_empty(&mut self, pointer : usize, frames : &mut CellFrames) {
 
         let mut dlist_opt = self.non_empty.find_by(&pointer,
                                                    |node| node.value().value.start_address(),
@@ -239,6 +254,7 @@ impl Slab {
         if allocator_is_empty {
             if let Some(dlist_cell) = dlist_opt {
                 let start_address = dlist_cell.value().value.start_address();
+                let aux_data_structures_frame = dlist_cell.value().aux_data_structures_frame;
 
                 // take head_cell out of dlist
                 DoubleLinkedList::modify_neighbour_connections(dlist_cell.leak());
@@ -246,20 +262,21 @@ impl Slab {
                 // take head_cell out of a tree (drops the head_cell)
                 self.non_empty.delete_by(start_address, |n| n.value().value.start_address());
 
-                // reclaim frame
-                frame_allocator.free(start_address);
+                // reclaim frames
+                frames.free(start_address, aux_data_structures_frame);
+                self.cells_count -= 1;
             }
         }
     }
 
-    fn free(&mut self, pointer : usize, frame_allocator : &mut BuddyAllocator) {
+    fn free(&mut self, pointer : usize, frames : &mut CellFrames) {
         // maybe the pointer belongs to allocator in the head of non_full dlist
         // if not then search for allocator in the address tree
         if  self.address_belongs_to_non_full(pointer)  {
-            self.free_from_non_full(pointer, frame_allocator);
+            self.free_from_non_full(pointer, frames);
         }
         else {
-            self.free_from_non_empty(pointer, frame_allocator);
+            self.free_from_non_empty(pointer, frames);
         }
     }
 
@@ -268,63 +285,121 @@ impl Slab {
     }
 }
 
-pub struct SlabAllocator {
-    size_to_slab                : Array<Option<Slab>>,
-    //address_to_size         : avl::AVLTree<(usize, usize), FreeListAllocator>,
-    start_address             : usize,
-    end_address               : usize,
-    array_allocator         : bump::BumpAllocator,
-    tree_allocator            : FreeListAllocator,
-    linked_list_allocator :  FreeListAllocator,
-    frame_allocator        : BuddyAllocator,
-}
+/// Max number of empty slab cells that every size class can keep for reuse
+pub const MAX_CACHED_SLABS : usize = 8;
 
-type DlistOfAllocators = DoubleLinkedList<FreeListAllocator, FreeListAllocator>;
+const DEFAULT_CACHED_SLABS : usize = 1;
 
-impl SlabAllocator {
+// frames of empty slab cells kept for reuse: working memory frame and aux data structures frame
+#[derive(Clone, Copy, Default)]
+struct SlabCache {
+    frames : [(usize, usize); MAX_CACHED_SLABS],
+    count  : usize,
+}
 
-    pub fn total_aux_data_structures_size(start_address1 : usize, end_address1 : usize) -> usize {
-        let (start_address, end_address)                        = allocator::align_addresses(start_address1, end_address1);
-        let total_slab_count          = SlabAllocator::slab_count(start_address, end_address);
+impl SlabCache {
+    // returns number of frames that were given back to frame allocator
+    fn release_above(&mut self, limit : usize, frame_allocator : &mut BuddyAllocator) -> usize {
+        let mut freed_frames = 0;
 
-        let (array_size, avl_tree_size, linked_list_size)   = SlabAllocator::aux_data_structures_size(total_slab_count);
+        while self.count > limit {
+            self.count -= 1;
 
-        let this_aux_data_structures_size =  array_size + avl_tree_size + linked_list_size;
+            let (working_memory_frame, aux_data_structures_frame) = self.frames[self.count];
+
+            frame_allocator.free(working_memory_frame);
+            frame_allocator.free(aux_data_structures_frame);
+            freed_frames += 2;
+        }
 
-        this_aux_data_structures_size + BuddyAllocator::total_aux_data_structures_size(start_address + this_aux_data_structures_size + 1, end_address)
+        freed_frames
     }
+}
 
-    fn avl_tree_cell_size() -> usize {
-        avl::AVLTree::<FreeListAllocator, FreeListAllocator>::cell_size()
+// frames for cells of a slab, empty cells go to the cache of slab size class before frame allocator
+struct CellFrames<'a> {
+    frame_allocator : &'a mut BuddyAllocator,
+    cache           : &'a mut SlabCache,
+    cache_limit     : usize,
+}
+
+impl<'a> CellFrames<'a> {
+    fn allocate(&mut self) -> Option<(usize, usize)> {
+        if self.cache.count > 0 {
+            self.cache.count -= 1;
+
+            return Some(self.cache.frames[self.cache.count])
+        }
+
+        let a = self.frame_allocator.allocate(FRAME_SIZE);
+        let b = self.frame_allocator.allocate(FRAME_SIZE);
+
+        match (a, b) {
+            (Some(working_memory_frame), Some(aux_data_structures_frame)) => Some((working_memory_frame, aux_data_structures_frame)),
+            (Some(frame), None) | (None, Some(frame)) => {
+                self.frame_allocator.free(frame);
+                None
+            }
+            _ => None
+        }
     }
 
-    fn linked_list_cell_size() -> usize {
-        heap::rc_size_for::<DlistOfAllocators>()
+    fn free(&mut self, working_memory_frame : usize, aux_data_structures_frame : usize) {
+        if self.cache.count < self.cache_limit {
+            self.cache.frames[self.cache.count] = (working_memory_frame, aux_data_structures_frame);
+            self.cache.count += 1;
+        }
+        else {
+            self.frame_allocator.free(working_memory_frame);
+            self.frame_allocator.free(aux_data_structures_frame);
+        }
     }
+}
 
-    fn slab_count(start_address : usize, end_address : usize) -> usize {
-        assert!(end_address > start_address, "Cannot create allocator when end address <= start address. Start address : {}, end address {}", start_address, end_address);
+pub struct SlabAllocator {
+    size_to_slab                : Array<Option<Slab>>,
+    //address_to_size         : avl::AVLTree<(usize, usize), FreeListAllocator>,
+    // for every frame: index of the slab that uses the frame + 1, 0 if frame was allocated by frame allocator,
+    // OBJECT_CACHE_OWNER if frame is a cell of object cache
+    frame_to_slab              : Array<u8>,
+    frame_to_slab_allocator : bump::BumpAllocator,
+    frame_allocator        : BuddyAllocator,
+    size_classes           : [SizeClassStatistics; SIZE_CLASSES_COUNT],
+    frame_blocks           : SizeClassStatistics,
+    realloc_statistics     : ReallocStatistics,
+    slab_caches            : [SlabCache; SIZE_CLASSES_COUNT],
+    cached_slabs_limit     : usize,
+    // sites of live allocations, recorded by `track_allocation`
+    #[cfg(feature = "leak_tracking")]
+    live_allocations       : Array<Option<LiveAllocation>>,
+    // red zones and quarantine of freed objects
+    #[cfg(feature = "heap_debugging")]
+    debugger               : HeapDebugger,
+}
 
-        let total_memory = allocator::total_memory(start_address, end_address);
+type DlistOfAllocators = DoubleLinkedList<FreeListAllocator, FreeListAllocator>;
 
-        assert!(total_memory > MIN_ALLOCATION_SIZE, "Cannot create allocator when total memory size < MIN_ALLOCATION_SIZE (32). Start address : {}, end address {}",
-                start_address,
-                end_address);
+impl SlabAllocator {
 
-        SlabAllocator::total_slab_count(total_memory)
+    /// Returns size of memory required by allocator aux data structures
+    /// # Arguments
+    /// * `regions` - physical memory that will be managed by allocator
+    pub fn aux_data_structures_size_for(regions : &MemoryRegions) -> usize {
+        // slab data structures are allocated from frames of buddy allocator
+        SlabAllocator::frame_to_slab_size(regions) + BuddyAllocator::aux_data_structures_size_for(regions)
     }
 
-    fn aux_data_structures_size(total_slab_count : usize) -> (usize, usize, usize) {
+    fn frame_to_slab_size(regions : &MemoryRegions) -> usize {
+        // keep buddy allocator aux data structures frame aligned
+        PhysFrame::address_align_up(Array::<u8>::mem_size_for(BuddyAllocator::frames_count_for(regions)))
+    }
 
-        let array_size             = Array::<Option<Slab>>::mem_size_for(total_slab_count);
-        let avl_tree_size        = SlabAllocator::avl_tree_cell_size() * total_slab_count;
-        let linked_list_size    = SlabAllocator::linked_list_cell_size() * (31457281 / 4096);
+    fn avl_tree_cell_size() -> usize {
+        avl::AVLTree::<FreeListAllocator, FreeListAllocator>::cell_size()
+    }
 
-        (
-            array_size,
-            avl_tree_size,
-            linked_list_size
-        )
+    fn linked_list_cell_size() -> usize {
+        heap::rc_size_for::<DlistOfAllocators>()
     }
 
     pub fn frame_allocator(&mut self) -> &mut BuddyAllocator {
@@ -338,72 +413,202 @@ impl SlabAllocator {
          slabs_are_empty// && tree_is_empty
     }
 
-    pub fn new(start_address : usize, total_memory : usize, end_address : usize) -> Self {
+    /// Creates allocator of provided physical memory regions
+    /// # Arguments
+    /// * `regions` - physical memory to manage, must not contain aux data structures memory
+    /// * `aux_start_address` - start of zeroed memory of size `aux_data_structures_size_for(regions)`
+    /// * `address_offset` - offset added to physical addresses returned by allocator, e.g. `address::PHYSICAL_MEMORY_OFFSET`
+    pub fn from_regions(regions : &MemoryRegions, aux_start_address : usize, address_offset : usize) -> Self {
+        SlabAllocator::from_managed_regions(regions, regions, aux_start_address, address_offset)
+    }
 
-        let mut frame_allocator = BuddyAllocator::new(start_address, total_memory, end_address);
+    /// Creates allocator whose frame allocator indexes more memory than it sets free, see `BuddyAllocator::from_managed_regions`
+    /// # Arguments
+    /// * `managed` - physical memory indexed by frame allocator, free memory included
+    /// * `free` - physical memory that is free right away, must not contain aux data structures memory
+    /// * `aux_start_address` - start of zeroed memory of size `aux_data_structures_size_for(managed)`
+    /// * `address_offset` - offset added to physical addresses returned by allocator, e.g. `address::PHYSICAL_MEMORY_OFFSET`
+    pub fn from_managed_regions(managed : &MemoryRegions, free : &MemoryRegions, aux_start_address : usize, address_offset : usize) -> Self {
+        let frame_to_slab_size = SlabAllocator::frame_to_slab_size(managed);
 
-        let total_slab_count = SlabAllocator::total_slab_count(total_memory);
+        let mut frame_to_slab_allocator = bump::BumpAllocator::from_address(aux_start_address, frame_to_slab_size);
+        let frame_to_slab                  = Array::<u8>::new_fill_value(BuddyAllocator::frames_count_for(managed), 0, &mut frame_to_slab_allocator);
 
-        let (array_size, avl_tree_size, linked_list_size)   = SlabAllocator::aux_data_structures_size(total_slab_count);
+        let mut frame_allocator = BuddyAllocator::from_managed_regions(managed, free, aux_start_address + frame_to_slab_size, address_offset);
 
-        // create inner allocators
-        let mut array_allocator             = bump::BumpAllocator::from_address(start_address, array_size);
-        let mut tree_allocator               = FreeListAllocator::from_size(array_allocator.end_address() + 1, avl_tree_size, SlabAllocator::avl_tree_cell_size());
-        let mut linked_list_allocator    = FreeListAllocator::from_size(tree_allocator.end_address() + 1, linked_list_size, SlabAllocator::linked_list_cell_size());
+        let total_slab_count = SlabAllocator::total_slab_count(managed.total_size());
 
         // create allocate/free data structures
-        let size_to_slab            = Array::<Option<Slab>>::new(total_slab_count, &mut frame_allocator);
+        let size_to_slab            = Array::<Option<Slab>>::new_fill_default(total_slab_count, &mut frame_allocator);
         //let address_to_size     = avl::AVLTree::<(usize, usize), FreeListAllocator>::new_empty();
-
-        // create frame allocator
-        //let frame_allocator = BuddyAllocator::new2(linked_list_allocator.end_address() + 1, total_memory, end_address);
+        #[cfg(feature = "leak_tracking")]
+        let live_allocations        = Array::<Option<LiveAllocation>>::new_fill_default(MAX_TRACKED_ALLOCATIONS, &mut frame_allocator);
+        #[cfg(feature = "heap_debugging")]
+        let debugger                = HeapDebugger::new(&mut frame_allocator);
 
         SlabAllocator {
             size_to_slab,
             //address_to_size,
-            start_address,
-            end_address,
-            array_allocator,
-            tree_allocator,
-            linked_list_allocator,
-            frame_allocator
+            frame_to_slab,
+            frame_to_slab_allocator,
+            frame_allocator,
+            size_classes       : [SizeClassStatistics::default(); SIZE_CLASSES_COUNT],
+            frame_blocks       : SizeClassStatistics::default(),
+            realloc_statistics : ReallocStatistics::default(),
+            slab_caches        : [SlabCache::default(); SIZE_CLASSES_COUNT],
+            cached_slabs_limit : DEFAULT_CACHED_SLABS,
+            #[cfg(feature = "leak_tracking")]
+            live_allocations,
+            #[cfg(feature = "heap_debugging")]
+            debugger
         }
     }
 
-    fn free_with_size_hint(&mut self, pointer: usize, size: usize) {
-        if size >= FRAME_SIZE {
-            self.frame_allocator.free(pointer)
-        } else {
-            let slab_array_idx = SlabAllocator::index_from_size(size);
+    pub fn realloc_statistics(&self) -> ReallocStatistics {
+        self.realloc_statistics
+    }
 
-            let mut slab_is_fully_free = false;
-            {
-                let linked_list_allocator = &mut self.linked_list_allocator;
-                let frame_allocator = &mut self.frame_allocator;
+    /// Returns snapshot of allocator counters
+    pub fn statistics(&self) -> HeapStatistics {
+        let mut result = HeapStatistics {
+            size_classes : self.size_classes,
+            frame_blocks : self.frame_blocks,
+            realloc      : self.realloc_statistics,
+            .. HeapStatistics::default()
+        };
 
-                if let Some(ref mut slab) = &mut self.size_to_slab[slab_array_idx] {
-                    slab.free(pointer, frame_allocator);
+        for i in 0 .. cmp::min(SIZE_CLASSES_COUNT, self.size_to_slab.length()) {
+            result.size_classes[i].slabs = self.size_to_slab[i].as_ref().map_or(0, |slab| slab.cells_count);
+            result.size_classes[i].cached_slabs = self.slab_caches[i].count;
+        }
 
-                    slab_is_fully_free = slab.is_fully_free();
-                }
-            }
+        for level in 0 .. cmp::min(self.frame_allocator.levels_count(), MAX_BUDDY_LEVELS) {
+            result.free_blocks[level] = self.frame_allocator.free_blocks_count(level);
+        }
+
+        result
+    }
+
+    /// Sets number of empty slab cells that every size class keeps for reuse, cells above the limit are freed
+    /// # Arguments
+    /// * `limit` - number of cells, must not be greater than `MAX_CACHED_SLABS`
+    pub fn set_cached_slabs_limit(&mut self, limit : usize) {
+        assert!(limit <= MAX_CACHED_SLABS, "Cannot cache more than {} slabs of size class", MAX_CACHED_SLABS);
+
+        self.cached_slabs_limit = limit;
+
+        for cache in self.slab_caches.iter_mut() {
+            cache.release_above(limit, &mut self.frame_allocator);
+        }
+    }
+
+    /// Returns frames of cached empty slabs to frame allocator. Allocator calls it when it runs out of frames,
+    /// frame allocator users can call it under memory pressure as well.
+    /// # Returns
+    /// number of freed frames
+    pub fn shrink(&mut self) -> usize {
+        let mut freed_frames = 0;
+
+        for cache in self.slab_caches.iter_mut() {
+            freed_frames += cache.release_above(0, &mut self.frame_allocator);
+        }
+
+        freed_frames
+    }
+
+    /// Changes size of allocated block. Block keeps its address if the new size fits its slab size class,
+    /// block of frames keeps it if it can merge with free buddies. Otherwise data is moved to a new block.
+    /// # Arguments
+    /// * `pointer` - start address of allocated block
+    /// * `old_size` - size of data to keep
+    /// * `new_size` - requested size of block
+    /// * `align` - alignment of block, must be the same as on allocation
+    pub fn reallocate(&mut self, pointer : usize, old_size : usize, new_size : usize, align : usize) -> Option<usize> {
+        if new_size == 0 {
+            return None
+        }
+
+        let size_rounded = SlabAllocator::allocation_size_rounded(cmp::max(new_size, align));
+
+        if self.resize_in_place(pointer, size_rounded) {
+            self.realloc_statistics.in_place += 1;
+
+            Some(pointer)
+        }
+        else {
+            let result = self.allocate_aligned(new_size, align);
+
+            if let Some(new_pointer) = result {
+                unsafe { ptr::copy_nonoverlapping(pointer as *const u8, new_pointer as *mut u8, cmp::min(old_size, new_size)); }
 
-            if slab_is_fully_free {
-                self.size_to_slab[slab_array_idx] = None;
+                #[cfg(feature = "leak_tracking")]
+                self.move_tracked_allocation(pointer, new_pointer, new_size);
+
+                self.free(pointer);
+                self.realloc_statistics.moved += 1;
             }
+
+            result
         }
     }
 
-    fn buddy_free_list_size(buddy_levels_count : usize, total_memory : usize, tree_cell_size : usize, linked_list_cell_size : usize) -> (usize, usize) {
-        let mut tree_size = 0;
-        let mut linked_list_size = 0;
+    #[cfg(not(feature = "heap_debugging"))]
+    fn resize_in_place(&mut self, pointer : usize, size_rounded : usize) -> bool {
+        let owner = self.frame_to_slab[self.frame_number(pointer)];
+
+        match owner {
+            // block of frames is kept unless the new size belongs to a slab
+            0 => size_rounded >= FRAME_SIZE && self.frame_allocator.grow_in_place(pointer, size_rounded),
+            OBJECT_CACHE_OWNER => panic!("Object {:#x} of object cache cannot be reallocated by slab allocator", pointer),
+            owner => size_rounded < FRAME_SIZE && SlabAllocator::index_from_size(size_rounded) == owner as usize - 1
+        }
+    }
+
+    // object is surrounded by red zones, so it is always moved to a new guarded block
+    #[cfg(feature = "heap_debugging")]
+    fn resize_in_place(&mut self, pointer : usize, size_rounded : usize) -> bool {
+        false
+    }
+
+    fn frame_number(&self, pointer : usize) -> usize {
+        self.frame_allocator.frame_containing(pointer).number()
+    }
+
+    // frame that was returned by buddy allocator is used by slab, or by other allocation afterwards
+    fn set_frame_owner(&mut self, pointer : usize, slab_array_idx : Option<usize>) {
+        let owner = slab_array_idx.map_or(0, |idx| idx as u8 + 1);
+
+        self.set_frame_owner0(pointer, owner);
+    }
+
+    fn set_frame_owner0(&mut self, pointer : usize, owner : u8) {
+        let frame_number = self.frame_number(pointer);
+
+        self.frame_to_slab[frame_number] = owner;
+    }
+
+    fn free_from_slab(&mut self, pointer: usize, slab_array_idx : usize) {
+        self.size_classes[slab_array_idx].freed();
+
+        let mut slab_is_fully_free = false;
+        {
+            let mut frames = CellFrames {
+                frame_allocator : &mut self.frame_allocator,
+                cache           : &mut self.slab_caches[slab_array_idx],
+                cache_limit     : self.cached_slabs_limit
+            };
+
+            if let Some(ref mut slab) = &mut self.size_to_slab[slab_array_idx] {
+                slab.free(pointer, &mut frames);
 
-        for block_count in block_count!(total_memory, buddy_levels_count, MIN_ALLOCATION_SIZE) {
-            tree_size           += tree_cell_size * block_count;
-            linked_list_size += linked_list_cell_size * block_count;
+                slab_is_fully_free = slab.is_fully_free();
+            }
         }
 
-        (tree_size, linked_list_size)
+        // every frame of the slab was returned to buddy allocator
+        if slab_is_fully_free {
+            self.size_to_slab[slab_array_idx] = None;
+        }
     }
 
     fn total_slab_count(total_memory : usize) -> usize {
@@ -436,141 +641,465 @@ impl SlabAllocator {
         }
     }
 
+    fn allocate_from_slab(&mut self, size_rounded : usize, size_array_idx : usize) -> Option<usize> {
+        let mut frames = CellFrames {
+            frame_allocator : &mut self.frame_allocator,
+            cache           : &mut self.slab_caches[size_array_idx],
+            cache_limit     : self.cached_slabs_limit
+        };
+//        let  address_to_size            = &mut self.address_to_size;
+
+        // check if we have existing slab for requested size,
+        // if not - try create a new slab for this size
+        let result_from_existing_slab = {
+            let slab_opt = &mut self.size_to_slab[size_array_idx];
+
+            slab_opt.as_mut().and_then(|slab| {
+
+                let result = SlabAllocator::allocate0(
+                    size_rounded,
+                    slab,
+                    &mut frames/*,
+                    address_to_size*/);
+
+                result
+            })
+        };
+
+        // if no slab is found, then try create a new one
+        let size_to_slab  = &mut self.size_to_slab;
+        result_from_existing_slab.or_else(|| {
+            let new_slab_opt = Slab::new(size_rounded, &mut frames);
+
+            // if slab cannot be created - then its oom
+            new_slab_opt.and_then(|mut new_slab| {
+
+                let result = SlabAllocator::allocate0(
+                    size_rounded,
+                    &mut new_slab,
+                    &mut frames/*,
+                    address_to_size*/);
+
+                size_to_slab.update(size_array_idx, Some(new_slab));
+
+                result
+            })
+        })
+    }
+
     fn allocate0(
         size_rounded : usize,
         slab : &mut Slab,
-        frame_allocator : &mut BuddyAllocator,
-        linked_list_allocator :  &mut FreeListAllocator,
-        tree_allocator : &mut FreeListAllocator/*,
+        frames : &mut CellFrames/*,
         address_to_size : &mut avl::AVLTree<(usize, usize), FreeListAllocator>*/) -> Option<usize> {
 
         slab.allocate(
             size_rounded,
             size_rounded,
-            frame_allocator
+            frames
         ).map(|r| {
             //address_to_size.insert((r, size_rounded), tree_allocator);
             r
         })
     }
-}
 
-impl MemoryAllocator for SlabAllocator {
-    fn allocate(&mut self, size: usize) -> Option<usize> {
+    fn allocate_block(&mut self, size: usize, align : usize) -> Option<usize> {
         if size == 0 {
             None
         } else {
-            let size_rounded = SlabAllocator::allocation_size_rounded(size);
+            // objects of a slab are aligned by their size, so the size is rounded up to alignment
+            let size_rounded = SlabAllocator::allocation_size_rounded(cmp::max(size, align));
 
             if size_rounded > self.full_size() {
+                self.frame_blocks.failed();
                 None
             }else if size_rounded >= FRAME_SIZE {
-                self.frame_allocator.allocate(size_rounded)
+                let mut result = self.frame_allocator.allocate_aligned(size_rounded, align);
+
+                // memory pressure: reclaim cached slabs and try again before reporting OOM
+                if result.is_none() && self.shrink() > 0 {
+                    result = self.frame_allocator.allocate_aligned(size_rounded, align);
+                }
+
+                if let Some(pointer) = result {
+                    self.set_frame_owner(pointer, None);
+                    self.frame_blocks.allocated();
+                }
+                else {
+                    self.frame_blocks.failed();
+                }
+
+                result
             }
                 else {
                 let size_array_idx = SlabAllocator::index_from_size(size_rounded);
+                let mut result = self.allocate_from_slab(size_rounded, size_array_idx);
 
-                let  frame_allocator            = &mut self.frame_allocator;
-                let  linked_list_allocator     = &mut self.linked_list_allocator;
-                let  tree_allocator               = &mut self.tree_allocator;
-//                let  address_to_size            = &mut self.address_to_size;
-
-                // check if we have existing slab for requested size,
-                // if not - try create a new slab for this size
-                let result_from_existing_slab = {
-                    let slab_opt = &mut self.size_to_slab[size_array_idx];
-
-                    slab_opt.as_mut().and_then(|slab| {
-
-                        let result = SlabAllocator::allocate0(
-                            size_rounded,
-                            slab,
-                            frame_allocator,
-                            linked_list_allocator,
-                            tree_allocator/*,
-                            address_to_size*/);
-
-                        result
-                    })
-                };
-
-                // if no slab is found, then try create a new one
-                let size_to_slab  = &mut self.size_to_slab;
-                result_from_existing_slab.or_else(|| {
-                    let new_slab_opt = Slab::new(size_rounded, frame_allocator);
-
-                    // if slab cannot be created - then its oom
-                    new_slab_opt.and_then(|mut new_slab| {
-
-                        let result = SlabAllocator::allocate0(
-                            size_rounded,
-                            &mut new_slab,
-                            frame_allocator,
-                            linked_list_allocator,
-                            tree_allocator/*,
-                            address_to_size*/);
-
-                        size_to_slab.update(size_array_idx, Some(new_slab));
-
-                        result
-                    })
-                })
+                // cached slabs of other size classes can free frames for a new slab
+                if result.is_none() && self.shrink() > 0 {
+                    result = self.allocate_from_slab(size_rounded, size_array_idx);
+                }
+
+                if let Some(pointer) = result {
+                    self.set_frame_owner(pointer, Some(size_array_idx));
+                    self.size_classes[size_array_idx].allocated();
+                }
+                else {
+                    self.size_classes[size_array_idx].failed();
+                }
+
+                result
             }
         }
     }
 
-    fn free(&mut self, pointer: usize) {
-        /*if let Some(was_allocated) = self.address_to_size.find_by(&pointer, |w| w.0,  |x, y| x .1== *y)*/ /*{
-            let slab_array_idx = SlabAllocator::index_from_size(was_allocated.1);
+    fn free_block(&mut self, pointer: usize) {
+        let owner = self.frame_to_slab[self.frame_number(pointer)];
+
+        // frame owner tells if pointer belongs to a slab or to a block of frames
+        match owner {
+            0 => {
+                self.frame_blocks.freed();
+                self.frame_allocator.free(pointer)
+            },
+            OBJECT_CACHE_OWNER => panic!("Object {:#x} of object cache must be freed by its cache", pointer),
+            owner => self.free_from_slab(pointer, owner as usize - 1)
+        }
+    }
 
-            let mut slab_is_fully_free = false;
-            {
-                let linked_list_allocator  = &mut self.linked_list_allocator;
-                let frame_allocator         = &mut self.frame_allocator;
+    #[cfg(not(feature = "heap_debugging"))]
+    fn allocate_object(&mut self, size: usize, align : usize) -> Option<usize> {
+        self.allocate_block(size, align)
+    }
 
-                if let Some(ref mut slab) = &mut self.size_to_slab[slab_array_idx]  {
+    #[cfg(not(feature = "heap_debugging"))]
+    fn free_object(&mut self, pointer: usize) {
+        self.free_block(pointer)
+    }
+}
 
-                    slab.free(pointer, frame_allocator);
+#[cfg(feature = "heap_debugging")]
+impl SlabAllocator {
 
-                    slab_is_fully_free = slab.is_fully_free();
+    /// Checks red zones of live objects and poison of freed objects that are not reused yet
+    pub fn verify(&self) -> Result<(), HeapCorruption> {
+        self.debugger.verify().map_err(|corruption| self.with_size_class(corruption))
+    }
 
-                    //self.address_to_size.delete(was_allocated.leak());
-                }
+    /// Returns every quarantined object to allocator, e.g. before statistics are compared
+    pub fn flush_quarantine(&mut self) {
+        loop {
+            match self.debugger.take_quarantined() {
+                Ok(Some(block)) => self.free_block(block.block),
+                Ok(None) => break,
+                Err(corruption) => panic!("Heap corruption: {}", self.with_size_class(corruption))
             }
+        }
+    }
 
-            if  slab_is_fully_free {
-                self.size_to_slab[slab_array_idx] = None;
-            }
-        }*/
+    // corruption is reported on the next allocation or free, heap can't be trusted afterwards
+    fn allocate_object(&mut self, size: usize, align : usize) -> Option<usize> {
+        if let Err(corruption) = self.verify() {
+            panic!("Heap corruption: {}", corruption);
+        }
+
+        if size == 0 {
+            return None
+        }
+
+        let red_zone = HeapDebugger::red_zone_size(align);
+        let block    = self.allocate_block(size + 2 * red_zone, align)?;
+
+        Some(self.debugger.guard(block, size, red_zone))
+    }
+
+    fn free_object(&mut self, pointer: usize) {
+        match self.debugger.release(pointer) {
+            Ok(Some(evicted)) => self.free_block(evicted.block),
+            Ok(None) => {},
+            Err(corruption) => panic!("Heap corruption: {}", self.with_size_class(corruption))
+        }
+    }
+
+    fn with_size_class(&self, corruption : HeapCorruption) -> HeapCorruption {
+        HeapCorruption { size_class : self.size_class_of(corruption.block), .. corruption }
+    }
+
+    fn size_class_of(&self, block : usize) -> usize {
+        if block < self.start_address() || block > self.end_address() {
+            return 0
+        }
+
+        match self.frame_to_slab[self.frame_number(block)] {
+            0 => self.frame_allocator.allocation_size(block),
+            OBJECT_CACHE_OWNER => 0,
+            owner => MIN_ALLOCATION_SIZE << (owner as usize - 1)
+        }
+    }
+}
+
+#[cfg(feature = "leak_tracking")]
+impl SlabAllocator {
+
+    /// Records site of allocation, site is kept until the block is freed.
+    /// Allocation is not recorded if too many allocations are tracked already
+    pub fn track_allocation(&mut self, pointer : usize, size : usize, site : AllocationSite) {
+        let free_slot = (0 .. self.live_allocations.length()).find(|&i| self.live_allocations[i].is_none());
+
+        if let Some(i) = free_slot {
+            self.live_allocations[i] = Some(LiveAllocation { pointer, size, site });
+        }
+    }
+
+    /// Returns recorded allocations that weren't freed yet
+    pub fn live_allocations<'a>(&'a self) -> impl Iterator<Item = LiveAllocation> + 'a {
+        (0 .. self.live_allocations.length()).filter_map(move |i| self.live_allocations[i])
+    }
+
+    /// Prints recorded allocations that weren't freed yet
+    pub fn report_leaks<W>(&self, writer : &mut W) -> fmt::Result where W : fmt::Write {
+        for allocation in self.live_allocations() {
+            writeln!(writer, "leaked {} B at {:#x}, allocated at {}", allocation.size, allocation.pointer, allocation.site)?;
+        }
+
+        Ok(())
+    }
+
+    fn tracked_allocation_index(&self, pointer : usize) -> Option<usize> {
+        (0 .. self.live_allocations.length())
+            .find(|&i| self.live_allocations[i].map_or(false, |a| a.pointer == pointer))
+    }
+
+    fn untrack_allocation(&mut self, pointer : usize) {
+        if let Some(i) = self.tracked_allocation_index(pointer) {
+            self.live_allocations[i] = None;
+        }
+    }
+
+    fn move_tracked_allocation(&mut self, pointer : usize, new_pointer : usize, new_size : usize) {
+        if let Some(i) = self.tracked_allocation_index(pointer) {
+            let site = self.live_allocations[i].unwrap().site;
+
+            self.live_allocations[i] = Some(LiveAllocation { pointer : new_pointer, size : new_size, site });
+        }
+    }
+}
+
+impl MemoryAllocator for SlabAllocator {
+    fn allocate(&mut self, size: usize) -> Option<usize> {
+        self.allocate_aligned(size, 1)
+    }
+
+    fn allocate_aligned(&mut self, size: usize, align : usize) -> Option<usize> {
+        self.allocate_object(size, align)
+    }
+
+    fn free(&mut self, pointer: usize) {
+        #[cfg(feature = "leak_tracking")]
+        self.untrack_allocation(pointer);
+
+        self.free_object(pointer)
     }
 }
 
 impl MemoryAllocatorMeta for SlabAllocator {
     fn start_address(&self) -> usize {
-        self.start_address
+        self.frame_allocator.start_address()
     }
 
     fn end_address(&self) -> usize {
-        self.end_address
+        self.frame_allocator.end_address()
     }
 
     fn aux_data_structures_size(&self) -> usize {
-        self.array_allocator.full_size() +
-            self.tree_allocator.full_size() +
-            self.linked_list_allocator.full_size() +
+        self.frame_to_slab_allocator.full_size() +
             self.frame_allocator.aux_data_structures_size()
     }
 }
 
 unsafe impl Alloc for SlabAllocator {
     unsafe fn alloc(&mut self, layout: Layout) -> Result<ptr::NonNull<u8>, AllocErr> {
-        self.allocate(layout.size())
+        self.allocate_aligned(layout.size(), layout.align())
             .map(|a| Ok(ptr::NonNull::new_unchecked(a as * mut u8)))
             .unwrap_or(Err(AllocErr))
     }
 
     unsafe fn dealloc(&mut self, ptr : ptr::NonNull<u8>, layout: Layout) {
-        self.free(*ptr.as_ref() as usize)
+        self.free(ptr.as_ptr() as usize)
+    }
+
+    unsafe fn realloc(&mut self, ptr : ptr::NonNull<u8>, layout: Layout, new_size: usize) -> Result<ptr::NonNull<u8>, AllocErr> {
+        self.reallocate(ptr.as_ptr() as usize, layout.size(), new_size, layout.align())
+            .map(|a| Ok(ptr::NonNull::new_unchecked(a as * mut u8)))
+            .unwrap_or(Err(AllocErr))
+    }
+}
+
+/// Dedicated slab cache of objects of a single type. Objects have exact size of the type
+/// (at least `MIN_ALLOCATION_SIZE`), so they don't waste memory of power of 2 size classes.
+/// Slab cells are made of frames of slab allocator frame allocator, objects must be freed to the cache they came from.
+pub struct ObjectCache<T> {
+    name               : &'static str,
+    object_size        : usize,
+    slab               : Option<Slab>,
+    slab_cache         : SlabCache,
+    cached_slabs_limit : usize,
+    constructor        : Option<fn() -> T>,
+    destructor         : Option<fn(&mut T)>,
+    statistics         : SizeClassStatistics,
+    phantom            : marker::PhantomData<T>,
+}
+
+impl<T> ObjectCache<T> {
+
+    /// Creates empty cache, frames are taken on the first allocation
+    /// # Arguments
+    /// * `name` - name of cache shown in statistics
+    /// * `constructor` - creates the value of every allocated object, objects are uninitialized without it
+    /// * `destructor` - is called for every freed object
+    pub fn new(name : &'static str, constructor : Option<fn() -> T>, destructor : Option<fn(&mut T)>) -> Self {
+        let object_size = address::align_up(cmp::max(mem::size_of::<T>(), MIN_ALLOCATION_SIZE), mem::align_of::<T>());
+
+        assert!(object_size <= FRAME_SIZE, "Object cache {} cannot hold objects bigger than a frame", name);
+
+        ObjectCache {
+            name,
+            object_size,
+            slab               : None,
+            slab_cache         : SlabCache::default(),
+            cached_slabs_limit : DEFAULT_CACHED_SLABS,
+            constructor,
+            destructor,
+            statistics         : SizeClassStatistics::default(),
+            phantom            : marker::PhantomData
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns size of memory used by every object
+    pub fn object_size(&self) -> usize {
+        self.object_size
+    }
+
+    pub fn statistics(&self) -> SizeClassStatistics {
+        SizeClassStatistics {
+            slabs        : self.slab.as_ref().map_or(0, |slab| slab.cells_count),
+            cached_slabs : self.slab_cache.count,
+            .. self.statistics
+        }
+    }
+
+    /// Allocates object, object is created by constructor if cache has one
+    pub fn allocate(&mut self, allocator : &mut SlabAllocator) -> Option<ptr::NonNull<T>> {
+        let result = self.allocate_object(allocator);
+
+        if let (Some(pointer), Some(constructor)) = (result, self.constructor) {
+            unsafe { ptr::write(pointer.as_ptr(), constructor()); }
+        }
+
+        result
+    }
+
+    /// Allocates object with provided value, constructor is not called
+    pub fn allocate_value(&mut self, value : T, allocator : &mut SlabAllocator) -> Option<ptr::NonNull<T>> {
+        let result = self.allocate_object(allocator);
+
+        if let Some(pointer) = result {
+            unsafe { ptr::write(pointer.as_ptr(), value); }
+        }
+
+        result
+    }
+
+    /// Frees object that was allocated by this cache, destructor is called before memory is freed
+    pub fn free(&mut self, pointer : ptr::NonNull<T>, allocator : &mut SlabAllocator) {
+        if let Some(destructor) = self.destructor {
+            unsafe { destructor(&mut *pointer.as_ptr()); }
+        }
+
+        let mut slab_is_fully_free = false;
+
+        if let Some(ref mut slab) = self.slab {
+            let mut frames = CellFrames {
+                frame_allocator : &mut allocator.frame_allocator,
+                cache           : &mut self.slab_cache,
+                cache_limit     : self.cached_slabs_limit
+            };
+
+            slab.free(pointer.as_ptr() as usize, &mut frames);
+
+            slab_is_fully_free = slab.is_fully_free();
+        }
+
+        self.statistics.freed();
+
+        if slab_is_fully_free {
+            self.slab = None;
+        }
+    }
+
+    /// Sets number of empty slab cells kept for reuse, cells above the limit are freed
+    pub fn set_cached_slabs_limit(&mut self, limit : usize, allocator : &mut SlabAllocator) {
+        assert!(limit <= MAX_CACHED_SLABS, "Cannot cache more than {} slabs", MAX_CACHED_SLABS);
+
+        self.cached_slabs_limit = limit;
+        self.slab_cache.release_above(limit, &mut allocator.frame_allocator);
+    }
+
+    /// Returns frames of cached empty slabs to frame allocator
+    /// # Returns
+    /// number of freed frames
+    pub fn shrink(&mut self, allocator : &mut SlabAllocator) -> usize {
+        self.slab_cache.release_above(0, &mut allocator.frame_allocator)
+    }
+
+    fn allocate_object(&mut self, allocator : &mut SlabAllocator) -> Option<ptr::NonNull<T>> {
+        let mut result = self.allocate_from_slab(allocator);
+
+        // memory pressure: cached slabs of slab allocator can free frames for a new cell
+        if result.is_none() && allocator.shrink() > 0 {
+            result = self.allocate_from_slab(allocator);
+        }
+
+        match result {
+            Some(pointer) => {
+                // frame of the cell may be freed by slab allocator before, so its owner is updated on every allocation
+                allocator.set_frame_owner0(pointer, OBJECT_CACHE_OWNER);
+                self.statistics.allocated()
+            },
+            None => self.statistics.failed()
+        }
+
+        result.map(|pointer| unsafe { ptr::NonNull::new_unchecked(pointer as *mut T) })
+    }
+
+    fn allocate_from_slab(&mut self, allocator : &mut SlabAllocator) -> Option<usize> {
+        let object_size = self.object_size;
+        let mut frames = CellFrames {
+            frame_allocator : &mut allocator.frame_allocator,
+            cache           : &mut self.slab_cache,
+            cache_limit     : self.cached_slabs_limit
+        };
+
+        // if slab cannot be created - then its oom
+        if self.slab.is_none() {
+            self.slab = Slab::new(object_size, &mut frames);
+        }
+
+        self.slab.as_mut().and_then(|slab| slab.allocate(object_size, object_size, &mut frames))
+    }
+}
+
+impl<T> fmt::Display for ObjectCache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let statistics = self.statistics();
+
+        write!(f, "{} ({} B): live {}, peak {}, slabs {}, cached {}, failed {}",
+               self.name, self.object_size, statistics.live_objects, statistics.peak_live_objects,
+               statistics.slabs, statistics.cached_slabs, statistics.failed_allocations)
     }
 }
 
@@ -583,24 +1112,89 @@ impl SlabHelp {
     pub fn is_fully_free(&self) -> bool {
         unsafe { self.value.as_ref().is_fully_free() }
     }
+
+    pub fn realloc_statistics(&self) -> ReallocStatistics {
+        unsafe { self.value.as_ref().realloc_statistics() }
+    }
+
+    pub fn statistics(&self) -> HeapStatistics {
+        unsafe { self.value.as_ref().statistics() }
+    }
+
+    /// Checks red zones of live objects and poison of freed objects
+    #[cfg(feature = "heap_debugging")]
+    pub fn verify(&self) -> Result<(), HeapCorruption> {
+        unsafe { self.value.as_ref().verify() }
+    }
+
+    /// Returns frames of cached empty slabs to frame allocator
+    pub fn shrink(&self) -> usize {
+        // escape immutable self
+        let mut  v = self.value.clone();
+
+        allocator::without_preemption(|| unsafe { v.as_mut().shrink() })
+    }
+
+    #[cfg(feature = "heap_debugging")]
+    pub fn flush_quarantine(&self) {
+        // escape immutable self
+        let mut  v = self.value.clone();
+
+        unsafe { v.as_mut().flush_quarantine() }
+    }
+
+    /// Prints allocations that weren't freed yet
+    #[cfg(feature = "leak_tracking")]
+    pub fn report_leaks<W>(&self, writer : &mut W) -> fmt::Result where W : fmt::Write {
+        unsafe { self.value.as_ref().report_leaks(writer) }
+    }
 }
 
+// heap is used by processes and by kernel thread, see `allocator::without_preemption`
 unsafe impl GlobalAlloc for SlabHelp {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         // escape immutable self
         let mut  v = self.value.clone();
-        let mut escape = v.as_mut();
 
-        escape.allocate(layout.size())
-            .map(|a| a as * mut u8)
-            .unwrap_or(0 as * mut u8)
+        allocator::without_preemption(|| {
+            let mut escape = v.as_mut();
+
+            let result = escape.allocate_aligned(layout.size(), layout.align());
+
+            #[cfg(feature = "leak_tracking")]
+            {
+                if let Some(pointer) = result {
+                    escape.track_allocation(pointer, layout.size(), AllocationSite::capture());
+                }
+            }
+
+            result
+                .map(|a| a as * mut u8)
+                .unwrap_or(0 as * mut u8)
+        })
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         // escape immutable self
         let mut  v = self.value.clone();
-        let mut escape = v.as_mut();
 
-        escape.free_with_size_hint(ptr as usize, layout.size())
+        allocator::without_preemption(|| {
+            let mut escape = v.as_mut();
+
+            escape.free(ptr as usize)
+        })
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // escape immutable self
+        let mut  v = self.value.clone();
+
+        allocator::without_preemption(|| {
+            let mut escape = v.as_mut();
+
+            escape.reallocate(ptr as usize, layout.size(), new_size, layout.align())
+                .map(|a| a as * mut u8)
+                .unwrap_or(0 as * mut u8)
+        })
     }
 }
\ No newline at end of file
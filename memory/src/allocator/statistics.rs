@@ -0,0 +1,203 @@
+use core::fmt;
+#[cfg(feature = "leak_tracking")]
+use hardware::x86_64::registers;
+
+/// Number of slab size classes, class `i` holds objects of `32 << i` bytes, bigger objects are blocks of frames
+pub const SIZE_CLASSES_COUNT : usize = 7;
+
+/// Max number of buddy levels reported by statistics, level `i` has blocks of `FRAME_SIZE << i` bytes
+pub const MAX_BUDDY_LEVELS : usize = 32;
+
+/// Counters of a single allocation size class
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeClassStatistics {
+    /// objects that are allocated and not freed yet
+    pub live_objects       : usize,
+    /// slab cells (pairs of frames) used by the class
+    pub slabs              : usize,
+    /// empty slab cells kept by the class for reuse
+    pub cached_slabs       : usize,
+    /// max number of live objects the class ever had
+    pub peak_live_objects  : usize,
+    pub failed_allocations : usize,
+}
+
+impl SizeClassStatistics {
+    pub(crate) fn allocated(&mut self) {
+        self.live_objects += 1;
+
+        if self.live_objects > self.peak_live_objects {
+            self.peak_live_objects = self.live_objects;
+        }
+    }
+
+    pub(crate) fn freed(&mut self) {
+        self.live_objects -= 1;
+    }
+
+    pub(crate) fn failed(&mut self) {
+        self.failed_allocations += 1;
+    }
+}
+
+/// Counts of reallocations performed by slab allocator
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReallocStatistics {
+    /// block kept its address, it either fit its size class or merged with free buddies
+    pub in_place : usize,
+    /// data was copied to a new block
+    pub moved    : usize,
+}
+
+/// Snapshot of heap state, two snapshots can be compared with `diff`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStatistics {
+    pub size_classes : [SizeClassStatistics; SIZE_CLASSES_COUNT],
+    /// blocks of frames that were allocated directly from buddy allocator, they don't use slabs
+    pub frame_blocks : SizeClassStatistics,
+    /// free blocks of every buddy level
+    pub free_blocks  : [usize; MAX_BUDDY_LEVELS],
+    pub realloc      : ReallocStatistics,
+}
+
+impl HeapStatistics {
+
+    /// Returns number of live objects of every size class, including blocks of frames
+    pub fn live_objects(&self) -> usize {
+        self.size_classes.iter().map(|c| c.live_objects).sum::<usize>() + self.frame_blocks.live_objects
+    }
+
+    /// Returns changes that happened since `earlier` snapshot was taken
+    pub fn diff(&self, earlier : &HeapStatistics) -> HeapStatisticsDiff {
+        let mut result = HeapStatisticsDiff::default();
+
+        for i in 0 .. SIZE_CLASSES_COUNT {
+            result.live_objects[i] = delta(self.size_classes[i].live_objects, earlier.size_classes[i].live_objects);
+            result.slabs[i]        = delta(self.size_classes[i].slabs, earlier.size_classes[i].slabs);
+            result.failed_allocations += self.size_classes[i].failed_allocations - earlier.size_classes[i].failed_allocations;
+        }
+
+        for i in 0 .. MAX_BUDDY_LEVELS {
+            result.free_blocks[i] = delta(self.free_blocks[i], earlier.free_blocks[i]);
+        }
+
+        result.frame_blocks = delta(self.frame_blocks.live_objects, earlier.frame_blocks.live_objects);
+        result.failed_allocations += self.frame_blocks.failed_allocations - earlier.frame_blocks.failed_allocations;
+
+        result
+    }
+}
+
+impl fmt::Display for HeapStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, class) in self.size_classes.iter().enumerate().filter(|&(_, c)| *c != SizeClassStatistics::default()) {
+            writeln!(f, "{} B: live {}, peak {}, slabs {}, cached {}, failed {}",
+                     32 << i, class.live_objects, class.peak_live_objects, class.slabs, class.cached_slabs, class.failed_allocations)?;
+        }
+
+        writeln!(f, "frame blocks: live {}, peak {}, failed {}",
+                 self.frame_blocks.live_objects, self.frame_blocks.peak_live_objects, self.frame_blocks.failed_allocations)?;
+
+        write!(f, "realloc: in place {}, moved {}", self.realloc.in_place, self.realloc.moved)
+    }
+}
+
+/// Difference between two heap snapshots
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStatisticsDiff {
+    pub live_objects       : [isize; SIZE_CLASSES_COUNT],
+    pub slabs              : [isize; SIZE_CLASSES_COUNT],
+    pub frame_blocks       : isize,
+    pub free_blocks        : [isize; MAX_BUDDY_LEVELS],
+    /// allocations that failed between snapshots
+    pub failed_allocations : usize,
+}
+
+impl HeapStatisticsDiff {
+
+    /// Determines if heap has the same objects and free memory as it had before
+    pub fn is_empty(&self) -> bool {
+        self.live_objects.iter().all(|&d| d == 0) &&
+            self.slabs.iter().all(|&d| d == 0) &&
+            self.free_blocks.iter().all(|&d| d == 0) &&
+            self.frame_blocks == 0
+    }
+}
+
+fn delta(current : usize, earlier : usize) -> isize {
+    current as isize - earlier as isize
+}
+
+/// Number of return addresses recorded for every allocation
+#[cfg(feature = "leak_tracking")]
+pub const ALLOCATION_SITE_DEPTH : usize = 4;
+
+/// Max number of live allocations whose sites are recorded
+#[cfg(feature = "leak_tracking")]
+pub const MAX_TRACKED_ALLOCATIONS : usize = 1024;
+
+/// Return addresses of the code that requested allocation, innermost first
+#[cfg(feature = "leak_tracking")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocationSite {
+    pub return_addresses : [usize; ALLOCATION_SITE_DEPTH]
+}
+
+#[cfg(feature = "leak_tracking")]
+impl AllocationSite {
+
+    /// Captures return addresses by walking the chain of saved frame pointers.
+    /// # Warning:
+    /// Code must be compiled with frame pointers (`-C force-frame-pointers=yes`), otherwise
+    /// walking the chain reads arbitrary memory
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut site          = AllocationSite::default();
+        let mut frame_pointer = registers::rbp_read() as usize;
+
+        for i in 0 .. ALLOCATION_SITE_DEPTH {
+            if frame_pointer == 0 || frame_pointer % 8 != 0 {
+                break;
+            }
+
+            let (previous_frame_pointer, return_address) = unsafe {
+                (*(frame_pointer as *const usize), *((frame_pointer + 8) as *const usize))
+            };
+
+            site.return_addresses[i] = return_address;
+
+            // callers' frames are always above on the stack, anything else is the end of the chain
+            if previous_frame_pointer <= frame_pointer {
+                break;
+            }
+
+            frame_pointer = previous_frame_pointer;
+        }
+
+        site
+    }
+}
+
+#[cfg(feature = "leak_tracking")]
+impl fmt::Display for AllocationSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, address) in self.return_addresses.iter().take_while(|&&a| a != 0).enumerate() {
+            if i > 0 {
+                write!(f, " <- ")?;
+            }
+
+            write!(f, "{:#x}", address)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Allocation that wasn't freed yet
+#[cfg(feature = "leak_tracking")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LiveAllocation {
+    pub pointer : usize,
+    pub size    : usize,
+    pub site    : AllocationSite,
+}
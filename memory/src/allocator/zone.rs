@@ -0,0 +1,205 @@
+use core::fmt;
+use address::{PhysAddr, PhysAddrRange};
+use allocator::buddy::BuddyAllocator;
+use frame::{PhysFrame, FrameSource, FRAME_SIZE};
+use frame::regions::MemoryRegions;
+
+/// Number of physical memory zones
+pub const ZONES_COUNT : usize = 3;
+
+// first addresses of DMA32 and Normal zones
+const DMA32_START_ADDRESS  : usize = 16 * 1024 * 1024;
+const NORMAL_START_ADDRESS : usize = 4 * 1024 * 1024 * 1024;
+
+/// Range of physical memory that satisfies device address constraints
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum Zone {
+    /// Memory below 16 MiB, reachable by ISA DMA
+    Dma,
+    /// Memory from 16 MiB up to 4 GiB, reachable by devices with 32 bit addresses
+    Dma32,
+    /// Memory above 4 GiB
+    Normal
+}
+
+impl Zone {
+
+    /// Zones sorted by start address
+    pub const ALL : [Zone; ZONES_COUNT] = [Zone::Dma, Zone::Dma32, Zone::Normal];
+
+    /// Returns zone that contains physical address
+    pub fn containing_address(address : PhysAddr) -> Zone {
+        let address = address.as_usize();
+
+        if address < DMA32_START_ADDRESS {
+            Zone::Dma
+        }
+        else if address < NORMAL_START_ADDRESS {
+            Zone::Dma32
+        }
+        else {
+            Zone::Normal
+        }
+    }
+
+    pub fn start_address(&self) -> PhysAddr {
+        match *self {
+            Zone::Dma    => PhysAddr::zero(),
+            Zone::Dma32  => PhysAddr::new(DMA32_START_ADDRESS),
+            Zone::Normal => PhysAddr::new(NORMAL_START_ADDRESS),
+        }
+    }
+
+    /// Returns last address of the zone (inclusive)
+    pub fn end_address(&self) -> PhysAddr {
+        match *self {
+            Zone::Dma    => PhysAddr::new(DMA32_START_ADDRESS - 1),
+            Zone::Dma32  => PhysAddr::new(NORMAL_START_ADDRESS - 1),
+            Zone::Normal => PhysAddr::max(),
+        }
+    }
+
+    /// Returns zones that can satisfy allocation from this zone, in order of preference.
+    /// Allocation falls back only to lower zones, their memory meets every constraint of higher zones.
+    pub fn fallback(&self) -> &'static [Zone] {
+        match *self {
+            Zone::Dma    => &[Zone::Dma],
+            Zone::Dma32  => &[Zone::Dma32, Zone::Dma],
+            Zone::Normal => &[Zone::Normal, Zone::Dma32, Zone::Dma],
+        }
+    }
+
+    /// Returns part of memory regions that belongs to the zone
+    /// # Arguments
+    /// * `regions` - physical memory, e.g. regions of multiboot memory map
+    pub fn regions(&self, regions : &MemoryRegions) -> MemoryRegions {
+        let mut result = MemoryRegions::new();
+
+        for region in regions.iter() {
+            result.add(region.start_address(), region.size());
+        }
+
+        // memory outside of the zone is cut from both ends, so no region is ever split
+        if *self != Zone::Dma {
+            result.reserve(PhysAddrRange::new(PhysAddr::zero(), self.start_address() - 1));
+        }
+        if *self != Zone::Normal {
+            result.reserve(PhysAddrRange::new(self.end_address() + 1, PhysAddr::max()));
+        }
+
+        result
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Zone::Dma    => write!(f, "DMA"),
+            Zone::Dma32  => write!(f, "DMA32"),
+            Zone::Normal => write!(f, "Normal"),
+        }
+    }
+}
+
+/// Allocator of physically contiguous memory with address constraints, e.g. buffers of DMA capable devices.
+/// Every zone is managed by its own buddy allocator, zone without memory has no allocator.
+pub struct ZonedFrameAllocator {
+    zones : [Option<BuddyAllocator>; ZONES_COUNT]
+}
+
+impl ZonedFrameAllocator {
+
+    /// Returns size of memory required by aux data structures of all zones.
+    /// Buddy allocator indexes memory from address 0, so aux data structures of higher zones
+    /// also cover memory of lower zones.
+    /// # Arguments
+    /// * `regions` - physical memory that will be managed by allocator
+    pub fn aux_data_structures_size_for(regions : &MemoryRegions) -> usize {
+        Zone::ALL.iter()
+            .map(|zone| zone.regions(regions))
+            .filter(|zone_regions| !zone_regions.is_empty())
+            .map(|zone_regions| PhysFrame::address_align_up(BuddyAllocator::aux_data_structures_size_for(&zone_regions)))
+            .sum()
+    }
+
+    /// Creates allocator of provided physical memory regions, regions are split between zones
+    /// # Arguments
+    /// * `regions` - physical memory to manage, must not contain aux data structures memory
+    /// * `aux_start_address` - start of zeroed memory of size `aux_data_structures_size_for(regions)`
+    /// * `address_offset` - offset added to physical addresses by buddy allocators, e.g. `address::PHYSICAL_MEMORY_OFFSET`
+    pub fn from_regions(regions : &MemoryRegions, aux_start_address : usize, address_offset : usize) -> Self {
+        let mut zones                 = [None, None, None];
+        let mut zone_aux_start_address = aux_start_address;
+
+        for zone in Zone::ALL.iter() {
+            let zone_regions = zone.regions(regions);
+
+            if !zone_regions.is_empty() {
+                zones[zone.index()] = Some(BuddyAllocator::from_regions(&zone_regions, zone_aux_start_address, address_offset));
+
+                // keep aux data structures of every zone frame aligned
+                zone_aux_start_address += PhysFrame::address_align_up(BuddyAllocator::aux_data_structures_size_for(&zone_regions));
+            }
+        }
+
+        ZonedFrameAllocator {
+            zones
+        }
+    }
+
+    /// Allocates physically contiguous memory from the zone or from its fallback zones
+    /// # Arguments
+    /// * `size` - size in bytes, rounded up to power of 2 number of frames
+    /// * `zone` - highest zone that satisfies address constraint of the caller
+    /// * `align` - alignment of the physical address in bytes, must be a power of 2
+    /// # Returns
+    /// first frame of the block
+    pub fn allocate_contiguous(&mut self, size : usize, zone : Zone, align : usize) -> Option<PhysFrame> {
+        let count = PhysFrame::address_align_up(size) / FRAME_SIZE;
+        let align = if align > FRAME_SIZE { align / FRAME_SIZE } else { 1 };
+
+        if count == 0 {
+            return None
+        }
+
+        for fallback_zone in zone.fallback() {
+            let result = self.zones[fallback_zone.index()]
+                .as_mut()
+                .and_then(|allocator| allocator.allocate_contiguous(count, align));
+
+            if result.is_some() {
+                return result
+            }
+        }
+
+        None
+    }
+
+    /// Returns block of contiguous memory to the zone it was allocated from
+    /// # Arguments
+    /// * `frame` - first frame of the block
+    pub fn deallocate(&mut self, frame : PhysFrame) {
+        let zone = Zone::containing_address(frame.start_address());
+
+        self.zones[zone.index()]
+            .as_mut()
+            .expect("Frame doesn't belong to any zone with memory")
+            .deallocate_frame(frame)
+    }
+
+    /// Determines if memory map has memory in the zone
+    pub fn has_zone(&self, zone : Zone) -> bool {
+        self.zones[zone.index()].is_some()
+    }
+
+    /// Returns number of free frames of the zone, frames of fallback zones are not counted
+    pub fn free_frames_count(&self, zone : Zone) -> usize {
+        self.zones[zone.index()]
+            .as_ref()
+            .map_or(0, |allocator| allocator.free_frames_count())
+    }
+}
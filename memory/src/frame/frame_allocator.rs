@@ -1,14 +1,18 @@
 use multiboot::multiboot_header::MultibootHeader;
 use multiboot::multiboot_header::tags::memory_map::*;
 use multiboot::multiboot_header::tags::elf;
-use frame::Frame;
+use frame::{PhysFrame, FrameSource};
 use frame::FRAME_SIZE;
+use frame::regions::MemoryRegions;
+use frame::handover::MemoryHandover;
+use address::{PhysAddr, PhysAddrRange, to_physical};
 use stdx_memory::collections::linked_list::LinkedList;
 use allocator::bump::{BumpAllocator, ConstSizeBumpAllocator};
 use stdx_memory::MemoryAllocator;
 use stdx_memory::ConstantSizeMemoryAllocator;
 use stdx_memory::MemoryAllocatorMeta;
 use stdx_memory::heap;
+use core::cmp;
 use core::fmt;
 use core::mem;
 use core::ptr;
@@ -23,59 +27,38 @@ use core::ptr;
     create a big free frame stack that describes all available memory and use only it.  
 */
 
-pub struct FreeListFrameAllocator {
-    multiboot_start_frame: Frame,
-    multiboot_end_frame: Frame,
-    kernel_start_frame: Frame,
-    kernel_end_frame: Frame,
-    current_memory_area : ptr::NonNull<MemoryMapEntry>,
-    memory_areas: AvailableMemorySectionsIterator,
-    last_frame_number: Frame,
-    empty_frame_list: heap::WeakBox<LinkedList<Frame>>,
-    frame_list_allocator : ConstSizeBumpAllocator,
-    buddy_allocator_start_frame : Frame,
-    buddy_allocator_end_frame : Frame
-}
-
-pub trait FrameAllocatorFake {
-    fn bump_allocator(&self) -> ConstSizeBumpAllocator;
-}
-
 pub struct FrameAllocator {
-    multiboot_start_frame: Frame,
-    multiboot_end_frame: Frame,
-    kernel_start_frame: Frame,
-    kernel_end_frame: Frame,
+    multiboot_start_frame: PhysFrame,
+    multiboot_end_frame: PhysFrame,
+    kernel_start_frame: PhysFrame,
+    kernel_end_frame: PhysFrame,
     current_memory_area : ptr::NonNull<MemoryMapEntry>,
     memory_areas: AvailableMemorySectionsIterator,
-    last_frame_number: Frame,
-    empty_frame_list: heap::WeakBox<LinkedList<Frame>>,
+    last_frame_number: PhysFrame,
+    empty_frame_list: heap::WeakBox<LinkedList<PhysFrame>>,
+    free_list_length : usize,
+    // frames handed out by bump allocation, adjacent frames are merged into one region
+    allocated_frames : MemoryRegions,
     frame_list_allocator : ConstSizeBumpAllocator,
-    buddy_allocator_start_frame : Frame,
-    buddy_allocator_end_frame : Frame
-}
-
-impl FrameAllocatorFake for FrameAllocator {
-    fn bump_allocator(&self) -> ConstSizeBumpAllocator {
-        self.frame_list_allocator.clone()
-    }
+    buddy_allocator_start_frame : PhysFrame,
+    buddy_allocator_end_frame : PhysFrame
 }
 
 impl FrameAllocator {
 
-    pub fn multiboot_start_frame(&self) -> Frame {
+    pub fn multiboot_start_frame(&self) -> PhysFrame {
         self.multiboot_start_frame
     }
 
-    pub fn multiboot_end_frame(&self) -> Frame {
+    pub fn multiboot_end_frame(&self) -> PhysFrame {
         self.multiboot_end_frame
     }
 
-    pub fn kernel_start_frame(&self) -> Frame {
+    pub fn kernel_start_frame(&self) -> PhysFrame {
         self.kernel_start_frame
     }
 
-    pub fn kernel_end_frame(&self) -> Frame {
+    pub fn kernel_end_frame(&self) -> PhysFrame {
         self.kernel_end_frame
     }
 
@@ -89,7 +72,7 @@ impl FrameAllocator {
         self.memory_areas.clone()
     }
 
-    pub fn last_frame_number(&self) -> Frame {
+    pub fn last_frame_number(&self) -> PhysFrame {
         self.last_frame_number
     }
 
@@ -97,15 +80,92 @@ impl FrameAllocator {
         self.frame_list_allocator.end_address()
     }
         
-    pub fn set_buddy_start(&mut self, f : Frame){
+    pub fn set_buddy_start(&mut self, f : PhysFrame){
         self.buddy_allocator_start_frame = f
     }
 
-    pub fn set_buddy_end(&mut self, f : Frame){
+    pub fn set_buddy_end(&mut self, f : PhysFrame){
         self.buddy_allocator_end_frame = f
     }
 
-    fn empty_frame_list(&self) -> &heap::WeakBox<LinkedList<Frame>> {
+    // removes memory that can't be bump allocated from provided regions:
+    // multiboot information, kernel code, frame list and every frame allocated or skipped so far
+    fn reserve_used_memory(&self, regions : &mut MemoryRegions) {
+        let frame_list_start = PhysAddr::new(to_physical(self.frame_list_allocator.start_address()));
+        let frame_list_end = PhysAddr::new(to_physical(self.frame_list_allocator.end_address()));
+
+        let reserved = [
+            PhysAddrRange::new(self.multiboot_start_frame.start_address(), self.multiboot_end_frame.end_address()),
+            PhysAddrRange::new(self.kernel_start_frame.start_address(), self.kernel_end_frame.end_address()),
+            PhysAddrRange::new(frame_list_start, frame_list_end)
+        ];
+
+        for range in reserved.iter() {
+            assert!(regions.reserve(*range), "Too many memory regions to reserve {}", range);
+        }
+
+        // bump allocation hands out frames in ascending order, everything below the next frame is either allocated or skipped
+        if self.last_frame_number.number() > 0 {
+            assert!(regions.reserve(PhysAddrRange::new(PhysAddr::zero(), self.last_frame_number.start_address() - 1)), "Too many memory regions to reserve allocated frames");
+        }
+    }
+
+    /// Ends boot allocation and returns memory that runtime allocators can use: available memory
+    /// without kernel, multiboot information and frames that were allocated. Frames of free frame list
+    /// and boot page table are returned as free, frame list itself isn't needed after handover.
+    /// Multiboot information stays reserved until `MemoryHandover::reclaim_multiboot` is called.
+    /// # Arguments
+    /// * `boot_page_table` - P4 table of boot code that was replaced by `paging::remap_kernel`
+    pub fn handover(mut self, boot_page_table : PhysFrame) -> MemoryHandover {
+        let mut free = MemoryRegions::new();
+        let mut multiboot = MemoryRegions::new();
+
+        let multiboot_start = self.multiboot_start_frame.start_address().as_usize();
+        let multiboot_end = self.multiboot_end_frame.end_address().as_usize();
+
+        for area in self.memory_areas.clone() {
+            let area_start = area.base_address() as usize;
+            let area_end = area.end_address() as usize;
+
+            assert!(free.add(PhysAddr::new(area_start), area.length() as usize), "Too many memory areas");
+
+            // multiboot information can be reclaimed only where it is inside available memory
+            let (start, end) = (cmp::max(area_start, multiboot_start), cmp::min(area_end, multiboot_end));
+
+            if start < end {
+                assert!(multiboot.add(PhysAddr::new(start), end - start + 1), "Too many multiboot regions");
+            }
+        }
+
+        let kernel = PhysAddrRange::new(self.kernel_start_frame.start_address(), self.kernel_end_frame.end_address());
+
+        // multiboot information may share frames with the kernel
+        assert!(multiboot.reserve(kernel), "Too many multiboot regions");
+
+        let reserved = [kernel, PhysAddrRange::new(PhysAddr::new(multiboot_start), PhysAddr::new(multiboot_end))];
+
+        for range in reserved.iter() {
+            assert!(free.reserve(*range), "Too many memory regions to reserve {}", range);
+        }
+
+        for region in self.allocated_frames.iter() {
+            assert!(free.reserve(region.range()), "Too many memory regions to reserve {}", region);
+        }
+
+        // boot page table is replaced by kernel page table, nothing points to it after remap
+        assert!(free.add(boot_page_table.start_address(), FRAME_SIZE), "Too many memory regions");
+
+        // freed frames that don't fit into regions stay reserved
+        while self.free_list_length > 0 {
+            let frame = self.allocate().unwrap();
+
+            free.add(frame.start_address(), FRAME_SIZE);
+        }
+
+        MemoryHandover::new(free, multiboot)
+    }
+
+    fn empty_frame_list(&self) -> &heap::WeakBox<LinkedList<PhysFrame>> {
         &self.empty_frame_list
     }
 
@@ -133,22 +193,24 @@ impl FrameAllocator {
         let kernel_start_address = kernel_start_section.start_address() as usize;
         let kernel_end_address = kernel_end_section.end_address() as usize;        
             
-        let first_memory_area = FrameAllocator::next_fitting_memory_area(memory_areas.entries(), Frame::from_address(0)).expect("Cannot determine first memory area");            
+        let first_memory_area = FrameAllocator::next_fitting_memory_area(memory_areas.entries(), PhysFrame::containing_address(PhysAddr::zero())).expect("Cannot determine first memory area");            
         let last_frame_number = FrameAllocator::frame_for_base_address(first_memory_area.base_address() as usize);        
         let mut bump_allocator = bump_allocator1;
 
         FrameAllocator {
-            multiboot_start_frame: Frame::from_address(multiboot_header.start_address()),
-            multiboot_end_frame: Frame::from_address(multiboot_header.end_address()),
-            kernel_start_frame: Frame::from_address(kernel_start_address),
-            kernel_end_frame: Frame::from_address(kernel_end_address),
+            multiboot_start_frame: PhysFrame::containing_address(PhysAddr::new(multiboot_header.start_address())),
+            multiboot_end_frame: PhysFrame::containing_address(PhysAddr::new(multiboot_header.end_address())),
+            kernel_start_frame: PhysFrame::containing_address(PhysAddr::new(kernel_start_address)),
+            kernel_end_frame: PhysFrame::containing_address(PhysAddr::new(kernel_end_address)),
             current_memory_area : ptr::NonNull::from(first_memory_area),
             memory_areas: memory_areas.entries(),
             last_frame_number: last_frame_number,
             empty_frame_list: heap::WeakBox::new(LinkedList::Nil, &mut bump_allocator),
+            free_list_length : 0,
+            allocated_frames : MemoryRegions::new(),
             frame_list_allocator : bump_allocator,
-            buddy_allocator_start_frame : Frame::from_address(0),
-            buddy_allocator_end_frame : Frame::from_address(0)
+            buddy_allocator_start_frame : PhysFrame::containing_address(PhysAddr::zero()),
+            buddy_allocator_end_frame : PhysFrame::containing_address(PhysAddr::zero())
         }
     }
 
@@ -162,29 +224,33 @@ impl FrameAllocator {
         assert!(elf_sections.entries().count() != 0, "No elf sections, cannot determine kernel code address");
         assert!(memory_areas.entries().count() != 0, "No available memory areas for frame allocator");
         
-        let kernel_start_address = elf_sections.entries_start_address().unwrap() as usize;
-        let kernel_end_address = elf_sections.entries_end_address().unwrap() as usize;        
+        // kernel is linked to the higher half, but frame allocator works with physical addresses
+        let kernel_start_address = elf_sections.entries().map(|e| to_physical(e.start_address() as usize)).min().unwrap();
+        let kernel_end_address = elf_sections.entries().map(|e| to_physical(e.end_address() as usize)).max().unwrap();
             
-        let first_memory_area = FrameAllocator::next_fitting_memory_area(memory_areas.entries(), Frame::from_address(0)).expect("Cannot determine first memory area");            
+        let first_memory_area = FrameAllocator::next_fitting_memory_area(memory_areas.entries(), PhysFrame::containing_address(PhysAddr::zero())).expect("Cannot determine first memory area");            
         let last_frame_number = FrameAllocator::frame_for_base_address(first_memory_area.base_address() as usize);        
 
         let empty_frame_list_size = FrameAllocator::get_empty_frame_list_size(&memory_areas);
-        let kernel_end_frame = Frame::from_address(kernel_end_address);        
+        let kernel_end_frame = PhysFrame::containing_address(PhysAddr::new(kernel_end_address));        
         // move it to some proper place!
-        let mut bump_allocator = ConstSizeBumpAllocator::from_address_for_type::<LinkedList<Frame>>(multiboot_header.end_address() + 1, empty_frame_list_size);
+        // multiboot header is accessed through physical memory window, so is the frame list
+        let mut bump_allocator = ConstSizeBumpAllocator::from_address_for_type::<LinkedList<PhysFrame>>(multiboot_header.end_address() + 1, empty_frame_list_size);
 
         FrameAllocator {
-            multiboot_start_frame: Frame::from_address(multiboot_header.start_address()),
-            multiboot_end_frame: Frame::from_address(multiboot_header.end_address()),
-            kernel_start_frame: Frame::from_address(kernel_start_address),
+            multiboot_start_frame: PhysFrame::containing_address(PhysAddr::new(to_physical(multiboot_header.start_address()))),
+            multiboot_end_frame: PhysFrame::containing_address(PhysAddr::new(to_physical(multiboot_header.end_address()))),
+            kernel_start_frame: PhysFrame::containing_address(PhysAddr::new(kernel_start_address)),
             kernel_end_frame: kernel_end_frame,
             current_memory_area : ptr::NonNull::from(first_memory_area),
             memory_areas: memory_areas.entries(),
             last_frame_number: last_frame_number,
             empty_frame_list: heap::WeakBox::new(LinkedList::Nil, &mut bump_allocator),
+            free_list_length : 0,
+            allocated_frames : MemoryRegions::new(),
             frame_list_allocator : bump_allocator,
-            buddy_allocator_start_frame : Frame::from_address(0),
-            buddy_allocator_end_frame : Frame::from_address(0)
+            buddy_allocator_start_frame : PhysFrame::containing_address(PhysAddr::zero()),
+            buddy_allocator_end_frame : PhysFrame::containing_address(PhysAddr::zero())
         }
     }
     
@@ -192,46 +258,52 @@ impl FrameAllocator {
         let available_memory = memory_map.available_memory() as usize;
         let total_frames_count = available_memory / FRAME_SIZE;
 
-        total_frames_count * mem::size_of::<LinkedList<Frame>>()
+        total_frames_count * mem::size_of::<LinkedList<PhysFrame>>()
     }
 
-    pub fn allocate(&mut self) -> Option<Frame> {
+    pub fn allocate(&mut self) -> Option<PhysFrame> {
                         
         // check empty frame list first, if nothing perform bump allocation
         match self.empty_frame_list.take() {
             Some((value, prev)) => {
-                
-                // pick first result from empty frame list                
-                self.empty_frame_list = prev;                
-                
+                // cells are allocated and freed in stack order, so the head is always the last allocated cell
+                let head_address = &*self.empty_frame_list as *const LinkedList<PhysFrame> as usize;
+
+                self.frame_list_allocator.free_size(head_address);
+
+                // pick first result from empty frame list
+                self.empty_frame_list = prev;
+                self.free_list_length -= 1;
+
                 Some(value)
             },
-            _ => {
-                match self.bump_allocate() {
-                    Some(allocate_result) => {                    
-                        self.last_frame_number = allocate_result.next(); // next possible frame for bump allocator
-                        //self.frame_bit_map.set_in_use(allocate_result.number());
-
-                        Some(allocate_result)
-                    },
-                    // if we can't allocate with bump and there is nothing in free frame stack
-                    // then we are out of memory
-                    None => None
-                }
-            }
+            // if we can't allocate with bump and there is nothing in free frame stack
+            // then we are out of memory
+            _ => self.bump_allocate_next()
         }
     }
 
+    // bump allocates frame and records it, recorded frames stay reserved after handover
+    fn bump_allocate_next(&mut self) -> Option<PhysFrame> {
+        let frame = self.bump_allocate()?;
+
+        self.last_frame_number = frame.next(); // next possible frame for bump allocator
+
+        assert!(self.allocated_frames.add(frame.start_address(), FRAME_SIZE), "Too many discontiguous boot allocations");
+
+        Some(frame)
+    }
+
     /*
         tries to return self.last_frame_number first, if it fails tries to return self.last_frame_number.next
         Changes self.current_memory_area when moving to new memory area.
     */
-    fn bump_allocate(&mut self) -> Option<Frame> {
+    fn bump_allocate(&mut self) -> Option<PhysFrame> {
         unsafe {
         let possible_frame = self.last_frame_number;
         let result = self.step_over_reserved_memory_if_needed(possible_frame);        
 
-        if result.end_address() > self.current_memory_area.as_ref().end_address() as usize {  
+        if result.end_address().as_usize() > self.current_memory_area.as_ref().end_address() as usize {  
             if let Some(memory_area) = FrameAllocator::next_fitting_memory_area(self.memory_areas.clone(), result) {
                 self.current_memory_area = ptr::NonNull::from(memory_area);
 
@@ -251,10 +323,10 @@ impl FrameAllocator {
     // and frame size iz 4000, thus creating frame from address 1000 will result in frame
     // with number = 0, which has base address = 0 also, which is below memory area and will
     // result in memory read fault
-    fn frame_for_base_address(base_address : usize) -> Frame {
-        let first_attempt_frame = Frame::from_address(base_address);
+    fn frame_for_base_address(base_address : usize) -> PhysFrame {
+        let first_attempt_frame = PhysFrame::containing_address(PhysAddr::new(base_address));
 
-        if Frame::is_frame_aligned(base_address) {
+        if PhysFrame::is_frame_aligned(base_address) {
             first_attempt_frame
         }
         else {
@@ -262,7 +334,7 @@ impl FrameAllocator {
         }
     }
 
-    fn step_over_reserved_memory_if_needed(&self, frame : Frame) -> Frame {
+    fn step_over_reserved_memory_if_needed(&self, frame : PhysFrame) -> PhysFrame {
         // dont touch multiboot data
         if frame >= self.multiboot_start_frame &&
            frame <= self.multiboot_end_frame {
@@ -274,9 +346,9 @@ impl FrameAllocator {
             self.step_over_reserved_memory_if_needed(self.kernel_end_frame.next()) // in case next will touch empty frame list
         }
         // dont touch empty frame list
-        else if frame >= Frame::from_address(self.frame_list_allocator.start_address()) &&
-                frame <= Frame::from_address(self.frame_list_allocator.end_address()) {
-            let possible_frame = Frame::from_address(self.frame_list_allocator.end_address()).next();
+        else if frame >= PhysFrame::containing_address(PhysAddr::new(to_physical(self.frame_list_allocator.start_address()))) &&
+                frame <= PhysFrame::containing_address(PhysAddr::new(to_physical(self.frame_list_allocator.end_address()))) {
+            let possible_frame = PhysFrame::containing_address(PhysAddr::new(to_physical(self.frame_list_allocator.end_address()))).next();
             self.step_over_reserved_memory_if_needed(possible_frame) // in case next() will touch heap data structure
         }
         // don't touch heap
@@ -294,26 +366,32 @@ impl FrameAllocator {
     /*
         Try to find memory area with lowest base addr and which can hold provided frame
     */
-    fn next_fitting_memory_area(memory_areas : AvailableMemorySectionsIterator, last_frame_number : Frame) -> Option<&'static MemoryMapEntry> {        
+    fn next_fitting_memory_area(memory_areas : AvailableMemorySectionsIterator, last_frame_number : PhysFrame) -> Option<&'static MemoryMapEntry> {        
         memory_areas
             .clone()       
             .filter(|e| {
                 let frame = FrameAllocator::frame_for_base_address(e.base_address() as usize);
                 // frame must be fully inside memory area
-                frame.end_address() <= e.end_address() as usize && frame >= last_frame_number
+                frame.end_address().as_usize() <= e.end_address() as usize && frame >= last_frame_number
              })
             .min_by_key(|e| e.base_address())
     }
 
-    pub fn deallocate(&mut self, frame : Frame) {   
-        /*     
-        let new_list = self.empty_frame_list.map_or(
-            FreeList::new(frame, &mut self.frame_list_allocator),
-            |e| e.pointer().add(frame, &mut self.frame_list_allocator)          
-        );
+    /// Puts frame into free frame list, it is picked by the next allocation
+    pub fn deallocate(&mut self, frame : PhysFrame) {
+        let prev = heap::WeakBox::from_pointer(&*self.empty_frame_list);
 
-        self.empty_frame_list = Some(new_list);
-        */
+        self.empty_frame_list = heap::WeakBox::new(LinkedList::Cell { value : frame, prev }, &mut self.frame_list_allocator);
+        self.free_list_length += 1;
+    }
+
+    // returns frames of partially allocated block
+    fn deallocate_block(&mut self, first_frame : Option<PhysFrame>, block_size : usize) {
+        if let Some(first_frame) = first_frame {
+            for number in first_frame.number() .. first_frame.number() + block_size {
+                self.deallocate(PhysFrame { number });
+            }
+        }
     }
 }
 
@@ -332,15 +410,70 @@ impl MemoryAllocatorMeta for FrameAllocator {
     }
 }
 
-impl ConstantSizeMemoryAllocator for FrameAllocator {
-    fn allocate_size(&mut self) -> Option<usize> {
-        self.allocate().map(|e| e.address())
+impl FrameSource for FrameAllocator {
+
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate()
     }
 
-    fn free_size(&mut self, pointer : usize) {
-        self.deallocate(Frame::from_address(pointer))
+    /// Block is bump allocated, frames that are skipped to satisfy alignment are put into free frame list.
+    /// Frame list holds single frames, so only the first frame of a block can be returned.
+    fn allocate_contiguous(&mut self, count : usize, align : usize) -> Option<PhysFrame> {
+        assert!(count > 0, "Block must contain at least one frame");
+        assert!(align.is_power_of_two(), "Block alignment {} is not a power of 2", align);
+
+        let mut first_frame = None;
+        let mut block_size = 0;
+
+        while block_size < count {
+            let frame = match self.bump_allocate_next() {
+                Some(frame) => frame,
+                None => {
+                    self.deallocate_block(first_frame, block_size);
+                    return None
+                }
+            };
+
+            // reserved memory or the end of memory area breaks the block
+            let continues_block = first_frame.map_or(false, |f : PhysFrame| f.number() + block_size == frame.number());
+
+            if !continues_block {
+                self.deallocate_block(first_frame, block_size);
+
+                if frame.number() % align == 0 {
+                    first_frame = Some(frame);
+                    block_size = 0;
+                }
+                else {
+                    self.deallocate(frame);
+                    first_frame = None;
+                    block_size = 0;
+
+                    continue;
+                }
+            }
+
+            block_size += 1;
+        }
+
+        first_frame
     }
 
+    fn deallocate_frame(&mut self, frame : PhysFrame) {
+        self.deallocate(frame)
+    }
+
+    fn free_frames_count(&self) -> usize {
+        let mut regions = MemoryRegions::new();
+
+        for area in self.memory_areas.clone() {
+            assert!(regions.add(PhysAddr::new(area.base_address() as usize), area.length() as usize), "Too many memory areas");
+        }
+
+        self.reserve_used_memory(&mut regions);
+
+        regions.total_size() / FRAME_SIZE + self.free_list_length
+    }
 }
 
 impl fmt::Display for FrameAllocator {    
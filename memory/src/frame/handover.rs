@@ -0,0 +1,94 @@
+use core::cmp;
+use address::{PhysAddr, PhysAddrRange};
+use allocator::zone::Zone;
+use frame::{PhysFrame, FrameSource};
+use frame::regions::MemoryRegions;
+
+/// Physical memory that boot frame allocator passes to runtime allocators, see `FrameAllocator::handover`.
+/// Memory of multiboot information is kept reserved until the kernel doesn't need it.
+pub struct MemoryHandover {
+    free      : MemoryRegions,
+    multiboot : MemoryRegions,
+}
+
+impl MemoryHandover {
+
+    pub(crate) fn new(free : MemoryRegions, multiboot : MemoryRegions) -> Self {
+        MemoryHandover {
+            free,
+            multiboot
+        }
+    }
+
+    /// Memory that runtime allocators can use right away
+    pub fn free_regions(&self) -> &MemoryRegions {
+        &self.free
+    }
+
+    /// Frames of multiboot information that are not reclaimed yet
+    pub fn multiboot_regions(&self) -> &MemoryRegions {
+        &self.multiboot
+    }
+
+    /// Memory that runtime frame allocator must index: free regions and multiboot information
+    /// that is returned to the allocator by `reclaim_multiboot`
+    pub fn managed_regions(&self) -> MemoryRegions {
+        let mut managed = MemoryRegions::new();
+
+        for region in self.free.iter().chain(self.multiboot.iter()) {
+            assert!(managed.add(region.start_address(), region.size()), "Too many managed memory regions");
+        }
+
+        managed
+    }
+
+    /// Removes memory from free regions, e.g. memory of allocator aux data structures
+    /// # Arguments
+    /// * `size` - size in bytes, aligned up to frame size
+    pub fn take(&mut self, size : usize) -> Option<PhysAddr> {
+        self.free.take(size)
+    }
+
+    /// Removes memory of the zone from free regions, e.g. memory of device buffers that the heap must never use.
+    /// Memory is taken from the biggest free region of the zone.
+    /// # Arguments
+    /// * `zone` - zone of the memory
+    /// * `size` - size in bytes, aligned up to frame size
+    /// # Returns
+    /// Removed memory, it is smaller than requested if the zone has no region of that size.
+    /// None if the zone has no free memory
+    pub fn take_zone(&mut self, zone : Zone, size : usize) -> Option<PhysAddrRange> {
+        let region = zone.regions(&self.free).iter().max_by_key(|region| region.size())?;
+        let size = cmp::min(PhysFrame::address_align_up(size), region.size());
+
+        if size == 0 {
+            return None;
+        }
+
+        let range = PhysAddrRange::with_size(region.start_address(), size);
+
+        assert!(self.free.reserve(range), "Too many memory regions to reserve {}", range);
+
+        Some(range)
+    }
+
+    /// Returns frames of multiboot information to frame source, multiboot header must not be read after that
+    /// # Arguments
+    /// * `frame_source` - runtime frame allocator that indexes memory of `managed_regions`
+    /// # Returns
+    /// number of reclaimed frames
+    pub fn reclaim_multiboot<S>(&mut self, frame_source : &mut S) -> usize where S : FrameSource {
+        let mut reclaimed = 0;
+
+        for region in self.multiboot.iter() {
+            for frame in region.range().frames() {
+                frame_source.deallocate_frame(frame);
+                reclaimed += 1;
+            }
+        }
+
+        self.multiboot = MemoryRegions::new();
+
+        reclaimed
+    }
+}
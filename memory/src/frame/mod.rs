@@ -1,36 +1,74 @@
 pub mod frame_allocator;
+pub mod handover;
+pub mod regions;
 
 use core::fmt;
 use core::iter;
+use address::PhysAddr;
 
 pub const FRAME_SIZE: usize = 4096;
 
-#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Copy)]
-pub struct Frame {
+/// Source of physical frames: boot frame allocator before heap exists, buddy allocator after that.
+/// Memory of handed out frames is accessed through physical memory window.
+pub trait FrameSource {
+
+    /// Allocates single frame
+    fn allocate_frame(&mut self) -> Option<PhysFrame>;
+
+    /// Allocates physically contiguous frames
+    /// # Arguments
+    /// * `count` - number of frames
+    /// * `align` - alignment of the first frame in frames, must be a power of 2
+    /// # Returns
+    /// first frame of the block
+    fn allocate_contiguous(&mut self, count : usize, align : usize) -> Option<PhysFrame>;
+
+    /// Returns frame to the source, block of contiguous frames is returned by its first frame
+    fn deallocate_frame(&mut self, frame : PhysFrame);
+
+    /// Returns number of frames that can still be allocated
+    fn free_frames_count(&self) -> usize;
+}
+
+/// Physical memory frame of 4 KiB, virtual memory is described by `paging::page::Page`
+#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Copy, Debug)]
+pub struct PhysFrame {
     number: usize,
 }
 
-impl Frame {
+impl PhysFrame {
 
-    pub fn from_address(address: usize) -> Frame {
-        Frame { number: address / FRAME_SIZE }
+    /// Returns frame that contains provided physical address
+    /// # Arguments
+    /// * `address` - physical address
+    pub fn containing_address(address : PhysAddr) -> PhysFrame {
+        PhysFrame { number: address.as_usize() / FRAME_SIZE }
     }
 
-    pub fn number(&self) -> usize {
-        self.number
+    pub fn start_address(&self) -> PhysAddr {
+        PhysAddr::new(self.number * FRAME_SIZE)
     }
 
-    pub fn address(&self) -> usize {
-        self.number * FRAME_SIZE
+    /// Returns last address of the frame (inclusive)
+    pub fn end_address(&self) -> PhysAddr {
+        self.start_address() + (FRAME_SIZE - 1)
     }
 
-    pub fn end_address(&self) -> usize {
-        self.address() + FRAME_SIZE - 1
+    pub fn number(&self) -> usize {
+        self.number
     }
 
-    // creates inclusive range iterator
-    pub fn range_inclusive(start_address : usize, end_address : usize) -> ExclusiveFrameRange {
-        ExclusiveFrameRange::new(Frame::from_address(start_address), Frame::from_address(end_address))
+    /// Creates inclusive range iterator
+    /// # Arguments
+    /// * `start` - first frame of the range
+    /// * `end` - last frame of the range
+    pub fn range_inclusive(start : PhysFrame, end : PhysFrame) -> PhysFrameRange {
+        PhysFrameRange::new(start, end)
+    }
+
+    /// Returns frame that is `count` frames after this one, e.g. frame of contiguous block
+    pub fn offset(&self, count : usize) -> PhysFrame {
+        PhysFrame { number : self.number + count }
     }
 
     fn is_frame_aligned(address : usize) -> bool {
@@ -46,11 +84,11 @@ impl Frame {
     /// # Arguments
     /// * `address` - address to align
     pub fn address_align_up(address : usize) -> usize {
-        if Frame::is_frame_aligned(address) {
+        if PhysFrame::is_frame_aligned(address) {
             address
         }
         else {
-            (Frame::address_to_frame_number(address) + 1) * FRAME_SIZE
+            (PhysFrame::address_to_frame_number(address) + 1) * FRAME_SIZE
         }
     }
 
@@ -59,59 +97,32 @@ impl Frame {
     /// # Arguments
     /// * `address` - address to align
     pub fn address_align_down(address : usize) -> usize {
-        if Frame::is_frame_aligned(address) {
+        if PhysFrame::is_frame_aligned(address) {
             address
         }
         else {
-            Frame::address_to_frame_number(address) * FRAME_SIZE
-        }
-    }
-
-    pub fn number_for_address(address : usize) -> usize {
-        address / FRAME_SIZE
-    }
-
-    pub fn number_to_address(number : usize) -> usize {
-        number * FRAME_SIZE
-    }
-
-    pub fn aligned_up(address : usize) -> Frame {
-        if Frame::is_frame_aligned(address) {
-            Frame::from_address(address)
-        }
-        else {
-            Frame::from_address(address).next()
-        }
-    }
-
-    pub fn aligned_down(address : usize) -> Frame {
-        if Frame::is_frame_aligned(address) {
-            Frame::from_address(address)
-        }
-        else {
-            Frame::from_address(address).previous()
+            PhysFrame::address_to_frame_number(address) * FRAME_SIZE
         }
     }
 
     // creates new frame with number = self.number + 1
-    fn next(&self) -> Frame {
-        Frame { number : self.number + 1}
-    }
-
-    fn previous(&self) -> Frame {
-        let number = if self.number == 0 { 0 } else { self.number - 1 };
-        Frame { number : number }
+    fn next(&self) -> PhysFrame {
+        self.offset(1)
     }
 
-    pub fn zero_frame(frame : &Frame) {
+    /// Fills frame with zeros, frame is accessed through physical memory window
+    pub fn zero_frame(frame : &PhysFrame) {
         use core::ptr;
         use frame::FRAME_SIZE;
+        use address;
+
+        let frame_virtual_address = address::physical_to_virtual(frame.start_address());
 
-        unsafe { ptr::write(frame.address() as *mut [u8; FRAME_SIZE], [0; FRAME_SIZE]); }
+        unsafe { ptr::write(frame_virtual_address.as_mut_ptr::<[u8; FRAME_SIZE]>(), [0; FRAME_SIZE]); }
     }
 }
 
-impl fmt::Display for Frame {
+impl fmt::Display for PhysFrame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f,
                "number: {}",
@@ -119,43 +130,33 @@ impl fmt::Display for Frame {
     }
 }
 
-pub struct ExclusiveFrameRange {
-    current_frame : Frame,
-    end_frame : Frame    
+/// Inclusive range of physical frames
+pub struct PhysFrameRange {
+    current_frame : PhysFrame,
+    end_frame : PhysFrame
 }
 
-impl ExclusiveFrameRange {
-    fn new(current_frame : Frame, end_frame : Frame) -> ExclusiveFrameRange {
-        ExclusiveFrameRange {
-            current_frame : current_frame,
-            end_frame : end_frame
+impl PhysFrameRange {
+    fn new(current_frame : PhysFrame, end_frame : PhysFrame) -> PhysFrameRange {
+        PhysFrameRange {
+            current_frame,
+            end_frame
         }
     }
-
-    fn current_frame(&self) -> Frame {
-        self.current_frame
-    }
-
-    fn end_frame(&self) -> Frame {
-        self.end_frame
-    }
-
-    fn next_frame(&mut self) {
-        self.current_frame = self.current_frame.next()
-    }
 }
 
-impl iter::Iterator for ExclusiveFrameRange {
-    type Item = Frame;
+impl iter::Iterator for PhysFrameRange {
+    type Item = PhysFrame;
 
-    fn next(&mut self) -> Option<Frame> {
-        let current_frame = self.current_frame();
-        if current_frame <= self.end_frame() {            
-            self.next_frame();
+    fn next(&mut self) -> Option<PhysFrame> {
+        let current_frame = self.current_frame;
+
+        if current_frame <= self.end_frame {
+            self.current_frame = current_frame.next();
             Some(current_frame)
         }
         else {
             None
         }
     }
-}
\ No newline at end of file
+}
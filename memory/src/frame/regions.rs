@@ -0,0 +1,272 @@
+use core::fmt;
+use address::{PhysAddr, PhysAddrRange};
+use frame::{PhysFrame, FRAME_SIZE};
+use multiboot::multiboot_header::tags::memory_map::MemoryMap;
+
+/// Maximum number of discontiguous memory regions. Regions are collected before heap exists,
+/// so they are kept in a fixed size array.
+pub const MAX_MEMORY_REGIONS : usize = 32;
+
+/// Frame aligned range of physical memory
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct MemoryRegion {
+    start_address : PhysAddr,
+    // inclusive
+    end_address : PhysAddr
+}
+
+impl MemoryRegion {
+
+    /// Creates new region
+    /// # Arguments
+    /// * `start_address` - first address of the region, must be frame aligned
+    /// * `end_address` - last address of the region (inclusive), must be the last address of a frame
+    pub fn new(start_address : PhysAddr, end_address : PhysAddr) -> MemoryRegion {
+        assert!(start_address.is_aligned(FRAME_SIZE), "Region start {} is not frame aligned", start_address);
+        assert!((end_address + 1).is_aligned(FRAME_SIZE), "Region end {} is not the last address of a frame", end_address);
+        assert!(end_address > start_address, "Region end {} is below region start {}", end_address, start_address);
+
+        MemoryRegion {
+            start_address,
+            end_address
+        }
+    }
+
+    const fn empty() -> MemoryRegion {
+        MemoryRegion {
+            start_address : PhysAddr::zero(),
+            end_address : PhysAddr::zero()
+        }
+    }
+
+    pub fn start_address(&self) -> PhysAddr {
+        self.start_address
+    }
+
+    pub fn end_address(&self) -> PhysAddr {
+        self.end_address
+    }
+
+    pub fn size(&self) -> usize {
+        self.end_address.as_usize() - self.start_address.as_usize() + 1
+    }
+
+    pub fn start_frame(&self) -> PhysFrame {
+        PhysFrame::containing_address(self.start_address)
+    }
+
+    pub fn end_frame(&self) -> PhysFrame {
+        PhysFrame::containing_address(self.end_address)
+    }
+
+    /// Returns physical addresses of the region
+    pub fn range(&self) -> PhysAddrRange {
+        PhysAddrRange::new(self.start_address, self.end_address)
+    }
+
+    /// Determines if address belongs to the region
+    pub fn contains(&self, address : PhysAddr) -> bool {
+        address >= self.start_address && address <= self.end_address
+    }
+
+    // regions that overlap or follow each other can be merged into one
+    fn touches(&self, start_address : usize, end_address : usize) -> bool {
+        self.start_address.as_usize() <= end_address.saturating_add(1) &&
+            start_address <= self.end_address.as_usize().saturating_add(1)
+    }
+}
+
+impl fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "memory region {} - {}", self.start_address, self.end_address)
+    }
+}
+
+/// Sorted set of non overlapping physical memory regions.
+/// Adjacent and overlapping regions are merged on insertion.
+pub struct MemoryRegions {
+    regions : [MemoryRegion; MAX_MEMORY_REGIONS],
+    count : usize
+}
+
+impl MemoryRegions {
+
+    pub const fn new() -> MemoryRegions {
+        MemoryRegions {
+            regions : [MemoryRegion::empty(); MAX_MEMORY_REGIONS],
+            count : 0
+        }
+    }
+
+    /// Creates regions from available entries of multiboot memory map
+    /// # Arguments
+    /// * `memory_map` - multiboot memory map
+    pub fn from_memory_map(memory_map : &MemoryMap) -> MemoryRegions {
+        let mut regions = MemoryRegions::new();
+
+        for entry in memory_map.entries() {
+            let added = regions.add(PhysAddr::new(entry.base_address() as usize), entry.length() as usize);
+
+            assert!(added, "Memory map has more than {} discontiguous available regions", MAX_MEMORY_REGIONS);
+        }
+
+        regions
+    }
+
+    /// Adds memory to the set. Memory is shrunk to frame boundaries, memory that doesn't contain
+    /// a single whole frame is ignored.
+    /// # Arguments
+    /// * `start_address` - start of the memory
+    /// * `size` - size of the memory in bytes
+    /// # Returns
+    /// false if there is no space left for new region
+    pub fn add(&mut self, start_address : PhysAddr, size : usize) -> bool {
+        let start = PhysFrame::address_align_up(start_address.as_usize());
+        let end = PhysFrame::address_align_down(start_address.as_usize() + size);
+
+        if end <= start {
+            return true;
+        }
+
+        let (mut start, mut end) = (start, end - 1);
+
+        // absorb every region that overlaps or touches the new one
+        let mut i = 0;
+        while i < self.count {
+            let region = self.regions[i];
+
+            if region.touches(start, end) {
+                start = start.min(region.start_address.as_usize());
+                end = end.max(region.end_address.as_usize());
+
+                self.remove(i);
+            }
+            else {
+                i += 1;
+            }
+        }
+
+        self.insert(MemoryRegion::new(PhysAddr::new(start), PhysAddr::new(end)))
+    }
+
+    /// Removes memory from the set, e.g. memory occupied by kernel or multiboot information.
+    /// Memory is extended to frame boundaries, so partially reserved frames are removed too.
+    /// # Arguments
+    /// * `range` - reserved physical addresses
+    /// # Returns
+    /// false if region must be split and there is no space left for new region
+    pub fn reserve(&mut self, range : PhysAddrRange) -> bool {
+        let start = PhysFrame::address_align_down(range.start_address().as_usize());
+        let end = PhysFrame::address_align_down(range.end_address().as_usize()) + FRAME_SIZE - 1;
+
+        let mut i = 0;
+        while i < self.count {
+            let region = self.regions[i];
+            let region_start = region.start_address.as_usize();
+            let region_end = region.end_address.as_usize();
+
+            if region_end < start || region_start > end {
+                i += 1;
+            }
+            // region is fully reserved
+            else if region_start >= start && region_end <= end {
+                self.remove(i);
+            }
+            // reserved memory is inside the region
+            else if region_start < start && region_end > end {
+                self.regions[i].end_address = PhysAddr::new(start - 1);
+
+                return self.insert(MemoryRegion::new(PhysAddr::new(end + 1), region.end_address));
+            }
+            // reserved memory covers the beginning of the region
+            else if region_start >= start {
+                self.regions[i].start_address = PhysAddr::new(end + 1);
+                i += 1;
+            }
+            // reserved memory covers the end of the region
+            else {
+                self.regions[i].end_address = PhysAddr::new(start - 1);
+                i += 1;
+            }
+        }
+
+        true
+    }
+
+    /// Removes memory of requested size from the first region that can hold it
+    /// # Arguments
+    /// * `size` - size in bytes, aligned up to frame size
+    /// # Returns
+    /// Start address of removed memory
+    pub fn take(&mut self, size : usize) -> Option<PhysAddr> {
+        let size = PhysFrame::address_align_up(size);
+        let index = self.iter().position(|r| r.size() >= size)?;
+        let start_address = self.regions[index].start_address;
+
+        if self.regions[index].size() == size {
+            self.remove(index);
+        }
+        else {
+            self.regions[index].start_address = start_address + size;
+        }
+
+        Some(start_address)
+    }
+
+    /// Returns region that starts at the lowest and ends at the highest address of the set
+    pub fn span(&self) -> Option<MemoryRegion> {
+        if self.count == 0 {
+            None
+        }
+        else {
+            Some(MemoryRegion::new(self.regions[0].start_address, self.regions[self.count - 1].end_address))
+        }
+    }
+
+    /// Returns total size of all regions in bytes
+    pub fn total_size(&self) -> usize {
+        self.iter().map(|r| r.size()).sum()
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns iterator over regions sorted by start address
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = MemoryRegion> + 'a {
+        self.regions[.. self.count].iter().cloned()
+    }
+
+    fn insert(&mut self, region : MemoryRegion) -> bool {
+        if self.count == MAX_MEMORY_REGIONS {
+            return false;
+        }
+
+        let index = self.iter()
+            .position(|r| r.start_address > region.start_address)
+            .unwrap_or(self.count);
+
+        let mut i = self.count;
+        while i > index {
+            self.regions[i] = self.regions[i - 1];
+            i -= 1;
+        }
+
+        self.regions[index] = region;
+        self.count += 1;
+
+        true
+    }
+
+    fn remove(&mut self, index : usize) {
+        for i in index .. self.count - 1 {
+            self.regions[i] = self.regions[i + 1];
+        }
+
+        self.count -= 1;
+    }
+}
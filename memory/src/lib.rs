@@ -11,6 +11,7 @@ extern crate hardware;
 extern crate stdx_memory;
 extern crate display;
 
+pub mod address;
 pub mod frame;
 pub mod paging;
 pub mod allocator;
\ No newline at end of file
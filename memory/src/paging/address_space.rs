@@ -0,0 +1,275 @@
+use address::{PhysAddr, physical_to_virtual};
+use frame::{PhysFrame, FrameSource};
+use paging;
+use paging::page::Page;
+use paging::page_table::{PageTable, TableLevel, P4Table, P4, P3, P2, P1, EntryFlags, PRESENT, WRITABLE, COPY_ON_WRITE};
+use allocator::SharedFrameAllocator;
+use hardware::x86_64::tlb;
+
+/// First P4 entry that belongs to process private memory.
+/// Identity mapping of the boot code isn't preserved after kernel remap, so the whole lower half is private.
+pub const PROCESS_FIRST_P4_ENTRY : usize = 0;
+
+/// Last P4 entry that belongs to process private memory.
+/// Higher half (entries 256 - 510) is reserved for the kernel and is shared between all address spaces,
+/// entry 511 is recursive entry of every table.
+pub const PROCESS_LAST_P4_ENTRY : usize = 255;
+
+/// Virtual memory of a process. Described by its own P4 table, kernel entries of the P4 table point
+/// to the same P3 tables as entries of the kernel P4 table, so kernel mappings made inside already
+/// existing P4 entries are visible in every address space.
+pub struct AddressSpace {
+    p4_frame : PhysFrame
+}
+
+impl AddressSpace {
+
+    /// Creates new address space, kernel entries are copied from current P4 table.
+    /// # Arguments
+    /// * `current_p4_table` - current p4 table
+    /// * `frame_allocator` - frame allocator
+    /// # Returns
+    /// None if there is no memory for new P4 table
+    pub fn new<M>(current_p4_table : &mut P4Table, frame_allocator : &mut M) -> Option<AddressSpace> where M : FrameSource {
+        let p4_frame = frame_allocator.allocate_frame()?;
+
+        PhysFrame::zero_frame(&p4_frame);
+
+        unsafe {
+            current_p4_table.modify_other_table_with_current(p4_frame, frame_allocator, |new_p4, current_p4, _| {
+                // must be done before anything is mapped to the new table,
+                // otherwise kernel entries will point to new tables instead of shared ones
+                for index in 0..511 {
+                    if !AddressSpace::is_private_entry(index) {
+                        new_p4[index] = current_p4[index].clone();
+                    }
+                }
+            });
+        }
+
+        Some(AddressSpace {
+            p4_frame
+        })
+    }
+
+    /// Frame that holds P4 table of the address space
+    pub fn p4_frame(&self) -> PhysFrame {
+        self.p4_frame
+    }
+
+    /// Determines if this address space is loaded into CR3
+    pub fn is_active(&self) -> bool {
+        use hardware::x86_64::registers;
+
+        PhysFrame::containing_address(PhysAddr::new(registers::cr3() as usize)) == self.p4_frame
+    }
+
+    /// Switches to this address space
+    /// # Why unsafe
+    ///  Uses paging::switch_tables() which is unsafe
+    pub unsafe fn activate(&self) {
+        if !self.is_active() {
+            paging::switch_tables(self.p4_frame.start_address());
+        }
+    }
+
+    /// Maps page of the process private memory to newly allocated frame
+    /// # Arguments
+    /// * `current_p4_table` - current p4 table
+    /// * `page` - page inside process private memory
+    /// * `flags` - page flags
+    /// * `frame_allocator` - frame allocator
+    /// # Returns
+    /// Allocated frame or None if there is no memory
+    pub fn map_private<M>(&self, current_p4_table : &mut P4Table, page : Page, flags : EntryFlags, frame_allocator : &mut M) -> Option<PhysFrame> where M : FrameSource {
+        assert!(AddressSpace::is_private_page(page), "Page {} doesn't belong to process private memory", page);
+
+        let frame = frame_allocator.allocate_frame()?;
+
+        unsafe {
+            self.modify(current_p4_table, frame_allocator, |p4, _, frame_alloc| {
+                p4.map_page(page, frame, flags | PRESENT, frame_alloc);
+            });
+        }
+
+        Some(frame)
+    }
+
+    /// Performs action on the P4 table of this address space
+    /// # Arguments
+    /// * `current_p4_table` - current p4 table
+    /// * `frame_allocator` - frame allocator
+    /// * `action` - function to be executed on p4 table of this address space, receives current table as second parameter
+    /// # Why unsafe
+    ///  Uses modify_other_table_with_current() which is unsafe
+    pub unsafe fn modify<F, M>(&self, current_p4_table : &mut P4Table, frame_allocator : &mut M, action : F)
+    where M : FrameSource,
+          F : FnOnce(&mut P4Table, &mut P4Table, &mut M)
+    {
+        if self.is_active() {
+            // recursive entry already points to this table
+            let current_p4_address = current_p4_table as *mut P4Table;
+            action(current_p4_table, &mut *current_p4_address, frame_allocator);
+        }
+        else {
+            current_p4_table.modify_other_table_with_current(self.p4_frame, frame_allocator, action);
+        }
+    }
+
+    /// Creates copy of this address space. Private memory is not copied, frames are shared between
+    /// address spaces instead: writable pages become read only copy-on-write pages in both address spaces,
+    /// private copy of the frame is made on first write, see `paging::fault`.
+    /// # Arguments
+    /// * `current_p4_table` - current p4 table
+    /// * `frame_allocator` - frame allocator that was used to allocate process memory
+    /// # Returns
+    /// None if there is no memory for new P4 table
+    /// # Why unsafe
+    ///  Uses modify() which is unsafe
+    pub unsafe fn fork<M>(&self, current_p4_table : &mut P4Table, frame_allocator : &mut M) -> Option<AddressSpace> where M : SharedFrameAllocator {
+        let child = AddressSpace::new(current_p4_table, frame_allocator)?;
+        // tables of this address space are read through physical memory window,
+        // because recursive entry will point to the child table during modification
+        let parent_p4 = AddressSpace::window_table::<P4>(self.p4_frame.start_address());
+
+        child.modify(current_p4_table, frame_allocator, |child_p4, _, frame_alloc| {
+            for p4_index in PROCESS_FIRST_P4_ENTRY..(PROCESS_LAST_P4_ENTRY + 1) {
+                if !parent_p4.has_next_table(p4_index) {
+                    continue;
+                }
+
+                let p3 = AddressSpace::window_table::<P3>(parent_p4[p4_index].address());
+
+                for p3_index in 0..512 {
+                    assert!(!p3.is_huge_page(p3_index), "Huge pages of process private memory can't be shared");
+
+                    if !p3.has_next_table(p3_index) {
+                        continue;
+                    }
+
+                    let p2 = AddressSpace::window_table::<P2>(p3[p3_index].address());
+
+                    for p2_index in 0..512 {
+                        assert!(!p2.is_huge_page(p2_index), "Huge pages of process private memory can't be shared");
+
+                        if !p2.has_next_table(p2_index) {
+                            continue;
+                        }
+
+                        let p1 = AddressSpace::window_table::<P1>(p2[p2_index].address());
+
+                        for p1_index in 0..512 {
+                            if !p1[p1_index].flags().contains(PRESENT) {
+                                continue;
+                            }
+
+                            let frame = p1[p1_index].frame();
+                            let flags = AddressSpace::shared_flags(p1[p1_index].flags());
+                            let page = Page::from_table_indices(p4_index, p3_index, p2_index, p1_index);
+
+                            p1[p1_index].set_frame(frame, flags);
+                            frame_alloc.share_frame(frame);
+
+                            child_p4.map_page(page, frame, flags, frame_alloc);
+                        }
+                    }
+                }
+            }
+        });
+
+        if self.is_active() {
+            // writable pages of this address space became read only
+            tlb::flush_all();
+        }
+
+        Some(child)
+    }
+
+    // writable pages are shared as copy-on-write, read only pages are shared as is
+    fn shared_flags(flags : EntryFlags) -> EntryFlags {
+        if flags.contains(WRITABLE) {
+            (flags & !WRITABLE) | COPY_ON_WRITE
+        }
+        else {
+            flags
+        }
+    }
+
+    fn window_table<L>(address : PhysAddr) -> &'static mut PageTable<L> where L : TableLevel {
+        unsafe { &mut (*physical_to_virtual(address).as_mut_ptr::<PageTable<L>>()) }
+    }
+
+    /// Releases all frames of process private memory (shared frames are freed when the last address
+    /// space releases them), frees page tables of process private memory and P4 table itself.
+    /// Address space must not be active.
+    /// # Arguments
+    /// * `current_p4_table` - current p4 table
+    /// * `frame_allocator` - frame allocator that was used to allocate process memory
+    /// # Why unsafe
+    ///  Uses modify_other_table_with_current() which is unsafe
+    pub unsafe fn destroy<M>(self, current_p4_table : &mut P4Table, frame_allocator : &mut M) where M : SharedFrameAllocator {
+        assert!(!self.is_active(), "Active address space cannot be destroyed");
+
+        current_p4_table.modify_other_table_with_current(self.p4_frame, frame_allocator, |p4, _, frame_alloc| {
+            for p4_index in PROCESS_FIRST_P4_ENTRY..(PROCESS_LAST_P4_ENTRY + 1) {
+                let p4_page = Page::from_table_indices(p4_index, 0, 0, 0);
+
+                if let Some(p3) = p4.next_table_opt(p4_page) {
+                    AddressSpace::free_p3(p3, p4_index, frame_alloc);
+
+                    AddressSpace::free_frame_at(p4[p4_index].address(), frame_alloc);
+                    p4[p4_index].set_unused();
+                }
+            }
+        });
+
+        AddressSpace::free_frame_at(self.p4_frame.start_address(), frame_allocator);
+    }
+
+    fn free_p3<M>(p3 : &mut PageTable<P3>, p4_index : usize, frame_allocator : &mut M) where M : SharedFrameAllocator {
+        for p3_index in 0..512 {
+            let page = Page::from_table_indices(p4_index, p3_index, 0, 0);
+
+            if p3.is_huge_page(p3_index) {
+                frame_allocator.release_frame(p3[p3_index].frame());
+            }
+            else if let Some(p2) = p3.next_table_opt(page) {
+                AddressSpace::free_p2(p2, p4_index, p3_index, frame_allocator);
+
+                AddressSpace::free_frame_at(p3[p3_index].address(), frame_allocator);
+            }
+        }
+    }
+
+    fn free_p2<M>(p2 : &mut PageTable<P2>, p4_index : usize, p3_index : usize, frame_allocator : &mut M) where M : SharedFrameAllocator {
+        for p2_index in 0..512 {
+            let page = Page::from_table_indices(p4_index, p3_index, p2_index, 0);
+
+            if p2.is_huge_page(p2_index) {
+                frame_allocator.release_frame(p2[p2_index].frame());
+            }
+            else if let Some(p1) = p2.next_table_opt(page) {
+                for p1_index in 0..512 {
+                    if p1[p1_index].flags().contains(PRESENT) {
+                        frame_allocator.release_frame(p1[p1_index].frame());
+                    }
+                }
+
+                AddressSpace::free_frame_at(p2[p2_index].address(), frame_allocator);
+            }
+        }
+    }
+
+    fn free_frame_at<M>(address : PhysAddr, frame_allocator : &mut M) where M : FrameSource {
+        frame_allocator.deallocate_frame(PhysFrame::containing_address(address))
+    }
+
+    /// Determines if page belongs to process private memory
+    pub fn is_private_page(page : Page) -> bool {
+        AddressSpace::is_private_entry((page.number() >> 27) & 511)
+    }
+
+    fn is_private_entry(p4_index : usize) -> bool {
+        p4_index >= PROCESS_FIRST_P4_ENTRY && p4_index <= PROCESS_LAST_P4_ENTRY
+    }
+}
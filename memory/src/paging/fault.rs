@@ -0,0 +1,236 @@
+use core::fmt;
+use core::ptr;
+use address::{VirtAddr, physical_to_virtual};
+use frame::{PhysFrame, FRAME_SIZE};
+use paging::page::Page;
+use paging::page_table::{P4Table, PRESENT, WRITABLE, NO_EXECUTE, COPY_ON_WRITE};
+use allocator::SharedFrameAllocator;
+use paging::virtual_region::{VirtualRegion, VirtualRegions, RegionKind};
+use hardware::x86_64::interrupts::handler::{
+    PageFaultErrorCode,
+    PROTECTION_VIOLATION,
+    CAUSED_BY_WRITE,
+    USER_MODE,
+    MALFORMED_TABLE,
+    INSTRUCTION_FETCH
+};
+use hardware::x86_64::tlb;
+
+/// Reason why page fault can't be resolved
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum FaultReason {
+    /// Address doesn't belong to any reserved region
+    NotReserved,
+    /// Address belongs to guard region
+    GuardPage,
+    /// Write to present page that is not writable
+    WriteToReadOnly,
+    /// Instruction fetch from present page that is not executable
+    ExecuteNoExecute,
+    /// User mode access to present page that is not user accessible
+    UserAccessToKernel,
+    /// Access type is not permitted by flags of the region
+    RegionAccessViolation,
+    /// Page table entry has reserved bit set
+    MalformedTable,
+    /// There are no frames to back the page
+    OutOfMemory
+}
+
+impl fmt::Display for FaultReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FaultReason::NotReserved => write!(f, "address is not reserved"),
+            FaultReason::GuardPage => write!(f, "access to guard page"),
+            FaultReason::WriteToReadOnly => write!(f, "write to read only page"),
+            FaultReason::ExecuteNoExecute => write!(f, "instruction fetch from non executable page"),
+            FaultReason::UserAccessToKernel => write!(f, "user mode access to kernel page"),
+            FaultReason::RegionAccessViolation => write!(f, "access is not permitted by region flags"),
+            FaultReason::MalformedTable => write!(f, "reserved bit set in page table entry"),
+            FaultReason::OutOfMemory => write!(f, "no memory to back the page"),
+        }
+    }
+}
+
+/// Detailed description of the page fault that can't be resolved
+#[derive(Clone, Copy, Debug)]
+pub struct FaultReport {
+    pub address : VirtAddr,
+    pub instruction_pointer : usize,
+    pub error_code : PageFaultErrorCode,
+    pub reason : FaultReason,
+    pub region : Option<VirtualRegion>
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = if self.error_code.contains(INSTRUCTION_FETCH) {
+            "instruction fetch"
+        }
+        else if self.error_code.contains(CAUSED_BY_WRITE) {
+            "write"
+        }
+        else {
+            "read"
+        };
+
+        let mode = if self.error_code.contains(USER_MODE) { "user" } else { "kernel" };
+        let page_state = if self.error_code.contains(PROTECTION_VIOLATION) { "present" } else { "non present" };
+
+        writeln!(f, "PAGE FAULT at {}: {}", self.address, self.reason)?;
+        writeln!(f, "  {} {} of {} page, instruction pointer {:#x}", mode, access, page_state, self.instruction_pointer)?;
+        writeln!(f, "  error code {:#x}", self.error_code.bits())?;
+
+        match self.region {
+            Some(region) => writeln!(f, "  inside {}", region),
+            None => writeln!(f, "  outside of reserved regions")
+        }
+    }
+}
+
+/// Result of the page fault handling
+#[derive(Clone, Copy, Debug)]
+pub enum FaultResolution {
+    /// Page was backed by newly allocated frame, faulted instruction can be restarted
+    Mapped(Page, PhysFrame),
+    /// Write to copy-on-write page, page was made writable and now is backed by provided frame
+    /// (a copy of the shared frame or the same frame if it was the last reference)
+    CopiedOnWrite(Page, PhysFrame),
+    /// Fault is caused by invalid access
+    Invalid(FaultReport)
+}
+
+/// Handles page fault: if faulted address belongs to reserved region, its page is backed by
+/// new zeroed frame, write to copy-on-write page gets private copy of the frame,
+/// otherwise fault is reported as invalid.
+/// # Arguments
+/// * `p4_table` - current p4 table
+/// * `regions` - reserved regions of the current address space
+/// * `address` - faulted address (CR2)
+/// * `instruction_pointer` - address of the faulted instruction
+/// * `error_code` - error code placed on stack by processor
+/// * `frame_allocator` - frame allocator
+pub fn handle_page_fault<M>(p4_table : &mut P4Table,
+                            regions : &VirtualRegions,
+                            address : VirtAddr,
+                            instruction_pointer : usize,
+                            error_code : PageFaultErrorCode,
+                            frame_allocator : &mut M) -> FaultResolution where M : SharedFrameAllocator {
+    let region = regions.find(address);
+    let report = |reason| FaultResolution::Invalid(FaultReport {
+        address,
+        instruction_pointer,
+        error_code,
+        reason,
+        region
+    });
+
+    if error_code.contains(MALFORMED_TABLE) {
+        return report(FaultReason::MalformedTable);
+    }
+
+    if error_code.contains(PROTECTION_VIOLATION) && error_code.contains(CAUSED_BY_WRITE) && is_copy_on_write(p4_table, address) {
+        return match copy_on_write(p4_table, Page::containing_address(address), frame_allocator) {
+            Some(resolution) => resolution,
+            None => report(FaultReason::OutOfMemory)
+        };
+    }
+
+    // page is present, demand paging can't help here
+    if error_code.contains(PROTECTION_VIOLATION) {
+        let reason = if error_code.contains(INSTRUCTION_FETCH) {
+            FaultReason::ExecuteNoExecute
+        }
+        else if error_code.contains(CAUSED_BY_WRITE) {
+            FaultReason::WriteToReadOnly
+        }
+        else {
+            FaultReason::UserAccessToKernel
+        };
+
+        return report(reason);
+    }
+
+    let region = match region {
+        Some(region) => region,
+        None => return report(FaultReason::NotReserved)
+    };
+
+    if region.kind() == RegionKind::Guard {
+        return report(FaultReason::GuardPage);
+    }
+
+    let write_denied = error_code.contains(CAUSED_BY_WRITE) && !region.flags().contains(WRITABLE);
+    let fetch_denied = error_code.contains(INSTRUCTION_FETCH) && region.flags().contains(NO_EXECUTE);
+
+    if write_denied || fetch_denied {
+        return report(FaultReason::RegionAccessViolation);
+    }
+
+    let frame = match frame_allocator.allocate_frame() {
+        Some(frame) => frame,
+        None => return report(FaultReason::OutOfMemory)
+    };
+
+    // memory of the region must not leak data of the previous frame owner
+    PhysFrame::zero_frame(&frame);
+
+    let page = Page::containing_address(address);
+    p4_table.map_page(page, frame, region.flags() | PRESENT, frame_allocator);
+
+    FaultResolution::Mapped(page, frame)
+}
+
+fn is_copy_on_write(p4_table : &P4Table, address : VirtAddr) -> bool {
+    p4_table.page_entry(Page::containing_address(address))
+        .map_or(false, |entry| entry.flags().contains(PRESENT | COPY_ON_WRITE))
+}
+
+// gives the page its own writable frame, frame is copied only if it is still shared
+fn copy_on_write<M>(p4_table : &mut P4Table, page : Page, frame_allocator : &mut M) -> Option<FaultResolution> where M : SharedFrameAllocator {
+    let entry = p4_table.page_entry(page).expect("Copy-on-write page is not mapped");
+    let shared_frame = entry.frame();
+    let flags = (entry.flags() & !COPY_ON_WRITE) | WRITABLE;
+
+    let frame = if frame_allocator.frame_reference_count(shared_frame) == 1 {
+        // other address spaces have already made their copies
+        shared_frame
+    }
+    else {
+        let frame = frame_allocator.allocate_frame()?;
+
+        unsafe {
+            ptr::copy_nonoverlapping(physical_to_virtual(shared_frame.start_address()).as_ptr::<u8>(),
+                                     physical_to_virtual(frame.start_address()).as_mut_ptr::<u8>(),
+                                     FRAME_SIZE);
+        }
+
+        frame_allocator.release_frame(shared_frame);
+
+        frame
+    };
+
+    entry.set_frame(frame, flags);
+
+    unsafe { tlb::flush(page.start_address().as_usize()); }
+
+    Some(FaultResolution::CopiedOnWrite(page, frame))
+}
+
+/// Unmaps all pages of the region that were backed by frames and releases their frames.
+/// Page tables that were created for the region are not freed.
+/// # Arguments
+/// * `p4_table` - current p4 table
+/// * `region` - region, usually returned by `VirtualRegions::release`
+/// * `frame_allocator` - frame allocator that was used to handle page faults of the region
+/// # Why unsafe
+///  Uses unmap_page() which is unsafe
+pub unsafe fn release_region<M>(p4_table : &mut P4Table, region : &VirtualRegion, frame_allocator : &mut M) where M : SharedFrameAllocator {
+    for page in region.pages() {
+        if let Some(frame) = p4_table.translate_page(page) {
+            p4_table.unmap_page(page);
+
+            frame_allocator.release_frame(frame);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use core::fmt;
+use core::iter;
+use address::{VirtAddr, PhysAddr};
+use paging::page::{Page, PageSize};
+use paging::page_table::{
+    P4Table,
+    EntryFlags,
+    PRESENT,
+    WRITABLE,
+    USER_ACCESSIBLE,
+    WRITE_THROUGH,
+    NO_CACHE,
+    ACCESSED,
+    DIRTY,
+    HUGE_PAGE,
+    GLOBAL,
+    NO_EXECUTE
+};
+
+const ENTRIES_PER_TABLE : usize = 512;
+
+/// Number of P4 entries that describe real mappings, the last one is recursive entry
+#[cfg(not(feature = "offset_page_table"))]
+const MAPPED_P4_ENTRIES : usize = ENTRIES_PER_TABLE - 1;
+
+/// Number of P4 entries that describe real mappings, there is no recursive entry
+/// when tables are accessed through physical memory window
+#[cfg(feature = "offset_page_table")]
+const MAPPED_P4_ENTRIES : usize = ENTRIES_PER_TABLE;
+
+/// Contiguous region of virtual memory that is mapped to contiguous region of physical memory
+/// with the same page size and flags.
+#[derive(Clone, Copy, Debug)]
+pub struct Mapping {
+    pub virtual_start : VirtAddr,
+    pub physical_start : PhysAddr,
+    pub size : usize,
+    pub page_size : PageSize,
+    pub flags : EntryFlags
+}
+
+impl Mapping {
+
+    /// Creates mapping of a single page
+    pub fn new(virtual_start : VirtAddr, physical_start : PhysAddr, page_size : PageSize, flags : EntryFlags) -> Mapping {
+        Mapping {
+            virtual_start,
+            physical_start,
+            size : page_size.size(),
+            page_size,
+            flags : flags & !(ACCESSED | DIRTY)
+        }
+    }
+
+    /// Last virtual address of the mapping
+    pub fn virtual_end(&self) -> VirtAddr {
+        VirtAddr::new_truncate(self.virtual_start.as_usize() + self.size - 1)
+    }
+
+    /// Last physical address of the mapping
+    pub fn physical_end(&self) -> PhysAddr {
+        self.physical_start + (self.size - 1)
+    }
+
+    /// Merges provided mapping into this one if it directly follows this mapping both in virtual
+    /// and physical memory and has the same page size and flags.
+    ///
+    /// # Returns
+    /// Some() with merged mapping, None if mappings can't be merged.
+    pub fn try_merge(&self, next : &Mapping) -> Option<Mapping> {
+        let virtual_follows = self.virtual_start.as_usize().checked_add(self.size) == Some(next.virtual_start.as_usize());
+        let physical_follows = self.physical_start.as_usize() + self.size == next.physical_start.as_usize();
+
+        if virtual_follows && physical_follows && self.page_size == next.page_size && self.flags == next.flags {
+            Some(Mapping {
+                size : self.size + next.size,
+                .. *self
+            })
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // virtual end is omitted to fit into 80 columns of vga text mode
+        write!(f,
+            "{:>16x} {:>13x} {:>10x} {:>5} ",
+            self.virtual_start.as_usize(),
+            self.physical_start.as_usize(),
+            self.size,
+            self.page_size)?;
+
+        write_flags(f, self.flags)
+    }
+}
+
+fn write_flags(f : &mut fmt::Formatter, flags : EntryFlags) -> fmt::Result {
+    let names = [
+        (PRESENT, "P"),
+        (WRITABLE, "W"),
+        (USER_ACCESSIBLE, "U"),
+        (WRITE_THROUGH, "WT"),
+        (NO_CACHE, "NC"),
+        (GLOBAL, "G"),
+        (NO_EXECUTE, "NX"),
+    ];
+
+    for &(flag, name) in names.iter() {
+        if flags.contains(flag) {
+            write!(f, "{} ", name)?;
+        }
+    }
+
+    Ok(())
+}
+
+// page table level which index should be advanced
+#[derive(Clone, Copy)]
+enum Level {
+    P4,
+    P3,
+    P2,
+    P1
+}
+
+/// Iterator over all present leaf entries of the page table, each entry is returned as separate mapping.
+/// Recursive entry is skipped.
+pub struct RawMappings<'a> {
+    p4 : &'a P4Table,
+    indices : [usize; 4]
+}
+
+impl<'a> RawMappings<'a> {
+    pub fn new(p4 : &'a P4Table) -> RawMappings<'a> {
+        RawMappings {
+            p4,
+            indices : [0; 4]
+        }
+    }
+
+    // moves to the next entry of the provided level, indices of the lower levels are reset
+    fn advance(&mut self, level : Level) {
+        let mut position = level as usize;
+
+        for lower in (position + 1)..4 {
+            self.indices[lower] = 0;
+        }
+
+        loop {
+            self.indices[position] += 1;
+
+            if self.indices[position] < ENTRIES_PER_TABLE || position == 0 {
+                break;
+            }
+
+            self.indices[position] = 0;
+            position -= 1;
+        }
+    }
+
+    fn current_page(&self) -> Page {
+        Page::from_table_indices(self.indices[0], self.indices[1], self.indices[2], self.indices[3])
+    }
+}
+
+impl<'a> iter::Iterator for RawMappings<'a> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        while self.indices[0] < MAPPED_P4_ENTRIES {
+            let page = self.current_page();
+
+            let p3 = match self.p4.next_table_opt(page) {
+                Some(p3) => p3,
+                None => { self.advance(Level::P4); continue; }
+            };
+
+            let p3_index = self.indices[1];
+
+            if p3.is_huge_page(p3_index) {
+                self.advance(Level::P3);
+                return Some(Mapping::new(page.start_address(), p3[p3_index].address(), PageSize::Size1G, p3[p3_index].flags() & !HUGE_PAGE));
+            }
+
+            let p2 = match p3.next_table_opt(page) {
+                Some(p2) => p2,
+                None => { self.advance(Level::P3); continue; }
+            };
+
+            let p2_index = self.indices[2];
+
+            if p2.is_huge_page(p2_index) {
+                self.advance(Level::P2);
+                return Some(Mapping::new(page.start_address(), p2[p2_index].address(), PageSize::Size2M, p2[p2_index].flags() & !HUGE_PAGE));
+            }
+
+            let p1 = match p2.next_table_opt(page) {
+                Some(p1) => p1,
+                None => { self.advance(Level::P2); continue; }
+            };
+
+            let p1_index = self.indices[3];
+            let p1_entry = &p1[p1_index];
+
+            self.advance(Level::P1);
+
+            if p1_entry.flags().contains(PRESENT) {
+                return Some(Mapping::new(page.start_address(), p1_entry.address(), PageSize::Size4K, p1_entry.flags()));
+            }
+        }
+
+        None
+    }
+}
+
+/// Iterator over all present mappings of the page table, contiguous mappings
+/// with the same page size and flags are coalesced into single mapping.
+pub struct Mappings<'a> {
+    raw : RawMappings<'a>,
+    pending : Option<Mapping>
+}
+
+impl<'a> Mappings<'a> {
+    pub fn new(p4 : &'a P4Table) -> Mappings<'a> {
+        let mut raw = RawMappings::new(p4);
+        let pending = raw.next();
+
+        Mappings {
+            raw,
+            pending
+        }
+    }
+}
+
+impl<'a> iter::Iterator for Mappings<'a> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        let mut current = self.pending.take()?;
+
+        while let Some(next) = self.raw.next() {
+            match current.try_merge(&next) {
+                Some(merged) => current = merged,
+                None => {
+                    self.pending = Some(next);
+                    break;
+                }
+            }
+        }
+
+        Some(current)
+    }
+}
+
+/// Formats all mappings of the page table as a table, one mapping per line.
+pub struct MappingsTable<'a> {
+    p4 : &'a P4Table
+}
+
+impl<'a> MappingsTable<'a> {
+    pub fn new(p4 : &'a P4Table) -> MappingsTable<'a> {
+        MappingsTable {
+            p4
+        }
+    }
+}
+
+impl<'a> fmt::Display for MappingsTable<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+            "{:>16} {:>13} {:>10} {:>5} {}",
+            "virtual",
+            "physical",
+            "size",
+            "page",
+            "flags")?;
+
+        for mapping in Mappings::new(self.p4) {
+            writeln!(f, "{}", mapping)?;
+        }
+
+        Ok(())
+    }
+}
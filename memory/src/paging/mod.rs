@@ -1,12 +1,18 @@
 pub mod page_table;
+pub mod page;
+pub mod mapping;
+pub mod address_space;
+pub mod virtual_region;
+pub mod fault;
 
 use paging::page_table::{ P4Table, PAGE_TABLE_SIZE, PAGE_TABLE_ENTRY_SIZE };
-use frame::frame_allocator::*;
-use frame::Frame;
+use paging::page::{Page, HUGE_PAGE_SIZE_2M};
+use frame::{PhysFrame, FrameSource};
+use address::{VirtAddr, PhysAddr, KERNEL_VIRTUAL_BASE, align_up, to_physical, physical_to_virtual};
 use multiboot::multiboot_header::MultibootHeader;
 use multiboot::multiboot_header::tags::elf;
+use multiboot::multiboot_header::tags::memory_map::MemoryMap;
 use hardware::x86_64::registers;
-use stdx_memory::MemoryAllocator;
 
 /// Returns size of page tables to describe virtual memory
 /// # Arguments
@@ -15,32 +21,43 @@ pub fn table_size_for(virtual_memory_size : usize) -> usize {
     (virtual_memory_size / PAGE_TABLE_SIZE) * PAGE_TABLE_ENTRY_SIZE
 }
 
-/// Returns current p4 table.
+/// Returns current p4 table, the table is accessed through recursive entry
+#[cfg(not(feature = "offset_page_table"))]
 pub fn p4_table() -> &'static mut P4Table {
     const P4_TABLE_ADDRESS : usize = 0xfffffffffffff000;         // recursive mapping to P4s 0 entry
     unsafe { &mut (*(P4_TABLE_ADDRESS as *mut P4Table)) } // reading predefined recursive address is safe
 }
 
+/// Returns current P4 table, the table is accessed through physical memory window
+#[cfg(feature = "offset_page_table")]
+pub fn p4_table() -> &'static mut P4Table {
+    let p4_frame = PhysFrame::containing_address(PhysAddr::new(registers::cr3() as usize));
+
+    unsafe { &mut (*physical_to_virtual(p4_frame.start_address()).as_mut_ptr::<P4Table>()) }
+}
+
 /// Switches paging tables
 /// # Arguments
 /// * `new_p4_table_address` - physical address of new p4 table
 /// # Why unsafe
 ///  Uses registers::cr3_write() which is unsafe
-pub unsafe fn switch_tables(new_p4_table_address : usize) {
-    registers::cr3_write(new_p4_table_address as u64);
+pub unsafe fn switch_tables(new_p4_table_address : PhysAddr) {
+    registers::cr3_write(new_p4_table_address.as_usize() as u64);
 }
 
-/// Properly maps (with proper flags and placement) kernel frames like: 
-/// kernel code, bump allocator, vga buffer etc. to fresh paging table. After that switches to
-/// that table. 
+/// Properly maps (with proper flags and placement) kernel sections to the higher half and
+/// all physical memory to physical memory window in fresh paging table. After that switches to
+/// that table. Identity mapping created by boot code is not preserved.
 /// # Arguments
 /// * `current_p4_table` - current p4 table
-/// * `frame_allocator` - frame allocator
+/// * `frame_allocator` - frame allocator, usually boot frame allocator
 /// * `multiboot_header` - multiboot header
+/// # Returns
+/// frame of the old p4 table, it isn't used after remap and can be reclaimed, see `FrameAllocator::handover`
 /// # Why unsafe
 ///  Uses modify_other_table(), page_table.unmap() which are unsafe
-pub unsafe fn remap_kernel(current_p4_table : &mut P4Table, frame_allocator : &mut FrameAllocator, multiboot_header : &MultibootHeader){     
-    let new_p4_table_address = frame_allocator.allocate().expect("No frames for kernel remap");
+pub unsafe fn remap_kernel<M>(current_p4_table : &mut P4Table, frame_allocator : &mut M, multiboot_header : &MultibootHeader) -> PhysFrame where M : FrameSource {
+    let new_p4_table_address = frame_allocator.allocate_frame().expect("No frames for kernel remap");
 
     current_p4_table.modify_other_table(new_p4_table_address, 
         frame_allocator, 
@@ -48,47 +65,70 @@ pub unsafe fn remap_kernel(current_p4_table : &mut P4Table, frame_allocator : &m
 
     let old_p4_address = registers::cr3();
 
-    switch_tables(new_p4_table_address.address());
+    switch_tables(new_p4_table_address.start_address());
 
     let new_p4 = p4_table();
 
-    // old p4 table should be identity mapped as part of kernel elf section
+    // old p4 table is part of kernel .bss section and is placed right below the stack,
     // unmapping it will create 'stack guard' - an unmapped area just below the stack.
     // Accessing it will immediately throw segfault, thus preventing stack growing out of hand
     // and overwriting something.
-    new_p4.unmap(old_p4_address  as usize)
+    new_p4.unmap(VirtAddr::new(KERNEL_VIRTUAL_BASE + old_p4_address as usize));
+
+    PhysFrame::containing_address(PhysAddr::new(old_p4_address as usize))
 }
 
-fn remap_kernel0<M>(p4_table : &mut P4Table, frame_allocator : &mut M, multiboot_header : & MultibootHeader)  where M : MemoryAllocator + FrameAllocatorFake {
+fn remap_kernel0<M>(p4_table : &mut P4Table, frame_allocator : &mut M, multiboot_header : & MultibootHeader)  where M : FrameSource {
     let elf_sections = multiboot_header
             .read_tag::<elf::ElfSections>()
             .unwrap();
 
-    for multiboot_header_frame in Frame::range_inclusive(multiboot_header.start_address(), multiboot_header.end_address()){
-        p4_table.map_page_1_to_1(multiboot_header_frame, page_table::PRESENT, frame_allocator);
-    }    
-        
     // todo figure out map or not non allocated section.
     // Reason: mapping non allocated section results in seg fault after remap operation.
-    let mut loaded_elf_sections = elf_sections.entries().filter(|e| e.flags().contains(elf::ALLOCATED));
+    // Sections below KERNEL_VIRTUAL_BASE contain boot code that is not used after remap.
+    let mut loaded_elf_sections = elf_sections
+        .entries()
+        .filter(|e| e.flags().contains(elf::ALLOCATED) && e.start_address() as usize >= KERNEL_VIRTUAL_BASE);
+
     while let Some(elf_section) = loaded_elf_sections.next() {
-        for elf_frame in Frame::range_inclusive(elf_section.start_address() as usize, elf_section.end_address() as usize) {
-            let page_flag = elf_sections_flag_to_page_flag(elf_section.flags());
-            p4_table.map_page_1_to_1(elf_frame, page_flag, frame_allocator);
+        let page_flag = elf_sections_flag_to_page_flag(elf_section.flags());
+        let start_page = Page::containing_address(VirtAddr::new(elf_section.start_address() as usize));
+        let end_page = Page::containing_address(VirtAddr::new(elf_section.end_address() as usize));
+
+        for elf_page in Page::range_inclusive(start_page, end_page) {
+            let elf_frame = PhysFrame::containing_address(PhysAddr::new(to_physical(elf_page.start_address().as_usize())));
+
+            p4_table.map_page(elf_page, elf_frame, page_flag, frame_allocator);
         }
     }
 
-    let vga_frame = Frame::from_address(0xb8000);
-    p4_table.map_page_1_to_1(vga_frame, page_table::PRESENT | page_table::WRITABLE, frame_allocator);
+    // physical memory window, it also covers vga buffer, multiboot header and
+    // boot frame allocator data structures
+    let physical_memory_end = physical_memory_end(multiboot_header);
+    let mut physical_address = PhysAddr::zero();
+
+    while physical_address.as_usize() < physical_memory_end {
+        let window_page = Page::containing_address(physical_to_virtual(physical_address));
+        let frame = PhysFrame::containing_address(physical_address);
+
+        p4_table.map_huge_2m(window_page, frame, page_table::PRESENT | page_table::WRITABLE | page_table::NO_EXECUTE, frame_allocator);
 
-    // remap bump allocator    
-    let bump_allocator = frame_allocator.bump_allocator();
-    
-    for bump_allocator_frame in Frame::range_inclusive(bump_allocator.start_address(), bump_allocator.end_address()) {
-        p4_table.map_page_1_to_1(bump_allocator_frame, page_table::PRESENT | page_table::WRITABLE, frame_allocator);
+        physical_address += HUGE_PAGE_SIZE_2M;
     }
 }
 
+/// Returns address that follows the last byte of available physical memory, aligned up to 2 MiB
+fn physical_memory_end(multiboot_header : &MultibootHeader) -> usize {
+    let memory_areas = multiboot_header.read_tag::<MemoryMap>().expect("Memory map is not present in MultiBootHeader");
+    let last_address = memory_areas
+        .entries()
+        .map(|e| e.end_address() as usize)
+        .max()
+        .unwrap_or(0);
+
+    align_up(last_address + 1, HUGE_PAGE_SIZE_2M)
+}
+
 fn elf_sections_flag_to_page_flag(elf_flags : elf::ElfSectionFlags) -> page_table::EntryFlags {
     let mut result = page_table::EntryFlags::from_bits_truncate(0);
 
@@ -101,9 +141,9 @@ fn elf_sections_flag_to_page_flag(elf_flags : elf::ElfSectionFlags) -> page_tabl
     }
 
     if !elf_flags.contains(elf::EXECUTABLE) {
-        // need to set NXE bit in some register for this to work,
-        // it will throw page fault otherwise
-        // result |= NO_EXECUTE;
+        // requires EFER.NXE bit to be set (registers::enable_nxe_bit),
+        // otherwise NO_EXECUTE is reserved bit and access to the page will throw page fault
+        result |= page_table::NO_EXECUTE;
     }
 
     result
@@ -0,0 +1,137 @@
+use core::fmt;
+use core::iter;
+use address::VirtAddr;
+use frame::PhysFrame;
+use paging::page_table::PAGE_TABLE_SIZE;
+
+pub const PAGE_SIZE : usize = PAGE_TABLE_SIZE;
+
+/// Size of the page mapped by P2 entry with HUGE_PAGE flag
+pub const HUGE_PAGE_SIZE_2M : usize = 512 * PAGE_SIZE;
+
+/// Size of the page mapped by P3 entry with HUGE_PAGE flag
+pub const HUGE_PAGE_SIZE_1G : usize = 512 * HUGE_PAGE_SIZE_2M;
+
+/// Sizes of the pages supported by x86-64 4 level paging
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum PageSize {
+    /// Regular page mapped by P1 entry
+    Size4K,
+    /// Huge page mapped by P2 entry
+    Size2M,
+    /// Huge page mapped by P3 entry
+    Size1G
+}
+
+impl PageSize {
+    pub fn size(&self) -> usize {
+        match *self {
+            PageSize::Size4K => PAGE_SIZE,
+            PageSize::Size2M => HUGE_PAGE_SIZE_2M,
+            PageSize::Size1G => HUGE_PAGE_SIZE_1G,
+        }
+    }
+}
+
+impl fmt::Display for PageSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PageSize::Size4K => write!(f, "4 KiB"),
+            PageSize::Size2M => write!(f, "2 MiB"),
+            PageSize::Size1G => write!(f, "1 GiB"),
+        }
+    }
+}
+
+/// Virtual memory page of 4 KiB
+#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Copy, Debug)]
+pub struct Page {
+    number : usize,
+}
+
+impl Page {
+
+    /// Returns page that contains provided virtual address
+    /// # Arguments
+    /// * `address` - virtual address
+    pub fn containing_address(address : VirtAddr) -> Page {
+        Page { number : address.as_usize() / PAGE_SIZE }
+    }
+
+    /// Returns page that has the same address as provided frame. Used for 1 to 1 (identity) mappings.
+    /// # Arguments
+    /// * `frame` - physical frame
+    pub fn identity_mapped(frame : PhysFrame) -> Page {
+        Page::containing_address(VirtAddr::new(frame.start_address().as_usize()))
+    }
+
+    /// Returns page that is addressed by provided page table indices
+    /// # Arguments
+    /// * `p4_index` - index inside P4 table
+    /// * `p3_index` - index inside P3 table
+    /// * `p2_index` - index inside P2 table
+    /// * `p1_index` - index inside P1 table
+    pub fn from_table_indices(p4_index : usize, p3_index : usize, p2_index : usize, p1_index : usize) -> Page {
+        let number = (p4_index << 27) | (p3_index << 18) | (p2_index << 9) | p1_index;
+
+        Page::containing_address(VirtAddr::new_truncate(number * PAGE_SIZE))
+    }
+
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    pub fn start_address(&self) -> VirtAddr {
+        VirtAddr::new_truncate(self.number * PAGE_SIZE)
+    }
+
+    pub fn end_address(&self) -> VirtAddr {
+        self.start_address() + (PAGE_SIZE - 1)
+    }
+
+    /// Creates inclusive range iterator
+    /// # Arguments
+    /// * `start` - first page of the range
+    /// * `end` - last page of the range
+    pub fn range_inclusive(start : Page, end : Page) -> PageRange {
+        PageRange {
+            current_page : start,
+            end_page : end
+        }
+    }
+
+    // creates new page with number = self.number + 1
+    fn next(&self) -> Page {
+        Page { number : self.number + 1 }
+    }
+}
+
+impl fmt::Display for Page {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "page number: {}, address: {}",
+               self.number,
+               self.start_address())
+    }
+}
+
+pub struct PageRange {
+    current_page : Page,
+    end_page : Page
+}
+
+impl iter::Iterator for PageRange {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        let current_page = self.current_page;
+
+        if current_page <= self.end_page {
+            self.current_page = current_page.next();
+            Some(current_page)
+        }
+        else {
+            None
+        }
+    }
+}
@@ -1,19 +1,22 @@
 use core::marker;
 use core::ops;
 use core::fmt;
-use frame::Frame;
+#[cfg(feature = "offset_page_table")]
+use address;
+use address::{VirtAddr, PhysAddr};
+use frame::{PhysFrame, FrameSource};
 use frame::FRAME_SIZE;
-use frame::frame_allocator::FrameAllocator;
+use paging::page::{Page, PageSize, PAGE_SIZE, HUGE_PAGE_SIZE_2M, HUGE_PAGE_SIZE_1G};
+use paging::mapping::{Mappings, MappingsTable};
 use hardware::x86_64::tlb;
-use stdx_memory::MemoryAllocator;
 
 pub const PAGE_TABLE_SIZE : usize = 4096; //4kb, x86-64 spec
 
 pub const PAGE_TABLE_ENTRY_SIZE : usize = 8;
 
-pub type VirtualFrame = Frame;
-
-pub type PhysicalFrame = Frame;
+/// Virtual address of kernel P4 entry 509, reserved for temporary mappings made by `modify_other_table`.
+/// It is shared between all address spaces and doesn't overlap process private memory (entries 0 - 255).
+pub const TEMPORARY_MAPPING_ADDRESS : usize = 0xFFFF_FE80_0000_0000;
 
 pub type P4Table = PageTable<P4>;
 
@@ -24,7 +27,7 @@ pub trait TableLevel {
     /// Determines index inside page table based on virtual page.
     /// Uses 'index_shift' to properly extract index based on table level.    
     /// 
-    fn page_index(page : VirtualFrame) -> usize {
+    fn page_index(page : Page) -> usize {
         (page.number() >> Self::index_shift()) & 511
     }
 }
@@ -115,14 +118,22 @@ impl <Level> PageTable<Level> where Level : TableLevel {
 
 impl<Level> PageTable<Level> where  Level : HasNextTableLevel {
 
+    /// Determines if entry points to the next level table. Entries with HUGE_PAGE
+    /// flag point to the huge page instead of the table.
     pub fn has_next_table(&self, index : usize) -> bool {        
-        let table_entry = &self[index];
-        let flags = table_entry.flags();
+        let flags = self[index].flags();
 
-        table_entry.flags().contains(PRESENT) && flags.contains(PRESENT)
+        flags.contains(PRESENT) && !flags.contains(HUGE_PAGE)
     }    
 
-    pub fn next_table_opt(&self, page : VirtualFrame) -> Option<&'static mut PageTable<Level::NextTableLevel>> {
+    /// Determines if entry maps huge page (2 MiB for P2, 1 GiB for P3)
+    pub fn is_huge_page(&self, index : usize) -> bool {
+        let flags = self[index].flags();
+
+        flags.contains(PRESENT) && flags.contains(HUGE_PAGE)
+    }
+
+    pub fn next_table_opt(&self, page : Page) -> Option<&'static mut PageTable<Level::NextTableLevel>> {
         let index = Level::page_index(page);
         if self.has_next_table(index) {
             Some(self.next_table(index))
@@ -132,21 +143,25 @@ impl<Level> PageTable<Level> where  Level : HasNextTableLevel {
         }
     }
 
-    pub fn next_table_or_create<M>(&mut self, page : VirtualFrame, frame_allocator : &mut M) -> &'static mut PageTable<Level::NextTableLevel> where M : MemoryAllocator {
+    pub fn next_table_or_create<M>(&mut self, page : Page, frame_allocator : &mut M) -> &'static mut PageTable<Level::NextTableLevel> where M : FrameSource {
         // page number is destructured to check if its index points to 
         // valid (present) page table entry. Recursive looping in P4 table is
         // used to physically address the desired table/frame. 
         let index = Level::page_index(page);
 
+        assert!(!self.is_huge_page(index), 
+            "Page {} is located inside huge page, split huge page before mapping it", 
+            page);
+
         if self.has_next_table(index) {
             self.next_table(index)
         }
         else {
             // create next level table
-            let new_table_frame = frame_allocator.allocate(FRAME_SIZE).expect("No memory for page table");
+            let new_table_frame = frame_allocator.allocate_frame().expect("No memory for page table");
 
             // set new entry in current table
-            self[index].set_frame(Frame::from_address(new_table_frame), PRESENT | WRITABLE);
+            self[index].set_frame(new_table_frame, PRESENT | WRITABLE);
             
             // clear next level table
             let result = self.next_table(index);
@@ -156,60 +171,48 @@ impl<Level> PageTable<Level> where  Level : HasNextTableLevel {
         }
     }
 
+    /// Next level table is accessed through recursive entry of the P4 table
+    #[cfg(not(feature = "offset_page_table"))]
     fn next_table(&self, index : usize) -> &'static mut PageTable<Level::NextTableLevel> {
         let table_address = self as *const _ as usize;
         let addr = (table_address << 9) | (index << 12);
 
         unsafe { &mut (*(addr as *mut PageTable<Level::NextTableLevel>)) }  
     }
+
+    /// Next level table is accessed through physical memory window
+    #[cfg(feature = "offset_page_table")]
+    fn next_table(&self, index : usize) -> &'static mut PageTable<Level::NextTableLevel> {
+        let table_address = address::physical_to_virtual(self[index].address());
+
+        unsafe { &mut (*table_address.as_mut_ptr::<PageTable<Level::NextTableLevel>>()) }
+    }
 }
 
 impl PageTable<P4> {
 
-    /// Returns overrall number of mapped memory in bytes  
-    // todo: rewrite  
+    /// Returns overrall number of mapped memory in bytes
     pub fn total_mapped_memory(&self) -> usize {
-        let mut result : usize = 0;
-
-        for entry in self.entries.iter().take(511).filter(|e| e.is_set()) {
-            let p4page = Frame::from_address(entry.address());
-
-            match self.next_table_opt(p4page) {
-                Some(p3) => {
-                    for p3Entry in p3.entries.iter().filter(|e| e.is_set()){
-                        let p3page = Frame::from_address(p3Entry.address());
-
-                        match p3.next_table_opt(p3page) {
-                            Some(p2) => {
-                                for p2Entry in p2.entries.iter().filter(|e| e.is_set()){
-                                    let p1page = Frame::from_address(p2Entry.address());
-
-                                    match p2.next_table_opt(p1page) {
-                                        Some(p1) =>{
-                                            result += p1.entries.iter().filter(|e| e.is_set()).count();
-                                        },
-                                        None => (),
-                                    }
-                                }
-                            },
-                            None => (),
-                        }
-                    }
-                },
-                None => (), 
-            }
-        };
+        self.mappings().map(|mapping| mapping.size).sum()
+    }
 
-        result * FRAME_SIZE
+    /// Returns iterator over all present mappings, contiguous mappings are coalesced
+    pub fn mappings(&self) -> Mappings {
+        Mappings::new(self)
+    }
+
+    /// Returns printable table of all present mappings
+    pub fn mappings_table(&self) -> MappingsTable {
+        MappingsTable::new(self)
     }
 
     /// maps virtual page to physical frame
     ///
     /// # Arguments
-    /// * `page` - virtual frame
+    /// * `page` - virtual page
     /// * `frame` - physical frame
     /// * `frame_allocator` - frame allocator
-    pub fn map_page<M>(&mut self, page : VirtualFrame, frame : PhysicalFrame, flags : EntryFlags, frame_allocator : &mut M)  where M : MemoryAllocator {
+    pub fn map_page<M>(&mut self, page : Page, frame : PhysFrame, flags : EntryFlags, frame_allocator : &mut M)  where M : FrameSource {
         let p1 = self.next_table_or_create(page, frame_allocator)
                          .next_table_or_create(page, frame_allocator)
                          .next_table_or_create(page, frame_allocator);
@@ -218,40 +221,253 @@ impl PageTable<P4> {
         p1[p1_index].set_frame(frame, flags)
     }
 
-    pub fn map<M>(&mut self, virtual_address : usize, physical_address : usize, flags : EntryFlags, frame_allocator : &mut M) where M : MemoryAllocator {
-        self.map_page(Frame::from_address(virtual_address), Frame::from_address(physical_address), flags, frame_allocator)
+    pub fn map<M>(&mut self, virtual_address : VirtAddr, physical_address : PhysAddr, flags : EntryFlags, frame_allocator : &mut M) where M : FrameSource {
+        self.map_page(Page::containing_address(virtual_address), PhysFrame::containing_address(physical_address), flags, frame_allocator)
     }
 
-    /// Maps virtual page to physical frame in 1 to 1 fashion, e.g.
-    /// virtual page will correspond to physical frame with the same address
+    /// Maps physical frame to virtual page in 1 to 1 fashion, e.g.
+    /// virtual page will have the same address as physical frame
     ///
     /// # Arguments
-    /// * `page` - virtual frame
+    /// * `frame` - physical frame
     /// * `frame_allocator` - frame allocator
-    pub fn map_page_1_to_1<M>(&mut self, page : VirtualFrame, flags : EntryFlags, frame_allocator : &mut M)  where M : MemoryAllocator {
-        let frame = page.clone();
+    pub fn map_page_1_to_1<M>(&mut self, frame : PhysFrame, flags : EntryFlags, frame_allocator : &mut M)  where M : FrameSource {
+        let page = Page::identity_mapped(frame);
         self.map_page(page, frame, flags, frame_allocator);
     }
 
-    pub fn map_1_to_1<M>(&mut self, virtual_address : usize, flags : EntryFlags, frame_allocator : &mut M) where M : MemoryAllocator {
-        self.map_page_1_to_1(Frame::from_address(virtual_address), flags, frame_allocator)
+    pub fn map_1_to_1<M>(&mut self, physical_address : PhysAddr, flags : EntryFlags, frame_allocator : &mut M) where M : FrameSource {
+        self.map_page_1_to_1(PhysFrame::containing_address(physical_address), flags, frame_allocator)
     }
 
-    pub fn map_pages_1_to_1<M>(&mut self, virtual_address_start : usize, count : usize, flags : EntryFlags, frame_allocator : &mut M) where M : MemoryAllocator {
-        let mut virtual_address = virtual_address_start;
+    pub fn map_pages_1_to_1<M>(&mut self, physical_address_start : PhysAddr, count : usize, flags : EntryFlags, frame_allocator : &mut M) where M : FrameSource {
+        let mut physical_address = physical_address_start;
 
         for _ in 0..count {
-            self.map_1_to_1(virtual_address, flags, frame_allocator);
-            virtual_address += FRAME_SIZE;
+            self.map_1_to_1(physical_address, flags, frame_allocator);
+            physical_address += FRAME_SIZE;
         }
     }
 
-    /// Unmaps virtual page
+    /// Maps 2 MiB virtual page to 2 MiB physical memory region using huge P2 entry.
     ///
     /// # Arguments
-    /// * `page` - virtual frame
+    /// * `page` - first 4 KiB page of the huge page, must be 2 MiB aligned
+    /// * `frame` - first frame of the physical region, must be 2 MiB aligned
     /// * `frame_allocator` - frame allocator
-    pub unsafe fn unmap_page(&self, page : VirtualFrame) {
+    pub fn map_huge_2m<M>(&mut self, page : Page, frame : PhysFrame, flags : EntryFlags, frame_allocator : &mut M) where M : FrameSource {
+        PageTable::<P4>::assert_huge_alignment(page, frame, PageSize::Size2M);
+
+        let p2 = self.next_table_or_create(page, frame_allocator)
+                     .next_table_or_create(page, frame_allocator);
+
+        let p2_index = P2::page_index(page);
+
+        assert!(!p2.has_next_table(p2_index), "Page {} is already mapped with 4 KiB pages", page);
+
+        p2[p2_index].set_frame(frame, flags | HUGE_PAGE);
+
+        unsafe { tlb::flush(page.start_address().as_usize()); }
+    }
+
+    /// Maps 1 GiB virtual page to 1 GiB physical memory region using huge P3 entry.
+    ///
+    /// # Arguments
+    /// * `page` - first 4 KiB page of the huge page, must be 1 GiB aligned
+    /// * `frame` - first frame of the physical region, must be 1 GiB aligned
+    /// * `frame_allocator` - frame allocator
+    pub fn map_huge_1g<M>(&mut self, page : Page, frame : PhysFrame, flags : EntryFlags, frame_allocator : &mut M) where M : FrameSource {
+        PageTable::<P4>::assert_huge_alignment(page, frame, PageSize::Size1G);
+
+        let p3 = self.next_table_or_create(page, frame_allocator);
+
+        let p3_index = P3::page_index(page);
+
+        assert!(!p3.has_next_table(p3_index), "Page {} is already mapped with smaller pages", page);
+
+        p3[p3_index].set_frame(frame, flags | HUGE_PAGE);
+
+        unsafe { tlb::flush(page.start_address().as_usize()); }
+    }
+
+    fn assert_huge_alignment(page : Page, frame : PhysFrame, page_size : PageSize) {
+        assert!(page.start_address().is_aligned(page_size.size()), 
+            "Page {} is not aligned to {} boundary", 
+            page, 
+            page_size);
+        assert!(frame.start_address().is_aligned(page_size.size()), 
+            "Frame {} is not aligned to {} boundary", 
+            frame, 
+            page_size);
+    }
+
+    /// Returns size of the page that maps provided virtual page, or None if page is not mapped.
+    ///
+    /// # Arguments
+    /// * `page` - virtual page
+    pub fn page_size(&self, page : Page) -> Option<PageSize> {
+        let p3 = self.next_table_opt(page)?;
+        let p3_index = P3::page_index(page);
+
+        if p3.is_huge_page(p3_index) {
+            return Some(PageSize::Size1G);
+        }
+
+        let p2 = p3.next_table_opt(page)?;
+        let p2_index = P2::page_index(page);
+
+        if p2.is_huge_page(p2_index) {
+            return Some(PageSize::Size2M);
+        }
+
+        let p1 = p2.next_table_opt(page)?;
+
+        if p1[P1::page_index(page)].flags().contains(PRESENT) {
+            Some(PageSize::Size4K)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Unmaps 2 MiB huge page. Does nothing if page is not mapped by huge P2 entry.
+    ///
+    /// # Arguments
+    /// * `page` - first 4 KiB page of the huge page
+    pub unsafe fn unmap_huge_2m(&self, page : Page) {
+        let p2_option = self.next_table_opt(page)
+                            .and_then(|p3| p3.next_table_opt(page));
+
+        if let Some(p2) = p2_option {
+            let p2_index = P2::page_index(page);
+
+            if p2.is_huge_page(p2_index) {
+                p2[p2_index].set_unused();
+
+                tlb::flush_all();
+            }
+        }
+    }
+
+    /// Unmaps 1 GiB huge page. Does nothing if page is not mapped by huge P3 entry.
+    ///
+    /// # Arguments
+    /// * `page` - first 4 KiB page of the huge page
+    pub unsafe fn unmap_huge_1g(&self, page : Page) {
+        if let Some(p3) = self.next_table_opt(page) {
+            let p3_index = P3::page_index(page);
+
+            if p3.is_huge_page(p3_index) {
+                p3[p3_index].set_unused();
+
+                tlb::flush_all();
+            }
+        }
+    }
+
+    /// Splits huge page that contains provided page into pages of the next smaller size,
+    /// e.g. 1 GiB page is splitted into 512 2 MiB pages and 2 MiB page into 512 4 KiB pages.
+    /// Mapping and flags of the memory stay the same.
+    ///
+    /// # Arguments
+    /// * `page` - virtual page located inside huge page
+    /// * `frame_allocator` - frame allocator, used to allocate new page table
+    ///
+    /// # Returns
+    /// True if huge page was splitted, false if page is not mapped by huge page.
+    pub unsafe fn split_huge_page<M>(&mut self, page : Page, frame_allocator : &mut M) -> bool where M : FrameSource {
+        let p3 = match self.next_table_opt(page) {
+            Some(p3) => p3,
+            None => return false
+        };
+
+        let p3_index = P3::page_index(page);
+
+        if p3.is_huge_page(p3_index) {
+            let p2 = PageTable::<P4>::split_huge_entry(p3, p3_index, frame_allocator);
+            let base = p2[0].address();
+            let flags = p2[0].flags();
+
+            for i in 0..512 {
+                p2[i].set(base + i * HUGE_PAGE_SIZE_2M, flags);
+            }
+
+            tlb::flush_all();
+
+            return true;
+        }
+
+        let p2 = match p3.next_table_opt(page) {
+            Some(p2) => p2,
+            None => return false
+        };
+
+        let p2_index = P2::page_index(page);
+
+        if p2.is_huge_page(p2_index) {
+            let p1 = PageTable::<P4>::split_huge_entry(p2, p2_index, frame_allocator);
+            let base = p1[0].address();
+            let flags = p1[0].flags() & !HUGE_PAGE;
+
+            for i in 0..512 {
+                p1[i].set(base + i * FRAME_SIZE, flags);
+            }
+
+            tlb::flush_all();
+
+            return true;
+        }
+
+        false
+    }
+
+    // Replaces huge entry with the entry that points to new next level table.
+    // First entry of the new table receives the old huge entry, caller fills the rest.
+    unsafe fn split_huge_entry<L, M>(table : &mut PageTable<L>, index : usize, frame_allocator : &mut M) -> &'static mut PageTable<L::NextTableLevel> 
+    where L : HasNextTableLevel,
+          M : FrameSource
+    {
+        let huge_entry_address = table[index].address();
+        let huge_entry_flags = table[index].flags();
+
+        let new_table_frame = frame_allocator.allocate_frame().expect("No memory for page table");
+        let table_flags = PRESENT | WRITABLE | (huge_entry_flags & USER_ACCESSIBLE);
+
+        table[index].set_frame(new_table_frame, table_flags);
+
+        let next_table = table.next_table(index);
+
+        // recursive address of the new table could be cached from the huge page mapping
+        tlb::flush(next_table as *const _ as usize);
+
+        next_table.clear_all_entries();
+        next_table[0].set(huge_entry_address, huge_entry_flags);
+
+        next_table
+    }
+
+    /// Unmaps virtual page, splits huge page that contains it if needed.
+    ///
+    /// # Arguments
+    /// * `page` - virtual page
+    /// * `frame_allocator` - frame allocator, used to allocate page tables for splitted huge pages
+    pub unsafe fn unmap_page_split_huge<M>(&mut self, page : Page, frame_allocator : &mut M) where M : FrameSource {
+        while self.split_huge_page(page, frame_allocator) {}
+
+        self.unmap_page(page)
+    }
+
+    /// Unmaps virtual page
+    ///
+    /// # Arguments
+    /// * `page` - virtual page
+    /// # Panic
+    /// Panics if page is located inside huge page, use `unmap_page_split_huge` or `unmap_huge_2m`/`unmap_huge_1g` instead
+    pub unsafe fn unmap_page(&self, page : Page) {
+        assert!(self.page_size(page).map(|size| size == PageSize::Size4K).unwrap_or(true),
+            "Page {} is located inside huge page",
+            page);
+
         let p1_option = self.next_table_opt(page)
                             .and_then(|p3| p3.next_table_opt(page))
                             .and_then(|p2| p2.next_table_opt(page));
@@ -271,15 +487,29 @@ impl PageTable<P4> {
                         //   if we don't flush TLB 
                 let should_be_page_fault = *(page.address() as *const u64) // won't produce segfault
             */
-            tlb::flush(page.address());
+            tlb::flush(page.start_address().as_usize());
         }    
     }
 
-    pub unsafe fn unmap(&self, virtual_address : usize) {
-        self.unmap_page(Frame::from_address(virtual_address))
+    /// Returns P1 entry that maps the page, entry may be not present
+    ///
+    /// # Arguments
+    /// * `page` - virtual page
+    ///
+    /// # Returns
+    /// None if there is no P1 table for the page, e.g. page is located inside huge page
+    pub fn page_entry(&self, page : Page) -> Option<&'static mut PageTableEntry> {
+        self.next_table_opt(page)
+            .and_then(|p3| p3.next_table_opt(page))
+            .and_then(|p2| p2.next_table_opt(page))
+            .map(|p1| &mut p1[P1::page_index(page)])
+    }
+
+    pub unsafe fn unmap(&self, virtual_address : VirtAddr) {
+        self.unmap_page(Page::containing_address(virtual_address))
     }
 
-    pub unsafe fn unmap_pages(&self, virtual_address_start : usize, count : usize) {
+    pub unsafe fn unmap_pages(&self, virtual_address_start : VirtAddr, count : usize) {
         let mut virtual_address = virtual_address_start;
 
         for _ in 0..count {
@@ -291,56 +521,95 @@ impl PageTable<P4> {
     /// Translates virtual page to physical frame.
     ///
     /// # Arguments
-    /// * `page` - virtual frame
-    /// * `frame_allocator` - frame allocator
+    /// * `page` - virtual page
     ///
     /// # Returns
-    /// Some() with physical frame if entry is present for corresponding virtual frame,
+    /// Some() with physical frame if entry is present for corresponding virtual page,
     /// otherwise returns None.
-    pub fn translate_page(&self, page : VirtualFrame) -> Option<Frame> {
-        
-        self.next_table_opt(page)
-        .and_then(|p3| p3.next_table_opt(page))
-        .and_then(|p2| p2.next_table_opt(page))
-        .and_then(|p1| { 
-            let p1_index = P1::page_index(page);
-            let p1_entry = &p1[p1_index];
-
-            if p1_entry.flags().contains(PRESENT) {            
-                Some(Frame::from_address(p1_entry.address()))
-            }
-            else {
-                None
-            }        
-        })
+    pub fn translate_page(&self, page : Page) -> Option<PhysFrame> {
+        self.translate(page.start_address()).map(PhysFrame::containing_address)
     }
 
     /// Checks whether virtual page points to existing physical frame
     ///
     /// # Arguments
-    /// * `page` - virtual frame
-    /// * `frame_allocator` - frame allocator
+    /// * `page` - virtual page
     ///
     /// # Returns
-    /// True if entry is present for corresponding virtual frame, otherwise returns false.
-    pub fn is_present(&self, page : VirtualFrame) -> bool {
+    /// True if entry is present for corresponding virtual page, otherwise returns false.
+    pub fn is_present(&self, page : Page) -> bool {
         self.translate_page(page).is_some()
     }
 
+    /// Returns flags of the entry that maps the page, e.g. to check access rights without touching the page
+    ///
+    /// # Arguments
+    /// * `page` - virtual page
+    ///
+    /// # Returns
+    /// Flags of P1 entry or of huge page entry, None if page is not mapped
+    pub fn page_flags(&self, page : Page) -> Option<EntryFlags> {
+        let p3 = self.next_table_opt(page)?;
+
+        if p3.is_huge_page(P3::page_index(page)) {
+            return Some(p3[P3::page_index(page)].flags());
+        }
+
+        let p2 = p3.next_table_opt(page)?;
+
+        if p2.is_huge_page(P2::page_index(page)) {
+            return Some(p2[P2::page_index(page)].flags());
+        }
+
+        let p1 = p2.next_table_opt(page)?;
+        let flags = p1[P1::page_index(page)].flags();
+
+        if flags.contains(PRESENT) {
+            Some(flags)
+        }
+        else {
+            None
+        }
+    }
+
     /// Translates virtual address to physical address.
     ///
     /// # Arguments
-    /// * `page` - virtual address
-    /// * `frame_allocator` - frame allocator
+    /// * `virtual_address` - virtual address
     ///
     /// # Returns
     /// Some() with physical address if entry is present for corresponding virtual address,
     /// otherwise returns None.
-    pub fn translate(&self, virtual_address : usize) -> Option<usize> {    
-        self.translate_page(Frame::from_address(virtual_address)).map(|frame| frame.address() + virtual_address % FRAME_SIZE)
+    pub fn translate(&self, virtual_address : VirtAddr) -> Option<PhysAddr> {    
+        let page = Page::containing_address(virtual_address);
+        let address = virtual_address.as_usize();
+
+        let p3 = self.next_table_opt(page)?;
+        let p3_entry = &p3[P3::page_index(page)];
+
+        if p3.is_huge_page(P3::page_index(page)) {
+            return Some(p3_entry.address().align_down(HUGE_PAGE_SIZE_1G) + address % HUGE_PAGE_SIZE_1G);
+        }
+
+        let p2 = p3.next_table_opt(page)?;
+        let p2_entry = &p2[P2::page_index(page)];
+
+        if p2.is_huge_page(P2::page_index(page)) {
+            return Some(p2_entry.address().align_down(HUGE_PAGE_SIZE_2M) + address % HUGE_PAGE_SIZE_2M);
+        }
+
+        let p1 = p2.next_table_opt(page)?;
+        let p1_entry = &p1[P1::page_index(page)];
+
+        if p1_entry.flags().contains(PRESENT) {
+            Some(p1_entry.address() + virtual_address.page_offset())
+        }
+        else {
+            None
+        }
     }
 
-    pub fn set_recursive_entry(&mut self, frame : Frame, flags : EntryFlags) {
+    pub fn set_recursive_entry(&mut self, frame : PhysFrame, flags : EntryFlags) {
         self[511].set_frame(frame, flags);
     }
 
@@ -352,9 +621,49 @@ impl PageTable<P4> {
     /// * `action` - function to be executed on another p4 table
     /// # Why unsafe
     ///  Uses tlb::flush() which is unsafe
-    pub unsafe fn modify_other_table<F, M>(&mut self, other_p4_table_address : Frame, frame_allocator : &mut M, action : F)
-    where M : MemoryAllocator,
+    pub unsafe fn modify_other_table<F, M>(&mut self, other_p4_table_address : PhysFrame, frame_allocator : &mut M, action : F)
+    where M : FrameSource,
                 F : FnOnce(&mut P4Table, &mut M)
+    {
+        self.modify_other_table0(other_p4_table_address, true, frame_allocator, |other, _current, frame_alloc| action(other, frame_alloc))
+    }
+
+    /// Performs action on another p4 table through this p4 table. Unlike `modify_other_table`
+    /// another table is not cleared, so it can be used to modify existing tables.
+    /// Action receives another table and the current table.
+    /// # Arguments
+    /// * `other_p4_table_address` - frame that holds another p4 table
+    /// * `frame_allocator` - frame allocator
+    /// * `action` - function to be executed on another p4 table
+    /// # Why unsafe
+    ///  Uses tlb::flush() which is unsafe
+    pub unsafe fn modify_other_table_with_current<F, M>(&mut self, other_p4_table_address : PhysFrame, frame_allocator : &mut M, action : F)
+    where M : FrameSource,
+                F : FnOnce(&mut P4Table, &mut P4Table, &mut M)
+    {
+        self.modify_other_table0(other_p4_table_address, false, frame_allocator, action)
+    }
+
+    #[cfg(feature = "offset_page_table")]
+    unsafe fn modify_other_table0<F, M>(&mut self, other_p4_table_address : PhysFrame, clear_other_table : bool, frame_allocator : &mut M, action : F)
+    where M : FrameSource,
+                F : FnOnce(&mut P4Table, &mut P4Table, &mut M)
+    {
+        // every table is reachable through physical memory window, so another table
+        // can be modified directly without touching the current one
+        let other_p4_table = &mut (*address::physical_to_virtual(other_p4_table_address.start_address()).as_mut_ptr::<P4Table>());
+
+        if clear_other_table {
+            other_p4_table.clear_all_entries();
+        }
+
+        action(other_p4_table, self, frame_allocator);
+    }
+
+    #[cfg(not(feature = "offset_page_table"))]
+    unsafe fn modify_other_table0<F, M>(&mut self, other_p4_table_address : PhysFrame, clear_other_table : bool, frame_allocator : &mut M, action : F)
+    where M : FrameSource,
+                F : FnOnce(&mut P4Table, &mut P4Table, &mut M)
     {
         let current_p4_table = self;
         // 1# map some unused virtual address to point to current p4
@@ -371,17 +680,20 @@ impl PageTable<P4> {
         // map some unused virtual address to point to current p4
         // this will be used to restore recursive mapping in current p4
         // after all the operations with temp p4 
-        let p4_physical_address = Frame::from_address(current_p4_table[511].address());   // p4's 511 entry points to self
-        let current_p4_save_address = Frame::from_address(0x400000000000);    // some temp address to save current p4
-        current_p4_table.map_page(current_p4_save_address, p4_physical_address, PRESENT | WRITABLE, frame_allocator);
+        let p4_physical_address = current_p4_table[511].frame();   // p4's 511 entry points to self
+        let current_p4_save_address = Page::containing_address(VirtAddr::new(TEMPORARY_MAPPING_ADDRESS));
+        current_p4_table.map_page(current_p4_save_address, p4_physical_address, PRESENT | WRITABLE | NO_EXECUTE, frame_allocator);
         
         // map temp table
-        let temp_p4_virtual_address = Frame::from_address(0x200000000000);   // some temp address to map temp p4
-        current_p4_table.map_page(temp_p4_virtual_address, other_p4_table_address, PRESENT, frame_allocator);
+        let temp_p4_virtual_address = Page::containing_address(VirtAddr::new(TEMPORARY_MAPPING_ADDRESS + PAGE_SIZE));
+        current_p4_table.map_page(temp_p4_virtual_address, other_p4_table_address, PRESENT | WRITABLE | NO_EXECUTE, frame_allocator);
         
         // set recursive entry in temp table
-        let temp_p4 = &mut (*(0x200000000000 as *mut P4Table));
-        temp_p4.clear_all_entries();
+        let temp_p4 = &mut (*temp_p4_virtual_address.start_address().as_mut_ptr::<P4Table>());
+        if clear_other_table {
+            temp_p4.clear_all_entries();
+        }
+
         temp_p4.set_recursive_entry(other_p4_table_address, PRESENT | WRITABLE);
         
         current_p4_table.unmap_page(temp_p4_virtual_address);
@@ -391,10 +703,12 @@ impl PageTable<P4> {
         
         tlb::flush_all();
 
-        action(current_p4_table, frame_allocator); // reading recursive entry again will move us to the temp table
+        // read old p4 through temp virtual address
+        let saved_p4 = &mut (*current_p4_save_address.start_address().as_mut_ptr::<P4Table>());
+
+        action(current_p4_table, saved_p4, frame_allocator); // reading recursive entry again will move us to the temp table
         
-        // read old p4 and place recursive entry back
-        let saved_p4 = &mut (*(current_p4_save_address.address() as *mut P4Table));
+        // place recursive entry back
         saved_p4.set_recursive_entry(p4_physical_address, PRESENT | WRITABLE);
         
         // unmap recursive address saving
@@ -405,6 +719,7 @@ impl PageTable<P4> {
 }
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct PageTableEntry {
     value : u64
 }
@@ -421,11 +736,15 @@ impl PageTableEntry {
         self.value
     }    
 
-    pub fn address(&self) -> usize {
+    pub fn address(&self) -> PhysAddr {
         // & 0x000ffffffffff000 because address is held in bits 12-52
-        self.value as usize & 0x000fffff_fffff000
+        PhysAddr::new(self.value as usize & 0x000fffff_fffff000)
     }                          
 
+    pub fn frame(&self) -> PhysFrame {
+        PhysFrame::containing_address(self.address())
+    }
+
     pub fn flags(&self) -> EntryFlags {
         EntryFlags::from_bits_truncate(self.value)
     }    
@@ -438,13 +757,13 @@ impl PageTableEntry {
         self.value != 0
     }
 
-    pub fn set_frame(&mut self, frame : Frame, flags : EntryFlags) {
-        self.set(frame.address(), flags)
+    pub fn set_frame(&mut self, frame : PhysFrame, flags : EntryFlags) {
+        self.set(frame.start_address(), flags)
     }    
 
-    pub fn set(&mut self, address : usize, flags : EntryFlags) {        
-        assert!(address & !0x000fffff_fffff000 == 0, "Address {} cannot be packed in 52 bits. Table entry value can be maximum 52 bits long", address);
-        self.value = (address as u64) | flags.bits();
+    pub fn set(&mut self, address : PhysAddr, flags : EntryFlags) {        
+        assert!(address.is_aligned(FRAME_SIZE), "Address {} is not frame aligned", address);
+        self.value = (address.as_usize() as u64) | flags.bits();
     }
 }
 
@@ -471,6 +790,9 @@ bitflags! {
         const DIRTY =           1 << 6;
         const HUGE_PAGE =       1 << 7;
         const GLOBAL =          1 << 8;
+        /// Bit available to OS. Page is shared between address spaces and is mapped read only,
+        /// write to it copies the frame, see `paging::fault`
+        const COPY_ON_WRITE =   1 << 9;
         const NO_EXECUTE =      1 << 63;
     }
 }
\ No newline at end of file
@@ -0,0 +1,154 @@
+use core::fmt;
+use address::{VirtAddr, VirtAddrRange};
+use paging::page::{PageRange, PAGE_SIZE};
+use paging::page_table::EntryFlags;
+
+/// Maximum number of regions that can be reserved at the same time. Registry doesn't use heap,
+/// because it is read by page fault handler, which also handles faults of the heap itself.
+pub const MAX_VIRTUAL_REGIONS : usize = 64;
+
+/// Determines how page fault inside the region is handled
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum RegionKind {
+    /// Heap memory, pages are backed by frames on first access
+    Heap,
+    /// Stack memory, pages are backed by frames on first access
+    Stack,
+    /// Memory that must never be accessed, e.g. area below the stack
+    Guard
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegionKind::Heap => write!(f, "heap"),
+            RegionKind::Stack => write!(f, "stack"),
+            RegionKind::Guard => write!(f, "guard"),
+        }
+    }
+}
+
+/// Reserved range of virtual memory, pages of the range are not backed by frames until they are accessed
+#[derive(Clone, Copy, Debug)]
+pub struct VirtualRegion {
+    range : VirtAddrRange,
+    flags : EntryFlags,
+    kind : RegionKind
+}
+
+impl VirtualRegion {
+
+    /// Creates new region
+    /// # Arguments
+    /// * `start_address` - first address of the region, must be page aligned
+    /// * `size` - size of the region in bytes, must be multiple of page size
+    /// * `flags` - flags of the pages that will be mapped inside the region
+    /// * `kind` - region kind
+    pub fn new(start_address : VirtAddr, size : usize, flags : EntryFlags, kind : RegionKind) -> VirtualRegion {
+        assert!(start_address.is_aligned(PAGE_SIZE), "Region start {} is not page aligned", start_address);
+        assert!(size != 0 && size % PAGE_SIZE == 0, "Region size {} is not multiple of page size", size);
+
+        VirtualRegion {
+            range : VirtAddrRange::with_size(start_address, size),
+            flags,
+            kind
+        }
+    }
+
+    pub fn start_address(&self) -> VirtAddr {
+        self.range.start_address()
+    }
+
+    pub fn end_address(&self) -> VirtAddr {
+        self.range.end_address()
+    }
+
+    pub fn size(&self) -> usize {
+        self.range.size()
+    }
+
+    /// Returns virtual addresses of the region
+    pub fn range(&self) -> VirtAddrRange {
+        self.range
+    }
+
+    pub fn flags(&self) -> EntryFlags {
+        self.flags
+    }
+
+    pub fn kind(&self) -> RegionKind {
+        self.kind
+    }
+
+    /// Returns iterator over all pages of the region
+    pub fn pages(&self) -> PageRange {
+        self.range.pages()
+    }
+
+    /// Determines if address belongs to the region
+    pub fn contains(&self, address : VirtAddr) -> bool {
+        self.range.contains(address)
+    }
+
+    /// Determines if regions have at least one common page
+    pub fn overlaps(&self, other : &VirtualRegion) -> bool {
+        self.range.overlaps(&other.range)
+    }
+}
+
+impl fmt::Display for VirtualRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} region {} - {}", self.kind, self.start_address(), self.end_address())
+    }
+}
+
+/// Registry of reserved virtual memory regions
+pub struct VirtualRegions {
+    regions : [Option<VirtualRegion>; MAX_VIRTUAL_REGIONS]
+}
+
+impl VirtualRegions {
+    pub const fn new() -> VirtualRegions {
+        VirtualRegions {
+            regions : [None; MAX_VIRTUAL_REGIONS]
+        }
+    }
+
+    /// Reserves region
+    /// # Returns
+    /// false if region overlaps already reserved region or there is no space left in the registry
+    pub fn reserve(&mut self, region : VirtualRegion) -> bool {
+        if self.iter().any(|r| r.overlaps(&region)) {
+            return false;
+        }
+
+        match self.regions.iter_mut().find(|r| r.is_none()) {
+            Some(slot) => {
+                *slot = Some(region);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Removes region that starts at provided address from the registry.
+    /// Pages that were mapped inside the region are not unmapped, see `paging::fault::release_region`
+    /// # Returns
+    /// Removed region or None if there is no region that starts at provided address
+    pub fn release(&mut self, start_address : VirtAddr) -> Option<VirtualRegion> {
+        self.regions
+            .iter_mut()
+            .find(|r| r.map_or(false, |region| region.start_address() == start_address))
+            .and_then(|slot| slot.take())
+    }
+
+    /// Returns region that contains provided address
+    pub fn find(&self, address : VirtAddr) -> Option<VirtualRegion> {
+        self.iter().find(|r| r.contains(address))
+    }
+
+    /// Returns iterator over all reserved regions
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = VirtualRegion> + 'a {
+        self.regions.iter().filter_map(|r| *r)
+    }
+}
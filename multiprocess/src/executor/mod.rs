@@ -7,17 +7,42 @@ use core::marker;
 use core::cell;
 use core::ptr;
 use core::ops;
+use core::mem;
+
+use memory::allocator::buddy::BuddyAllocator;
+use memory::frame::PhysFrame;
+use memory::address::{PhysAddr, VirtAddr};
+use memory::paging;
+use memory::paging::address_space::AddressSpace;
+use memory::paging::virtual_region::VirtualRegions;
+use hardware::x86_64::registers;
+use hardware::x86_64::fpu;
+use hardware::x86_64::fpu::FpuState;
+use hardware::x86_64::interrupts;
+use hardware::x86_64::interrupts::handler::{GeneralPurposeRegisters, InterruptedContext};
 
 use crate::process::Message;
 use crate::process::ProcessBox;
 use crate::process::Process;
+use crate::process::{ExitStatus, ProcessExited};
+use crate::stack::{ProcessStack, PROCESS_STACKS_START};
 
 pub type ExecutorRef = Rc<cell::UnsafeCell<Executor>>;
 
+// interrupts are enabled in new process, bit 1 of RFLAGS is always set
+const INITIAL_CPU_FLAGS: u64 = 0x202;
+
 pub struct ExecutorHelp {
     pub value: ptr::NonNull<ExecutorRef>
 }
 
+impl ExecutorHelp {
+    /// Determines if executor was created, e.g. faults before that can't belong to processes
+    pub fn is_initialized(&self) -> bool {
+        self.value != ptr::NonNull::dangling()
+    }
+}
+
 impl ops::Deref for ExecutorHelp {
     type Target = Executor;
 
@@ -35,48 +60,304 @@ impl ops::DerefMut for ExecutorHelp {
 pub struct Executor {
     id_counter: u64,
 
-    currently_executing: u64,
+    // None while kernel thread idles, see `switch_to_idle`
+    currently_executing: Option<u64>,
+
+    // registers of kernel thread that idles when no process is ready
+    idle_registers: Option<ProcessRegisters>,
 
     execution_line: VecDeque<u64>,
 
     existing: BTreeMap<u64, ProcessDescriptor>,
+
+    // allocates frames for process address spaces
+    frame_allocator: ptr::NonNull<BuddyAllocator>,
+
+    // registry where process stacks are reserved, the same registry is read by page fault handler
+    virtual_regions: ptr::NonNull<VirtualRegions>,
+
+    // p4 table of the kernel, active when executor is created
+    kernel_p4_frame: PhysFrame,
+
+    // process whose state is currently loaded into FPU
+    fpu_owner: Option<u64>,
+
+    // size of every process stack in bytes
+    stack_size: usize,
+
+    // guard page of the next stack that is placed above all other stacks
+    next_stack_address: VirtAddr,
+
+    // guard pages of released stacks, their virtual memory is reused by new stacks
+    released_stacks: Vec<VirtAddr>,
+
+    // finished processes whose memory is not freed yet, see `release_finished_processes`
+    finished: Vec<u64>,
 }
 
 impl Executor {
-    pub fn new() -> Self {
+    /// Creates new executor
+    /// # Arguments
+    /// * `frame_allocator` - allocator of process address spaces and stacks memory
+    /// * `virtual_regions` - registry of reserved virtual memory, pages of process stacks are backed on page fault
+    /// * `stack_size` - size of every process stack in bytes, must be multiple of page size, e.g. `stack::DEFAULT_STACK_SIZE`
+    pub fn new(mut frame_allocator: ptr::NonNull<BuddyAllocator>, virtual_regions: ptr::NonNull<VirtualRegions>, stack_size: usize) -> Self {
         let id_counter = 0;
         let execution_line: VecDeque<u64> = VecDeque::new();
         let existing: BTreeMap<u64, ProcessDescriptor> = BTreeMap::new();
+        let kernel_p4_frame = PhysFrame::containing_address(PhysAddr::new(registers::cr3() as usize));
+
+        // executor is created before any address space, so stacks are shared by all of them
+        unsafe { ProcessStack::prepare_stacks_memory(paging::p4_table(), frame_allocator.as_mut()); }
 
         Executor {
             id_counter,
-            currently_executing: 0,
+            currently_executing: None,
+            idle_registers: None,
             execution_line,
             existing,
+            frame_allocator,
+            virtual_regions,
+            kernel_p4_frame,
+            fpu_owner: None,
+            stack_size,
+            next_stack_address: VirtAddr::new(PROCESS_STACKS_START),
+            released_stacks: Vec::new(),
+            finished: Vec::new(),
         }
     }
 
+    pub fn kernel_p4_frame(&self) -> PhysFrame {
+        self.kernel_p4_frame
+    }
+
+    /// Puts message into process mailbox and wakes process blocked on empty mailbox,
+    /// messages to finished processes are dropped
     pub fn post_message(&mut self, id: u64, message: Message) {
-        if let Some(process) = self.existing.get_mut(&id) {
-            process.mailbox.push_back(message)
+        // mailbox and execution line are read by timer interrupt
+        interrupts::without_interrupts(|| self.post_message0(id, message))
+    }
+
+    fn post_message0(&mut self, id: u64, message: Message) {
+        let woken = match self.existing.get_mut(&id) {
+            Some(process) => {
+                if process.state != ProcessState::Finished {
+                    process.mailbox.push_back(message)
+                }
+
+                if process.state == ProcessState::Blocked {
+                    process.state = ProcessState::Ready;
+
+                    true
+                } else {
+                    false
+                }
+            },
+            None => false
+        };
+
+        if woken {
+            self.execution_line.push_back(id);
+        }
+    }
+
+    /// Takes current process out of execution line until a message is posted to it, called by the process itself.
+    /// Process keeps executing until the next process switch, so it may call this again while it is blocked already.
+    /// # Returns
+    /// false if mailbox isn't empty or process is neither running nor blocked, e.g. it has finished
+    pub fn block_current(&mut self) -> bool {
+        interrupts::without_interrupts(|| {
+            match self.current_process() {
+                Some(ref mut process) if (process.state == ProcessState::Running || process.state == ProcessState::Blocked) &&
+                    process.mailbox.is_empty() => {
+                    process.state = ProcessState::Blocked;
+
+                    true
+                },
+                _ => false
+            }
+        })
+    }
+
+    /// Finishes current process, called by the process itself. Process is never scheduled again,
+    /// its memory is freed by kernel thread, see `release_finished_processes`.
+    /// # Arguments
+    /// * `status` - exit status that is sent to the parent process
+    pub fn exit(&mut self, status: ExitStatus) {
+        interrupts::without_interrupts(|| {
+            if let Some(id) = self.currently_executing {
+                self.finish_process(id, status);
+            }
+        })
+    }
+
+    /// Finishes process and all its children
+    /// # Arguments
+    /// * `id` - id of the process, may be id of the current process
+    /// # Returns
+    /// false if process doesn't exist or has already finished
+    pub fn kill(&mut self, id: u64) -> bool {
+        interrupts::without_interrupts(|| self.finish_process(id, ExitStatus::Killed))
+    }
+
+    // marks process and its children finished and notifies parent, memory is freed by `release_finished_processes`
+    fn finish_process(&mut self, id: u64, status: ExitStatus) -> bool {
+        let (parent, children) = {
+            let process = match self.existing.get_mut(&id) {
+                Some(process) => process,
+                None => return false,
+            };
+
+            if process.state == ProcessState::Finished {
+                return false;
+            }
+
+            process.state = ProcessState::Finished;
+            process.mailbox.clear();
+
+            (process.parent, process.children.clone())
+        };
+
+        self.execution_line.retain(|&line_id| line_id != id);
+        self.finished.push(id);
+
+        for child_id in children {
+            self.finish_process(child_id, ExitStatus::Killed);
         }
+
+        // finished parent doesn't receive notification, see `post_message`
+        if let Some(parent_id) = parent {
+            self.post_message0(parent_id, Box::new(ProcessExited { id, status }));
+        }
+
+        true
+    }
+
+    /// Frees memory of finished processes, called by kernel thread while it idles. Memory isn't freed by
+    /// timer interrupt handler, it would free the stack it runs on. Heap and frame allocator can be used here
+    /// even if a process was preempted inside them, they disable interrupts while they update their data structures.
+    /// Process that is still executed on its stack is freed on the next call.
+    pub fn release_finished_processes(&mut self) {
+        interrupts::without_interrupts(|| {
+            let mut i = 0;
+
+            while i < self.finished.len() {
+                let id = self.finished[i];
+
+                if Some(id) == self.currently_executing {
+                    i += 1;
+                    continue;
+                }
+
+                self.finished.swap_remove(i);
+
+                if let Some(node) = self.existing.remove(&id) {
+                    self.release_process_memory(node);
+                }
+
+                if self.fpu_owner == Some(id) {
+                    self.fpu_owner = None;
+                }
+            }
+        })
     }
 
-    pub(crate) fn remove_process_with_children(&mut self, id: u64) {
-        if let Some(node) = self.existing.remove(&id) {
-            for child_id in node.children {
-                self.remove_process_with_children(child_id);
+    // returns frames of process address space back to frame allocator
+    fn release_process_memory(&mut self, node: ProcessDescriptor) {
+        unsafe {
+            if node.address_space.is_active() {
+                paging::switch_tables(self.kernel_p4_frame.start_address());
             }
+
+            node.address_space.destroy(paging::p4_table(), self.frame_allocator.as_mut());
+
+            let guard_address = node.stack.guard().start_address();
+            node.stack.release(self.virtual_regions.as_mut(), paging::p4_table(), self.frame_allocator.as_mut());
+            self.released_stacks.push(guard_address);
+        }
+    }
+
+    // reuses memory of released stack or places new stack right above the previous one,
+    // every stack has the same size, so released memory fits, stacks are separated by their guard pages
+    fn allocate_stack(&mut self) -> ProcessStack {
+        let (guard_address, reused) = match self.released_stacks.pop() {
+            Some(address) => (address, true),
+            None => (self.next_stack_address, false)
+        };
+
+        let stack = unsafe {
+            ProcessStack::new(guard_address,
+                              self.stack_size,
+                              self.virtual_regions.as_mut(),
+                              paging::p4_table(),
+                              self.frame_allocator.as_mut())
+                .expect("No memory for process stack")
+        };
+
+        if !reused {
+            self.next_stack_address += stack.reserved_size();
         }
+
+        stack
     }
 
-    pub(crate) fn remove_process(&mut self, id: u64) {
-        self.existing.remove(&id);
+    /// Returns id of process whose stack overflowed into its guard page
+    /// # Arguments
+    /// * `address` - faulted address
+    pub fn stack_overflow_process(&self, address: VirtAddr) -> Option<u64> {
+        self.existing
+            .iter()
+            .find(|&(_, process)| process.stack.guard().contains(address))
+            .map(|(id, _)| *id)
     }
 
     pub fn create_process(&mut self, process_message: ProcessBox) -> u64 {
-        let mut node = ProcessDescriptor::new(process_message);
-        //node.create_guard();
+        let address_space = unsafe {
+            AddressSpace::new(paging::p4_table(), self.frame_allocator.as_mut()).expect("No memory for process address space")
+        };
+
+        let stack = self.allocate_stack();
+
+        self.insert_process(ProcessDescriptor::new(process_message, address_space, stack))
+    }
+
+    /// Creates child process with a copy of the parent address space. Private memory is not
+    /// copied right away, frames are shared and copied on the first write of either process.
+    /// # Arguments
+    /// * `parent_id` - id of the parent process
+    /// * `process_message` - state of the child process
+    /// # Returns
+    /// Id of the child process or None if parent process doesn't exist
+    pub fn fork(&mut self, parent_id: u64, process_message: ProcessBox) -> Option<u64> {
+        // parent address space is modified and the child is put into execution line
+        interrupts::without_interrupts(|| self.fork0(parent_id, process_message))
+    }
+
+    fn fork0(&mut self, parent_id: u64, process_message: ProcessBox) -> Option<u64> {
+        let address_space = {
+            let parent_node = self.existing.get(&parent_id)?;
+
+            unsafe {
+                parent_node.address_space
+                    .fork(paging::p4_table(), self.frame_allocator.as_mut())
+                    .expect("No memory for process address space")
+            }
+        };
+
+        let stack = self.allocate_stack();
+        let mut child = ProcessDescriptor::new(process_message, address_space, stack);
+        child.parent = Some(parent_id);
+
+        let child_id = self.insert_process(child);
+
+        if let Some(parent_node) = self.existing.get_mut(&parent_id) {
+            parent_node.children.push(child_id);
+        }
+
+        Some(child_id)
+    }
+
+    fn insert_process(&mut self, node: ProcessDescriptor) -> u64 {
         let id = self.id_counter;
 
         self.existing.insert(id, node);
@@ -86,49 +367,132 @@ impl Executor {
         id
     }
 
-    /*pub (crate) fn fork(&mut self, parent_id : u64, process_message : ProcessBox) -> u64 {
-        if self.existing.contains_key(&parent_id) {
-            let child_id = self.create_process(process_message);
+    pub fn update_current_process(&mut self, interrupted_process_state: ProcessRegisters) {
+        match self.currently_executing {
+            Some(id) => {
+                if let Some(existing_process) = self.existing.get_mut(&id) {
+
+                    // blocked or woken process is executed until the switch, so it is resumed where it was interrupted
+                    match existing_process.state {
+                        ProcessState::Running | ProcessState::Blocked | ProcessState::Ready => {
+                            existing_process.registers = interrupted_process_state;
+                        },
+                        ProcessState::New | ProcessState::Finished => {}
+                    }
+                }
+            },
+            None => self.idle_registers = Some(interrupted_process_state)
+        }
+    }
 
-            let parent_node = self.existing.get_mut(&parent_id).unwrap();
+    /// Loads FPU state of the current process, called by device not available fault handler
+    /// on the first FPU instruction after process switch. State of the previous owner is saved only
+    /// when another process actually uses FPU.
+    pub fn switch_fpu_owner(&mut self) {
+        unsafe { fpu::clear_task_switched(); }
 
-            parent_node.children.push(child_id);
+        // kernel thread doesn't use FPU
+        let current = match self.currently_executing {
+            Some(id) => id,
+            None => return
+        };
 
-            child_id
+        if self.fpu_owner == Some(current) {
+            return;
         }
-        else {
-            0
+
+        if let Some(owner_id) = self.fpu_owner {
+            if let Some(owner) = self.existing.get_mut(&owner_id) {
+                unsafe { owner.fpu_state.save(); }
+            }
         }
-    }*/
 
-    pub fn update_current_process(&mut self, interrupted_process_state: ProcessRegisters) {
-        if let Some(existing_process) = self.existing.get_mut(&self.currently_executing) {
+        if let Some(process) = self.existing.get_mut(&current) {
+            unsafe { process.fpu_state.restore(); }
+        }
 
-            if existing_process.state == ProcessState::Running {
-                existing_process.registers = interrupted_process_state;
-            }
+        self.fpu_owner = Some(current);
+    }
+
+    /// Returns id of process that is executed at the moment, None if kernel thread idles
+    pub fn current_process_id(&self) -> Option<u64> {
+        self.currently_executing
+    }
+
+    /// Returns process that is executed at the moment
+    pub fn current_process(&mut self) -> Option<&mut ProcessDescriptor> {
+        match self.currently_executing {
+            Some(id) => self.existing.get_mut(&id),
+            None => None
         }
     }
 
+    /// Returns registers of kernel thread that idles when no process is ready, see `switch_to_idle`
+    pub fn idle_registers(&self) -> Option<&ProcessRegisters> {
+        self.idle_registers.as_ref()
+    }
+
     pub fn schedule_next(&mut self) -> Option<&mut ProcessDescriptor> {
         // Round robin algorithm: consecutively execute processes without any regard to priorities or round-trip time
         // pick one process to execute from execution line,
         // execute it and put it back into the queue
 
-        self.execution_line.push_back(self.currently_executing);
+        let executor = self as *mut Executor;
+
+        // only running process goes back to the line: new and woken processes are already there,
+        // blocked ones wait for a message and finished ones are never executed again
+        let running = match self.currently_executing {
+            Some(id) => self.existing.get_mut(&id).and_then(|process| {
+                if process.state == ProcessState::Running {
+                    process.state = ProcessState::Ready;
+
+                    Some(id)
+                } else {
+                    None
+                }
+            }),
+            None => None
+        };
+
+        // running process is put back after the head is taken, so the line never grows
+        // and interrupt handler doesn't use the allocator. Kernel thread idles until some process is ready
+        self.currently_executing = match (self.execution_line.pop_front(), running) {
+            (None, running) => running,
+            (head, Some(id)) => {
+                self.execution_line.push_back(id);
+                head
+            },
+            (head, None) => head
+        };
+
+        let next = match self.currently_executing {
+            Some(head_id) => self.existing.get_mut(&head_id),
+            None => None
+        };
+
+        next.map(|process| {
+            // new process is switched to like any stopped process, it resumes in `process_trampoline`
+            if process.state == ProcessState::New {
+                process.registers = process.start_registers(executor);
+            }
 
-        self.execution_line.pop_front().and_then(move |head_id| {
-            self.currently_executing = head_id;
+            process.state = ProcessState::Running;
 
-            self.existing.get_mut(&head_id)
+            process
         })
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ProcessState {
+    /// Created and never executed
     New,
+    /// Waits in execution line
+    Ready,
+    /// Executed at the moment
     Running,
+    /// Waits for a message, isn't in execution line
+    Blocked,
     Finished,
 }
 
@@ -136,63 +500,83 @@ pub enum ProcessState {
 pub struct ProcessDescriptor {
     process: ProcessBox,
 
-    stack_overflow_guard : [u8; 4096],
-
-    // 1 page frame
-    stack : [u8; 4096],
-
-    guard : [u8; 100],
+    stack: ProcessStack,
 
     mailbox: VecDeque<Message>,
 
     children: Vec<u64>,
 
+    // process that forked this one, notified when this process finishes
+    parent: Option<u64>,
+
     state: ProcessState,
 
     registers: ProcessRegisters,
+
+    address_space: AddressSpace,
+
+    // saved lazily, only when another process uses FPU
+    fpu_state: FpuState,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct ProcessRegisters {
+    pub general_purpose: GeneralPurposeRegisters,
+
     pub instruction_pointer: u64,
 
     pub stack_pointer: u64,
 
     pub cpu_flags: u64,
+
+    /// Physical address of P4 table of the process address space (CR3)
+    pub page_table: u64,
+}
+
+impl ProcessRegisters {
+    /// Takes registers of process that was stopped by interrupt
+    /// # Arguments
+    /// * `context` - registers saved by naked interrupt entry
+    pub fn from_context(context: &InterruptedContext) -> Self {
+        ProcessRegisters {
+            general_purpose: context.registers,
+            instruction_pointer: context.frame.instruction_pointer,
+            stack_pointer: context.frame.stack_pointer,
+            cpu_flags: context.frame.cpu_flags,
+            page_table: registers::cr3(),
+        }
+    }
 }
 
 impl ProcessDescriptor {
-    fn new(process: ProcessBox) -> Self {
+    fn new(process: ProcessBox, address_space: AddressSpace, stack: ProcessStack) -> Self {
         let mailbox: VecDeque<Message> = VecDeque::new();
         let children: Vec<u64> = Vec::new();
         let state = ProcessState::New;
-        let stack_overflow_guard = [0 as u8; 4096];
-        let stack = [0 as u8; 4096];
-        let guard = [0 as u8; 100];
 
         let registers = ProcessRegisters {
-            instruction_pointer: 0, // process function will be called directly and this value will be populated after interrupt
+            general_purpose: GeneralPurposeRegisters::default(),
+            instruction_pointer: 0, // populated by `start_registers` when process is scheduled for the first time
             stack_pointer : 0,
             cpu_flags: 0,
+            page_table: address_space.p4_frame().start_address().as_usize() as u64,
         };
 
         ProcessDescriptor {
             process,
-            stack_overflow_guard,
             stack,
-            guard,
             mailbox,
             children,
+            parent: None,
             state,
             registers,
+            address_space,
+            fpu_state: FpuState::new(),
         }
     }
 
-    pub fn create_guard(&mut self) {
-        use memory::paging;
-        use memory::frame::Frame;
-        let table = paging::p4_table();
-        unsafe { table.unmap_page(Frame::from_address(&self.stack_overflow_guard as *const _ as usize)) };
+    pub fn address_space(&self) -> &AddressSpace {
+        &self.address_space
     }
 
     pub fn registers(&self) -> &ProcessRegisters {
@@ -203,14 +587,53 @@ impl ProcessDescriptor {
         &self.state
     }
 
-    pub fn stack_address(&self) -> u64 {
-        (&self.stack as *const _ as u64)// + 4096
+    /// Returns top of the process stack, stack grows down towards its guard page
+    pub fn stack_top(&self) -> u64 {
+        self.stack.top()
+    }
+
+    pub fn stack(&self) -> &ProcessStack {
+        &self.stack
     }
 
-    pub fn process_front_message(&mut self) -> () {
-        if let Some(message) = self.mailbox.pop_front() {
-            self.state = ProcessState::Running;
-            self.process.process_message(message);
+    // registers that start process in `process_trampoline`, as if trampoline was called on empty stack
+    fn start_registers(&mut self, executor: *mut Executor) -> ProcessRegisters {
+        // zero return address ends stack traces of the process
+        let stack_pointer = self.stack_top() - mem::size_of::<u64>() as u64;
+
+        unsafe { ptr::write(stack_pointer as *mut u64, 0); }
+
+        let mut general_purpose = GeneralPurposeRegisters::default();
+        general_purpose.rdi = executor as u64;
+
+        ProcessRegisters {
+            general_purpose,
+            instruction_pointer: crate::process_trampoline as u64,
+            stack_pointer,
+            cpu_flags: INITIAL_CPU_FLAGS,
+            page_table: self.registers.page_table,
+        }
+    }
+
+    pub fn parent(&self) -> Option<u64> {
+        self.parent
+    }
+
+    /// Handles the oldest message of the mailbox
+    /// # Returns
+    /// false if mailbox is empty or process has finished
+    pub fn process_front_message(&mut self) -> bool {
+        if self.state == ProcessState::Finished {
+            return false;
+        }
+
+        match self.mailbox.pop_front() {
+            Some(message) => {
+                self.process.process_message(message);
+
+                true
+            },
+            None => false
         }
     }
 }
\ No newline at end of file
@@ -9,60 +9,87 @@ extern crate pic8259_simple;
 
 pub mod executor;
 pub mod process;
+pub mod stack;
 pub mod sync;
 
-use core::mem;
-use hardware::x86_64::interrupts::handler::InterruptStackFrameValue;
+use hardware::x86_64::fpu;
+use hardware::x86_64::interrupts;
+use hardware::x86_64::interrupts::handler::InterruptedContext;
 use hardware::x86_64::registers;
+use memory::address::PhysAddr;
+use memory::paging;
 
-/// Switches execution to previously stopped process.
+/// Switches execution to previously stopped process, new process is started the same way by `process_trampoline`.
 /// # Arguments
 ///  `next_process` - descriptor of the process to switch to
-///  `interrupted` - meta data of the stopped process
-pub fn switch_to_running_process(next_process : &executor::ProcessDescriptor, interrupted: &mut InterruptStackFrameValue) {
-    let next_process_registers = next_process.registers();
-
-    // New values for CS, SP and FLAGS registers will be picked automatically from `InterruptStackFrameValue` by the processor after exiting the interrupt handler
-    // The only thing we need to do here is to populate `interrupted` with the info of process to switch to.
-    interrupted.instruction_pointer = next_process_registers.instruction_pointer;
-    interrupted.stack_pointer           = next_process_registers.stack_pointer;
-    interrupted.cpu_flags                 = next_process_registers.cpu_flags;
+///  `interrupted` - registers of the stopped process, saved by naked interrupt entry
+pub fn switch_to_running_process(next_process : &executor::ProcessDescriptor, interrupted: &mut InterruptedContext) {
+    load_registers(next_process.registers(), interrupted);
+
+    // FPU state is switched on the first FPU instruction of the process, see `Executor::switch_fpu_owner`
+    unsafe { fpu::set_task_switched(); }
 }
 
-/// Starts new process.
+/// Switches execution back to kernel thread that idles when no process is ready, see `Executor::schedule_next`.
+/// Kernel thread was interrupted before the first process switch, so its registers are always known by then.
 /// # Arguments
-///  `next_process` - descriptor of the process to start
-///  # Safety
-/// Unsafe because starting new process involves unsafe memory operations
-pub unsafe fn start_new_process(new_process : &mut executor::ProcessDescriptor) {
-    // What this does is:
-    // 1) pushes new process descriptor into new process stack
-    // 2) switches SP to point to new process stack, essentially changing context of the current process to new process
-    // 3) rereads new process descriptor from new stack
-    // 4) calls process `process_message` function which now works in new process environment
-
-    let stack_address           = new_process.stack_address() as u32;
-    let descriptor_address   = new_process as *const _ as u64;
-
-    // push process descriptor pointer into new process stack
-    core::ptr::write_unaligned((stack_address - (mem::size_of::<u64>() as u32)) as *mut u64, descriptor_address);
-
-    // switches stack pointer to point to new process stack
-    // WARNING! After this line ALL local variables above will have undefined values and cannot be used anymore.
-    registers::sp_write(stack_address);
-
-    // reread stack head from register, because `stack_address` variable at the top will be undefined
-    let descriptor_address = registers::sp_read() - (mem::size_of::<u64>() as u32);
-
-    // read descriptor pointer from new process stack, because variables `descriptor_address` and `new_process` will also be undefined
-    let descriptor_pointer_raw = *(descriptor_address as *mut u64);
-
-    let new_process0 = mem::transmute::<u64, &mut executor::ProcessDescriptor>(descriptor_pointer_raw);
-
-    // execute process code
-    new_process0.process_front_message();
-
-    loop{}
+///  `executor` - executor that has no process to execute
+///  `interrupted` - registers of the stopped process, saved by naked interrupt entry
+pub fn switch_to_idle(executor : &executor::Executor, interrupted: &mut InterruptedContext) {
+    if let Some(idle_registers) = executor.idle_registers() {
+        load_registers(idle_registers, interrupted);
+    }
 }
 
+// Interrupt entry pops general purpose registers from `interrupted`, and processor picks new values for IP, SP and FLAGS
+// from its stack frame on `iretq`. The only thing we need to do here is to populate `interrupted` with the registers to switch to.
+// Kernel memory is shared between address spaces, so interrupt handler continues to work after page table switch.
+fn load_registers(registers : &executor::ProcessRegisters, interrupted: &mut InterruptedContext) {
+    if registers::cr3() != registers.page_table {
+        unsafe { paging::switch_tables(PhysAddr::new(registers.page_table as usize)); }
+    }
+
+    interrupted.registers                 = registers.general_purpose;
+    interrupted.frame.instruction_pointer = registers.instruction_pointer;
+    interrupted.frame.stack_pointer       = registers.stack_pointer;
+    interrupted.frame.cpu_flags           = registers.cpu_flags;
+}
 
+/// First code of every process. New process is switched to like any stopped process,
+/// its initial registers point here, see `ProcessDescriptor::start_registers`.
+/// Process handles messages of its mailbox one by one until it finishes, empty mailbox blocks it.
+/// # Arguments
+///  `executor` - executor that scheduled the process, current process of the executor is started
+pub(crate) extern "C" fn process_trampoline(executor : *mut executor::Executor) -> ! {
+    loop {
+        // descriptor is looked up on every message, other processes may move it while this one is stopped
+        let handled = unsafe {
+            (*executor).current_process().map_or(false, |process| process.process_front_message())
+        };
+
+        if handled {
+            continue;
+        }
+
+        // message posted between the mailbox check and blocking would be lost, so mailbox is checked again
+        // by `block_current` while timer can't switch processes
+        interrupts::disable_interrupts();
+
+        let blocked = unsafe { (*executor).block_current() };
+
+        let finished = unsafe {
+            (*executor).current_process().map_or(true, |process| *process.state() == executor::ProcessState::Finished)
+        };
+
+        if !blocked && !finished {
+            interrupts::enable_interrupts();
+
+            continue;
+        }
+
+        // process is executed until the next switch, blocked process is resumed here after a message is posted to it
+        // or after a tick that doesn't switch it, then it halts again. Interrupts are enabled only after `sti`,
+        // so timer can't fire between enabling them and halting
+        interrupts::enable_and_halt();
+    }
+}
@@ -58,6 +58,23 @@ impl Clone for ProcessRef {
     }
 }
 
+/// How process finished
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Process exited by itself, see `Executor::exit`
+    Exited(i32),
+    /// Process or one of its ancestors was killed, see `Executor::kill`
+    Killed,
+}
+
+/// Message that parent receives when its child finishes, parent joins the child by handling it
+pub struct ProcessExited {
+
+    pub id : u64,
+
+    pub status : ExitStatus
+}
+
 pub struct RemoveProcess {
     id : u64
 }
@@ -102,7 +119,7 @@ impl RootProcess {
             } else if message.is::<RemoveProcess>() {
                 let msg = message.downcast::<RemoveProcess>().unwrap();
 
-                self.executor.get().as_mut().unwrap().remove_process_with_children(msg.id);
+                self.executor.get().as_mut().unwrap().kill(msg.id);
             }
         }
     }
@@ -0,0 +1,120 @@
+use memory::address::VirtAddr;
+use memory::frame::{PhysFrame, FrameSource};
+use memory::paging::page::{Page, PAGE_SIZE};
+use memory::paging::page_table::{P4Table, EntryFlags, PRESENT, WRITABLE, NO_EXECUTE};
+use memory::paging::virtual_region::{VirtualRegion, VirtualRegions, RegionKind};
+use memory::paging::fault;
+use memory::allocator::SharedFrameAllocator;
+
+/// Start of kernel virtual memory where process stacks are mapped (P4 entry 416)
+pub const PROCESS_STACKS_START : usize = 0xffff_d000_0000_0000;
+
+/// Stack size of every process unless executor is created with another size
+pub const DEFAULT_STACK_SIZE : usize = 4 * PAGE_SIZE;
+
+// System V ABI requires 16 bytes aligned stack before call
+const STACK_ALIGNMENT : u64 = 16;
+
+/// Process stack reserved in the registry of virtual memory regions. Every page of the stack is backed right away,
+/// page fault handler can't allocate frames safely while process is inside the allocator or the timer interrupt handler.
+/// Page below the stack is reserved as guard and never mapped, so stack overflow produces page fault
+/// instead of silent overwrite of other memory.
+pub struct ProcessStack {
+    stack : VirtualRegion,
+    guard : VirtualRegion
+}
+
+impl ProcessStack {
+
+    /// Creates P3 table of the process stacks memory. Kernel entries of address spaces are copied on creation,
+    /// so it must be done before the first address space is created for stacks to be visible in every address space.
+    /// # Arguments
+    /// * `p4_table` - current p4 table
+    /// * `frame_allocator` - frame allocator
+    pub fn prepare_stacks_memory<M>(p4_table : &mut P4Table, frame_allocator : &mut M) where M : FrameSource {
+        p4_table.next_table_or_create(Page::containing_address(VirtAddr::new(PROCESS_STACKS_START)), frame_allocator);
+    }
+
+    /// Reserves stack right above its guard page and backs every page of the stack by zeroed frame
+    /// # Arguments
+    /// * `guard_address` - start of the guard page, stack starts at the next page
+    /// * `size` - size of the stack in bytes, must be multiple of page size
+    /// * `regions` - registry of reserved virtual memory
+    /// * `p4_table` - current p4 table
+    /// * `frame_allocator` - frame allocator
+    /// # Returns
+    /// None if there is no memory for the stack or no space left in the registry
+    pub fn new<M>(guard_address : VirtAddr, size : usize, regions : &mut VirtualRegions, p4_table : &mut P4Table, frame_allocator : &mut M) -> Option<ProcessStack> where M : FrameSource {
+        let guard = VirtualRegion::new(guard_address, PAGE_SIZE, EntryFlags::empty(), RegionKind::Guard);
+        let stack = VirtualRegion::new(guard_address + PAGE_SIZE, size, WRITABLE | NO_EXECUTE, RegionKind::Stack);
+
+        if !regions.reserve(guard) {
+            return None;
+        }
+
+        if !regions.reserve(stack) {
+            regions.release(guard.start_address());
+            return None;
+        }
+
+        for page in stack.pages() {
+            let frame = match frame_allocator.allocate_frame() {
+                Some(frame) => frame,
+                None => {
+                    // pages are mapped in order, so every page below this one is backed
+                    for mapped_page in Page::range_inclusive(Page::containing_address(stack.start_address()), page) {
+                        if let Some(mapped_frame) = p4_table.translate_page(mapped_page) {
+                            unsafe { p4_table.unmap_page(mapped_page); }
+
+                            frame_allocator.deallocate_frame(mapped_frame);
+                        }
+                    }
+
+                    regions.release(guard.start_address());
+                    regions.release(stack.start_address());
+                    return None;
+                }
+            };
+
+            PhysFrame::zero_frame(&frame);
+            p4_table.map_page(page, frame, stack.flags() | PRESENT, frame_allocator);
+        }
+
+        Some(ProcessStack {
+            stack,
+            guard
+        })
+    }
+
+    /// Returns top of the stack, stack grows down towards the guard page
+    pub fn top(&self) -> u64 {
+        let stack_end = self.stack.end_address().as_usize() as u64 + 1;
+
+        stack_end & !(STACK_ALIGNMENT - 1)
+    }
+
+    /// Memory of the stack
+    pub fn region(&self) -> VirtualRegion {
+        self.stack
+    }
+
+    /// Unmapped page right below the stack
+    pub fn guard(&self) -> VirtualRegion {
+        self.guard
+    }
+
+    /// Returns size of virtual memory taken by the stack and its guard page
+    pub fn reserved_size(&self) -> usize {
+        self.guard.size() + self.stack.size()
+    }
+
+    /// Removes the stack from the registry, unmaps its backed pages and returns their frames to frame allocator
+    /// # Safety
+    /// Stack must not be used after that, e.g. it must not be the current stack
+    pub unsafe fn release<M>(self, regions : &mut VirtualRegions, p4_table : &mut P4Table, frame_allocator : &mut M) where M : SharedFrameAllocator {
+        regions.release(self.guard.start_address());
+        regions.release(self.stack.start_address());
+
+        fault::release_region(p4_table, &self.stack, frame_allocator);
+    }
+}
@@ -10,18 +10,24 @@ use hardware::x86_64::interrupts::idt::{
     InterruptTableEntry
 };
 use hardware::x86_64::interrupts::pic;
+use hardware::x86_64::gdt;
+use hardware::x86_64::gdt::{GlobalDescriptorTable, TaskStateSegment};
 use memory::allocator::slab::{
     SlabHelp,
     SlabAllocator
 };
-use memory::allocator::bump::ConstSizeBumpAllocator;
+use memory::allocator::zone::{Zone, ZonedFrameAllocator};
 use memory::frame::{
-    Frame,
+    PhysFrame,
     FRAME_SIZE
 };
+use memory::frame::handover::MemoryHandover;
+use memory::frame::regions::MemoryRegions;
 use memory::paging;
-use memory::paging::page_table;
-use multiboot::multiboot_header::MultibootHeader;
+use memory::paging::page::Page;
+use memory::paging::virtual_region::VirtualRegions;
+use memory::address;
+use memory::address::VirtAddr;
 use crate::interrupts::handlers;
 
 
@@ -33,45 +39,132 @@ pub static mut INTERRUPT_TABLE: InterruptTable = InterruptTable::new();
 
 pub static mut CHAINED_PICS: ChainedPics = unsafe { pic::new() } ;
 
+pub static mut GLOBAL_DESCRIPTOR_TABLE: GlobalDescriptorTable = GlobalDescriptorTable::new();
+
+pub static mut TASK_STATE_SEGMENT: TaskStateSegment = TaskStateSegment::new();
+
+/// Interrupt stack table index of page fault handler stack
+pub const PAGE_FAULT_STACK_INDEX : u16 = 1;
+
+/// Interrupt stack table index of double fault handler stack
+pub const DOUBLE_FAULT_STACK_INDEX : u16 = 2;
+
+const INTERRUPT_STACK_SIZE : usize = 4 * FRAME_SIZE;
+
+// fault handlers run on their own stacks, current stack may be the one that overflowed into guard page
+static mut PAGE_FAULT_STACK : [u8; INTERRUPT_STACK_SIZE] = [0; INTERRUPT_STACK_SIZE];
+static mut DOUBLE_FAULT_STACK : [u8; INTERRUPT_STACK_SIZE] = [0; INTERRUPT_STACK_SIZE];
+
+/// Reserved regions of kernel virtual memory, e.g. process stacks of the executor. Pages of regions that aren't backed on creation are backed by frames on page fault
+pub static mut VIRTUAL_REGIONS: VirtualRegions = VirtualRegions::new();
+
+/// Frame allocator of memory with address constraints, e.g. buffers of DMA capable devices.
+/// Its memory is carved out of memory handover before the heap is created, so the heap never uses it
+pub static mut ZONED_FRAME_ALLOCATOR: Option<ZonedFrameAllocator> = None;
+
+// memory reserved for drivers in zones with address constraints, Normal zone memory is left to the heap
+const DMA_ZONE_SIZE : usize = 1024 * 1024;
+const DMA32_ZONE_SIZE : usize = 16 * 1024 * 1024;
+
 #[global_allocator]
 pub static mut HEAP_ALLOCATOR: SlabHelp = SlabHelp { value : ptr::NonNull::dangling() };
 
 pub unsafe fn initialize_interrupt_table() {
+    initialize_task_state_segment();
 
-    INTERRUPT_TABLE.double_fault = InterruptTableEntry::create_present_entry1(handlers::double_fault_handler);
-    INTERRUPT_TABLE.page_fault = InterruptTableEntry::create_present_entry1(handlers::page_fault_handler);
+    INTERRUPT_TABLE.double_fault = InterruptTableEntry::create_present_entry1(handlers::double_fault_handler)
+        .with_stack_index(DOUBLE_FAULT_STACK_INDEX);
+    INTERRUPT_TABLE.page_fault = InterruptTableEntry::create_present_entry1(handlers::page_fault_handler)
+        .with_stack_index(PAGE_FAULT_STACK_INDEX);
     INTERRUPT_TABLE.divide_by_zero = InterruptTableEntry::create_present_entry(handlers::divide_by_zero_handler);
+    INTERRUPT_TABLE.device_not_available = InterruptTableEntry::create_present_entry(handlers::device_not_available_handler);
 
-    INTERRUPT_TABLE.set_interrupt_handler(HardwareInterrupts::Timer as usize, handlers::timer_interrupt_handler);
+    // timer entry saves every register of interrupted process, so it can't be `x86-interrupt` function
+    INTERRUPT_TABLE.set_naked_interrupt_handler(HardwareInterrupts::Timer as usize, handlers::timer_interrupt_entry);
 
     CHAINED_PICS.initialize();
 }
 
-pub fn initialize_memory_allocator(multiboot_header : &MultibootHeader) -> SlabAllocator {
-    let (memory_start, memory_end1) = multiboot_header.biggest_memory_area();
-    let memory_end = memory_start + 31457280; //30 mb, something bigger than that produces 0x6 crash
-    let total_memory = memory_end - memory_start + 1;
+// replaces boot descriptor table with the one that also describes task state segment with fault handler stacks
+unsafe fn initialize_task_state_segment() {
+    TASK_STATE_SEGMENT.set_interrupt_stack(PAGE_FAULT_STACK_INDEX as usize, interrupt_stack_top(&PAGE_FAULT_STACK));
+    TASK_STATE_SEGMENT.set_interrupt_stack(DOUBLE_FAULT_STACK_INDEX as usize, interrupt_stack_top(&DOUBLE_FAULT_STACK));
 
-    let aux_structures_start_address = preallocate_memory_for_allocator_aux_data_structures(memory_start, memory_end);
+    // code segment has the same selector as in boot table, so CS doesn't have to be reloaded
+    GLOBAL_DESCRIPTOR_TABLE.add_kernel_code_segment();
+    let task_state_segment_selector = GLOBAL_DESCRIPTOR_TABLE.add_task_state_segment(&TASK_STATE_SEGMENT);
 
-    SlabAllocator::new(aux_structures_start_address + 4096, total_memory, memory_end)
+    GLOBAL_DESCRIPTOR_TABLE.load();
+    gdt::load_task_register(task_state_segment_selector);
 }
 
-fn preallocate_memory_for_allocator_aux_data_structures(memory_start : usize, memory_end : usize) -> usize {
-    let aux_data_structures_size = SlabAllocator::total_aux_data_structures_size(memory_start, memory_end);
+fn interrupt_stack_top(stack : &[u8; INTERRUPT_STACK_SIZE]) -> u64 {
+    // processor aligns stack by 16 bytes before pushing interrupt frame
+    (stack.as_ptr() as u64 + INTERRUPT_STACK_SIZE as u64) & !0xf
+}
 
-    let premade_bump_end_address  = Frame::address_align_up(memory_start + aux_data_structures_size);
-    let mut premade_bump                = ConstSizeBumpAllocator::from_address(memory_start, premade_bump_end_address, FRAME_SIZE);
+/// Creates allocator of memory with address constraints for device drivers, see `ZONED_FRAME_ALLOCATOR`.
+/// Must be called before `initialize_memory_allocator`, memory of the zones is taken out of free regions.
+/// # Arguments
+/// * `handover` - memory returned by `FrameAllocator::handover`
+pub fn initialize_zoned_frame_allocator(handover : &mut MemoryHandover) {
+    let mut regions = MemoryRegions::new();
+
+    // zone may have less memory than requested or no memory at all, allocation falls back to lower zones then
+    for &(zone, size) in [(Zone::Dma, DMA_ZONE_SIZE), (Zone::Dma32, DMA32_ZONE_SIZE)].iter() {
+        if let Some(range) = handover.take_zone(zone, size) {
+            regions.add(range.start_address(), range.size());
+        }
+    }
 
-    // |aux structures page tables|aux structures working memory|allocator working memory|
-    // premap memory for memory allocator inner data structures
-    let aux_structures_start_address = premade_bump_end_address + FRAME_SIZE; // next frame
-    let aux_structures_end_address = Frame::address_align_up(aux_structures_start_address + aux_data_structures_size);
+    if regions.is_empty() {
+        return;
+    }
 
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
-        let p4_table = paging::p4_table();
-        p4_table.map_page_1_to_1(frame, page_table::PRESENT | page_table::WRITABLE, &mut premade_bump);
+    let aux_data_structures_size = ZonedFrameAllocator::aux_data_structures_size_for(&regions);
+    let aux_structures_start_address = preallocate_memory_for_aux_data_structures(handover, aux_data_structures_size);
+
+    unsafe {
+        ZONED_FRAME_ALLOCATOR = Some(ZonedFrameAllocator::from_regions(&regions, aux_structures_start_address, address::PHYSICAL_MEMORY_OFFSET));
+    }
+}
+
+/// Creates heap allocator of memory that boot frame allocator handed over, multiboot information
+/// can be reclaimed by the frame allocator of the heap later
+/// # Arguments
+/// * `handover` - memory returned by `FrameAllocator::handover`
+pub fn initialize_memory_allocator(handover : &mut MemoryHandover) -> SlabAllocator {
+    // multiboot information may lie above every free region, its frames must be indexed to be reclaimed
+    let managed_regions = handover.managed_regions();
+    let aux_data_structures_size = SlabAllocator::aux_data_structures_size_for(&managed_regions);
+    let aux_structures_start_address = preallocate_memory_for_aux_data_structures(handover, aux_data_structures_size);
+
+    // allocator works with addresses of physical memory window, so its memory is already mapped
+    let mut allocator = SlabAllocator::from_managed_regions(&managed_regions, handover.free_regions(), aux_structures_start_address, address::PHYSICAL_MEMORY_OFFSET);
+
+    // frame allocator users, e.g. page fault handler, get frames of cached slabs when memory runs out
+    allocator.frame_allocator().set_reclaim_handler(reclaim_heap_frames);
+
+    allocator
+}
+
+// returns frames of cached empty slabs of the heap to its frame allocator
+fn reclaim_heap_frames() -> usize {
+    unsafe {
+        if HEAP_ALLOCATOR.value == ptr::NonNull::dangling() {
+            0
+        }
+        else {
+            HEAP_ALLOCATOR.shrink()
+        }
     }
+}
+
+fn preallocate_memory_for_aux_data_structures(handover : &mut MemoryHandover, aux_data_structures_size : usize) -> usize {
+    // aux structures memory is taken out of free regions, so allocator never hands it out
+    let aux_structures_start = handover.take(aux_data_structures_size).expect("No memory for allocator aux data structures");
+    let aux_structures_start_address = address::physical_to_virtual(aux_structures_start).as_usize();
+    let aux_structures_end_address = aux_structures_start_address + PhysFrame::address_align_up(aux_data_structures_size) - 1;
 
     test_allocator_aux_data_structures_memory(aux_structures_start_address, aux_structures_end_address);
 
@@ -79,12 +172,15 @@ fn preallocate_memory_for_allocator_aux_data_structures(memory_start : usize, me
 }
 
 fn test_allocator_aux_data_structures_memory(aux_structures_start_address : usize, aux_structures_end_address : usize) {
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
+    let start_page = Page::containing_address(VirtAddr::new(aux_structures_start_address));
+    let end_page = Page::containing_address(VirtAddr::new(aux_structures_end_address));
+
+    for page in Page::range_inclusive(start_page, end_page) {
         let p4_table = paging::p4_table();
-        let present = p4_table.is_present(frame);
+        let present = p4_table.is_present(page);
 
-        unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Is present {}, val {}", frame, present); }
+        unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Is present {}, val {}", page, present); }
 
-        Frame::zero_frame(&frame);
+        unsafe { ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, FRAME_SIZE); }
     }
 }
\ No newline at end of file
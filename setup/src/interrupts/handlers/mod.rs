@@ -1,4 +1,5 @@
 use core::fmt::Write;
+use core::intrinsics;
 
 use hardware::x86_64::registers;
 use hardware::x86_64::interrupts;
@@ -10,13 +11,21 @@ use hardware::x86_64::interrupts::InterruptTableHelp;
 use hardware::x86_64::interrupts::handler::{
     InterruptHandler,
     InterruptHandlerWithErrorCode,
-    InterruptStackFrameValue
+    InterruptStackFrameValue,
+    InterruptedContext,
+    PageFaultErrorCode
 };
 use hardware::x86_64::interrupts::pic;
+use memory::address::VirtAddr;
+use memory::paging;
+use memory::paging::fault;
+use memory::paging::fault::{FaultResolution, FaultReason};
 use multiprocess::executor;
 use crate::globals::{
    CHAINED_PICS,
-    PROCESS_EXECUTOR
+    PROCESS_EXECUTOR,
+    HEAP_ALLOCATOR,
+    VIRTUAL_REGIONS
 };
 
 use crate::globals::VGA_WRITER;
@@ -33,17 +42,121 @@ pub extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: &mut Interrupt
     unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "INVALID OPCODE OCCURED"); }
 }
 
+/// Instruction fetch fault armed by in-kernel tests that deliberately jump into non executable memory
+#[repr(C)]
+pub struct ExpectedFetchFault {
+    /// Address of the jump target, zero if no fault is expected
+    pub address : u64,
+    /// Execution resumes here after the expected fault
+    pub recovery_address : u64,
+    /// Number of expected faults that were handled
+    pub handled : usize
+}
+
+/// Fault that page fault handler recovers from instead of reporting it, see `ExpectedFetchFault`
+pub static mut EXPECTED_FETCH_FAULT : ExpectedFetchFault = ExpectedFetchFault {
+    address : 0,
+    recovery_address : 0,
+    handled : 0
+};
+
 pub extern "x86-interrupt" fn page_fault_handler(stack_frame: &mut InterruptStackFrameValue, code : u64) {
-    unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "PAGE FAULT OCCURED"); }
+    let error_code = PageFaultErrorCode::from_bits_truncate(code);
+
+    unsafe {
+        let faulted_address = VirtAddr::new(registers::cr2() as usize);
+        let frame_allocator = HEAP_ALLOCATOR.value.as_mut().frame_allocator();
+
+        let resolution = fault::handle_page_fault(paging::p4_table(),
+                                                  &VIRTUAL_REGIONS,
+                                                  faulted_address,
+                                                  stack_frame.instruction_pointer as usize,
+                                                  error_code,
+                                                  frame_allocator);
+
+        match resolution {
+            // page is backed now, faulted instruction will be restarted
+            FaultResolution::Mapped(_, _) | FaultResolution::CopiedOnWrite(_, _) => (),
+            // fault is proven by processor, jump is abandoned and test continues at its recovery address
+            FaultResolution::Invalid(ref report) if report.reason == FaultReason::ExecuteNoExecute &&
+                EXPECTED_FETCH_FAULT.address == faulted_address.as_usize() as u64 => {
+                EXPECTED_FETCH_FAULT.address = 0;
+                EXPECTED_FETCH_FAULT.handled += 1;
+
+                stack_frame.instruction_pointer = EXPECTED_FETCH_FAULT.recovery_address;
+            },
+            FaultResolution::Invalid(report) => {
+                if PROCESS_EXECUTOR.is_initialized() {
+                    if let Some(id) = PROCESS_EXECUTOR.stack_overflow_process(faulted_address) {
+                        writeln!(VGA_WRITER.as_mut().unwrap(), "Stack overflow in process {}", id);
+                    }
+                }
+
+                write!(VGA_WRITER.as_mut().unwrap(), "{}", report);
+
+                loop {}
+            }
+        }
+    }
 }
 
 pub extern "x86-interrupt" fn double_fault_handler(stack_frame: &mut InterruptStackFrameValue, error_code : u64) {
     unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "DOUBLE FAULT OCCURED"); }
 }
 
+pub extern "x86-interrupt" fn device_not_available_handler(stack_frame: &mut InterruptStackFrameValue) {
+    // raised by the first FPU instruction after process switch, see `multiprocess::switch_to_running_process`
+    unsafe { PROCESS_EXECUTOR.switch_fpu_owner(); }
+}
+
+/// Timer interrupt entry. Saves every general purpose register of interrupted process on its stack,
+/// so `timer_interrupt_handler` can replace them with registers of the next process.
+#[naked]
+pub unsafe extern "C" fn timer_interrupt_entry() {
+    asm!("push rax
+          push rbx
+          push rcx
+          push rdx
+          push rsi
+          push rdi
+          push rbp
+          push r8
+          push r9
+          push r10
+          push r11
+          push r12
+          push r13
+          push r14
+          push r15
+          mov rdi, rsp
+          call $0
+          pop r15
+          pop r14
+          pop r13
+          pop r12
+          pop r11
+          pop r10
+          pop r9
+          pop r8
+          pop rbp
+          pop rdi
+          pop rsi
+          pop rdx
+          pop rcx
+          pop rbx
+          pop rax
+          iretq"
+          :: "i" (timer_interrupt_handler as extern "C" fn(&mut InterruptedContext))
+          : "memory" : "intel", "volatile");
+
+    intrinsics::unreachable();
+}
+
 static mut timer_ctr : usize = 0;
 
-pub extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: &mut InterruptStackFrameValue) {
+// processor pushes 5 words of interrupt frame and entry pushes 15 registers,
+// so stack stays 16 bytes aligned for the call
+extern "C" fn timer_interrupt_handler(context: &mut InterruptedContext) {
     unsafe {
 
         if timer_ctr > 40 { // emulates tick every 3 secs
@@ -52,41 +165,30 @@ pub extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: &mut Interrup
 
             writeln!(VGA_WRITER.as_mut().unwrap(), "TICK");
 
-            writeln!(VGA_WRITER.as_mut().unwrap(), "Tick interrupt frame {:?}", stack_frame);
-
-            let interrupted_process_registers = executor::ProcessRegisters {
-                instruction_pointer: stack_frame.instruction_pointer,
-                stack_pointer: stack_frame.stack_pointer,
-                cpu_flags: stack_frame.cpu_flags,
-            };
-
-            PROCESS_EXECUTOR.update_current_process(interrupted_process_registers);
-
-            if let Some(next) = PROCESS_EXECUTOR.schedule_next() {
-
-                match next.state() {
-                    executor::ProcessState::Running => {
+            writeln!(VGA_WRITER.as_mut().unwrap(), "Tick interrupt frame {:?}", context.frame);
 
-                        multiprocess::switch_to_running_process(next, stack_frame);
+            PROCESS_EXECUTOR.update_current_process(executor::ProcessRegisters::from_context(context));
 
-                        CHAINED_PICS.notify_end_of_interrupt(HardwareInterrupts::Timer as u8);
-                    },
-                    executor::ProcessState::New => {
-                        hardware::x86_64::interrupts::disable_interrupts();
+            let switched = match PROCESS_EXECUTOR.schedule_next() {
+                Some(next) => {
+                    // new process was given registers that start it, so it is switched to the same way
+                    multiprocess::switch_to_running_process(next, context);
 
-                        hardware::x86_64::interrupts::enable_interrupts();
-
-                        CHAINED_PICS.notify_end_of_interrupt(HardwareInterrupts::Timer as u8);
+                    true
+                },
+                None => false
+            };
 
-                        multiprocess::start_new_process(next);
-                    },
-                    _ => ()
-                }
+            // every process is blocked or finished
+            if !switched {
+                multiprocess::switch_to_idle(&PROCESS_EXECUTOR, context);
             }
+
+            CHAINED_PICS.notify_end_of_interrupt(HardwareInterrupts::Timer as u8);
         } else {
             timer_ctr += 1;
 
             CHAINED_PICS.notify_end_of_interrupt(HardwareInterrupts::Timer as u8);
         }
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,8 @@
 #![no_std]
 #![feature(abi_x86_interrupt)]
+#![feature(asm)]
+#![feature(naked_functions)]
+#![feature(core_intrinsics)]
 
 extern crate hardware;
 extern crate multiprocess;
@@ -43,6 +43,12 @@ pub trait MemoryAllocator : MemoryAllocatorMeta {
 
     fn allocate(&mut self, size : usize) -> Option<usize>;
 
+    /// Allocates memory block which start address is multiple of `align`
+    /// # Arguments
+    /// * `size` - size of memory block
+    /// * `align` - alignment of memory block, must be power of 2
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize>;
+
     fn allocate_for<T>(&mut self) -> Option<usize> {
         self.allocate(mem::size_of::<T>())        
     }    
@@ -55,6 +61,20 @@ pub trait ConstantSizeMemoryAllocator : MemoryAllocatorMeta {
 
     fn allocate_size(&mut self) -> Option<usize>;    
 
+    /// Allocates block which start address is multiple of `align`. Blocks have constant size
+    /// and can't be moved, so allocation fails if picked block is not aligned
+    fn allocate_size_aligned(&mut self, align : usize) -> Option<usize> {
+        let result = self.allocate_size()?;
+
+        if result % align == 0 {
+            Some(result)
+        }
+        else {
+            self.free_size(result);
+            None
+        }
+    }
+
     fn free_size(&mut self, pointer : usize);
 }
 
@@ -64,6 +84,10 @@ impl<T> MemoryAllocator for T where T: ConstantSizeMemoryAllocator
         self.allocate_size()
     }
 
+    fn allocate_aligned(&mut self, size : usize, align : usize) -> Option<usize> {
+        self.allocate_size_aligned(align)
+    }
+
     fn free(&mut self, pointer : usize) {
         self.free_size(pointer)
     }
@@ -1,3 +1,6 @@
+; kernel is linked at KERNEL_VIRTUAL_BASE + physical address, must match memory::address::KERNEL_VIRTUAL_BASE
+KERNEL_VIRTUAL_BASE equ 0xFFFFFF0000000000
+
 section .multiboot_header
 header_start:
     dd 0xe85250d6                ; magic number (multiboot 2)
@@ -34,16 +37,20 @@ gdt64:
     dq 0 ; zero entry
 .codeSeg: equ $ - gdt64    
     dq (1<<43) | (1<<44) | (1<<47) | (1<<53) ; code segment
-.meta:
+gdt64_meta:
     dw $ - gdt64 - 1
     dq gdt64
 
+global gdt64_meta
+global stack_top
 global start
 extern long_mode_start
-section .text
+; code below runs before paging is enabled, it is linked at physical addresses
+; and has to access higher half symbols through their physical addresses
+section .boot
 bits 32
 start:    
-    mov esp, stack_top
+    mov esp, stack_top - KERNEL_VIRTUAL_BASE
     mov edi, ebx ; ebx contains multiboot header info, it will be read by os
     
     call check_cpuid
@@ -51,7 +58,7 @@ start:
     call set_up_page_tables
     call enable_paging
 
-    lgdt [gdt64.meta]
+    lgdt [boot_gdt_meta]
     jmp gdt64.codeSeg:long_mode_start
     
     hlt
@@ -115,20 +122,24 @@ check_long_mode:
 
 set_up_page_tables:
     ; map P4 table recursively
-    mov eax, p4_table
+    mov eax, p4_table - KERNEL_VIRTUAL_BASE
     or eax, 0b11 ; present + writable
-    mov [p4_table + 511 * 8], eax ;set recursive entry
-
+    mov [p4_table - KERNEL_VIRTUAL_BASE + 511 * 8], eax ;set recursive entry
 
-    ; map first P4 entry to P3 table
-    mov eax, p3_table
+    ; temporary mapping, the same first 1gb of physical memory is visible at:
+    ; P4 entry 0 - identity mapping, boot code runs here until jump to higher half
+    ; P4 entry 256 - physical memory window
+    ; P4 entry 510 - kernel
+    mov eax, p3_table - KERNEL_VIRTUAL_BASE
     or eax, 0b11 ; present + writable
-    mov [p4_table], eax
+    mov [p4_table - KERNEL_VIRTUAL_BASE], eax
+    mov [p4_table - KERNEL_VIRTUAL_BASE + 256 * 8], eax
+    mov [p4_table - KERNEL_VIRTUAL_BASE + 510 * 8], eax
 
     ; map first P3 entry to P2 table
-    mov eax, p2_table
+    mov eax, p2_table - KERNEL_VIRTUAL_BASE
     or eax, 0b11 ; present + writable
-    mov [p3_table], eax
+    mov [p3_table - KERNEL_VIRTUAL_BASE], eax
 
     mov ecx, 0         ; counter variable
 
@@ -138,7 +149,7 @@ set_up_page_tables:
     mov eax, 0x200000  ; 2MiB
     mul ecx            ; start address of ecx-th page
     or eax, 0b10000011 ; present + writable + huge
-    mov [p2_table + ecx * 8], eax ; map ecx-th entry
+    mov [p2_table - KERNEL_VIRTUAL_BASE + ecx * 8], eax ; map ecx-th entry
 
     inc ecx            ; increase counter
     cmp ecx, 512       ; if counter == 512, the whole P2 table is mapped
@@ -147,7 +158,7 @@ set_up_page_tables:
 
 enable_paging:
     ; load P4 to cr3 register (cpu uses this to access the P4 table)
-    mov eax, p4_table
+    mov eax, p4_table - KERNEL_VIRTUAL_BASE
     mov cr3, eax
 
     ; enable PAE-flag in cr4 (Physical Address Extension)
@@ -167,3 +178,8 @@ enable_paging:
     mov cr0, eax
 
     ret
+
+; gdt pointer with physical address of gdt64, 32 bit lgdt can't load higher half address
+boot_gdt_meta:
+    dw gdt64_meta - gdt64 - 1
+    dd gdt64 - KERNEL_VIRTUAL_BASE
@@ -1,6 +1,9 @@
 global long_mode_start
+extern gdt64_meta
+extern stack_top
 
-section .text
+; still runs at physical address, identity mapping set up by boot.asm is active
+section .boot
 bits 64
 long_mode_start:
 
@@ -11,5 +14,16 @@ long_mode_start:
     mov fs, ax
     mov gs, ax
 
-    extern rust_main     
-    call rust_main    
\ No newline at end of file
+    ; absolute jump, relative one would stay in the lower half
+    mov rax, qword higher_half_start
+    jmp rax
+
+section .text
+bits 64
+higher_half_start:
+    ; reload gdt and stack with their higher half addresses, identity mapping is dropped by remap_kernel
+    lgdt [rel gdt64_meta]
+    mov rsp, qword stack_top
+
+    extern rust_main
+    call rust_main
@@ -19,15 +19,18 @@ extern crate setup;
 use multiboot::multiboot_header::MultibootHeader;
 use multiboot::multiboot_header::tags::{basic_memory_info, elf, memory_map};
 use multiboot::multiboot_header::tags::memory_map::*;
-use display::vga::writer::Writer;
+use display::vga::writer::{Writer, VGA_ADDRESS};
 use memory::allocator::bump::BumpAllocator;
-use memory::allocator::bump::ConstSizeBumpAllocator;
 use memory::frame::frame_allocator::*;
-use memory::frame::Frame;
+use memory::frame::{PhysFrame, FrameSource};
 use memory::frame::FRAME_SIZE;
 use memory::paging;
 use memory::paging::page_table;
 use memory::paging::page_table::P4Table;
+use memory::paging::page::{Page, PageSize, HUGE_PAGE_SIZE_2M};
+use memory::paging::virtual_region::VirtualRegions;
+use memory::address;
+use memory::address::{VirtAddr, PhysAddr};
 use stdx_memory::MemoryAllocator;
 use stdx_memory::MemoryAllocatorMeta;
 use core::clone::Clone;
@@ -55,6 +58,7 @@ use stdx_memory::heap;
 use multiprocess::process::{Process, Message};
 use multiprocess::executor;
 use multiprocess::process;
+use multiprocess::stack;
 use pic8259_simple::ChainedPics;
 
 use setup::interrupts::handlers;
@@ -64,7 +68,9 @@ use setup::globals::{
     PROCESS_EXECUTOR,
     INTERRUPT_TABLE,
     CHAINED_PICS,
-    HEAP_ALLOCATOR
+    HEAP_ALLOCATOR,
+    VIRTUAL_REGIONS,
+    ZONED_FRAME_ALLOCATOR
 };
 
 #[no_mangle]
@@ -72,27 +78,60 @@ use setup::globals::{
 pub extern "C" fn rust_main(multiboot_header_address: usize) {
     unsafe {
 
-        let multiboot_header = MultibootHeader::load(multiboot_header_address);
+        // boot code passes physical address, it is accessible through physical memory window
+        let multiboot_header = MultibootHeader::load(address::physical_to_virtual(PhysAddr::new(multiboot_header_address)).as_usize());
 
-        VGA_WRITER = Some(Writer::new());
+        VGA_WRITER = Some(Writer::from_address(address::physical_to_virtual(PhysAddr::new(VGA_ADDRESS)).as_usize()));
 
         //print_multiboot_data(multiboot_header, VGA_WRITERG.as_mut().unwrap());
 
         let mut frame_allocator = FrameAllocator::new(multiboot_header);
 
-        paging::remap_kernel(&mut paging::p4_table(), &mut frame_allocator, multiboot_header);
+        // must be enabled before remap, new tables contain NO_EXECUTE entries
+        registers::enable_nxe_bit();
+        registers::enable_write_protect_bit();
 
-        let mut slab_allocator = globals::initialize_memory_allocator(&multiboot_header);
+        let boot_page_table = paging::remap_kernel(&mut paging::p4_table(), &mut frame_allocator, multiboot_header);
+
+        //print_page_table_mappings(paging::p4_table(), VGA_WRITER.as_mut().unwrap());
+
+        let mut memory_handover = frame_allocator.handover(boot_page_table);
+
+        // memory of device buffers is carved out before the heap takes the rest
+        globals::initialize_zoned_frame_allocator(&mut memory_handover);
+
+        let mut slab_allocator = globals::initialize_memory_allocator(&mut memory_handover);
 
         HEAP_ALLOCATOR.value = ptr::NonNull::new_unchecked(&mut slab_allocator as *mut SlabAllocator);
 
         memory_allocator_should_properly_allocate_and_free_memory();
 
+        // multiboot information isn't read after this point
+        memory_handover.reclaim_multiboot(slab_allocator.frame_allocator());
+
         globals::initialize_interrupt_table();
 
         interrupts::load_interrupt_table(&INTERRUPT_TABLE);
 
-        let mut executor = Rc::new(cell::UnsafeCell::new(executor::Executor::new()));
+        // run pre-init tests, they switch page tables and use frame allocator of the heap directly,
+        // so they run before processes are created and timer interrupt is enabled
+        let p4_table = paging::p4_table();
+
+        /*paging_map_should_properly_map_pages(p4_table, slab_allocator.frame_allocator(), &mut vga_writer);
+        paging_translate_page_should_properly_translate_pages(p4_table, slab_allocator.frame_allocator());
+        paging_unmap_should_properly_unmap_elements(p4_table, slab_allocator.frame_allocator());
+        paging_translate_address_should_properly_translate_virtual_address(p4_table, slab_allocator.frame_allocator());*/
+        paging_map_huge_2m_should_translate_and_split_on_partial_unmap(p4_table, slab_allocator.frame_allocator());
+        paging_no_execute_should_page_fault_on_jump_into_data_page();
+        address_space_should_isolate_private_memory(slab_allocator.frame_allocator());
+        address_space_fork_should_copy_private_memory_on_write(slab_allocator.frame_allocator());
+        demand_paging_should_back_reserved_pages_on_access(slab_allocator.frame_allocator());
+        process_stack_should_be_backed_above_unmapped_guard_page(slab_allocator.frame_allocator());
+        zoned_frame_allocator_should_allocate_buffers_below_zone_end();
+
+        let process_frame_allocator = ptr::NonNull::new_unchecked(slab_allocator.frame_allocator() as *mut BuddyAllocator);
+        let virtual_regions = ptr::NonNull::new_unchecked(&mut VIRTUAL_REGIONS as *mut VirtualRegions);
+        let mut executor = Rc::new(cell::UnsafeCell::new(executor::Executor::new(process_frame_allocator, virtual_regions, stack::DEFAULT_STACK_SIZE)));
 
         PROCESS_EXECUTOR.value =  ptr::NonNull::new_unchecked(&mut executor as *mut executor::ExecutorRef);
 
@@ -105,6 +144,9 @@ pub extern "C" fn rust_main(multiboot_header_address: usize) {
         PROCESS_EXECUTOR.create_process( dummy_process_state_box);
         PROCESS_EXECUTOR.post_message(0, Box::new(IncreaseCtr { some : 299}));
 
+        context_switch_should_preserve_registers_of_processes();
+        process_exit_and_kill_should_notify_parent();
+
         /*let mut root_process = process::RootProcess::new(Rc::clone(&executor));
 
         let sample_process = SampleProcess {
@@ -137,15 +179,11 @@ pub extern "C" fn rust_main(multiboot_header_address: usize) {
 
         hardware::x86_64::interrupts::enable_interrupts();
 
-        // run pre-init tests
-        let p4_table = paging::p4_table();
-
-        /*paging_map_should_properly_map_pages(p4_table, slab_allocator.frame_allocator(), &mut vga_writer);
-        paging_translate_page_should_properly_translate_pages(p4_table, slab_allocator.frame_allocator());
-        paging_unmap_should_properly_unmap_elements(p4_table, slab_allocator.frame_allocator());
-        paging_translate_address_should_properly_translate_virtual_address(p4_table, slab_allocator.frame_allocator());*/
+        // main thread becomes idle thread, it is executed when no process is ready
+        // and frees memory of finished processes outside of interrupt handlers
         loop {
-            unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Main thread end loop!"); };
+            PROCESS_EXECUTOR.release_finished_processes();
+            interrupts::halt();
         }
     }
 }
@@ -174,12 +212,7 @@ impl DummyProcess {
 
 impl Process for DummyProcess {
     fn process_message(&mut self, message: Message) -> () {
-        unsafe {
-            self.process_message1(message);
-            loop {
-                // unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Inside dummy end"); }
-            }
-        }
+        self.process_message1(message);
     }
 }
 
@@ -204,7 +237,152 @@ impl Process for SenderProcess {
     }
 }
 
+// number of loop iterations, big enough to be interrupted by several process switches
+const REGISTER_COUNTER_ITERATIONS : u64 = 1 << 28;
+
+/// Counts in general purpose registers only, so result is correct only if every register
+/// of the process survives process switches
+pub struct RegisterCounterProcess {
+    start : u64
+}
+
+pub struct StartCounting {}
+
+impl RegisterCounterProcess {
+    // every register starts from `start` and is increased by its own step on every iteration
+    fn count_in_registers(&self) -> [u64; 13] {
+        let mut r = [self.start; 13];
+        let mut counter = REGISTER_COUNTER_ITERATIONS;
+
+        unsafe {
+            asm!("2:
+                  add rax, 1
+                  add rbx, 2
+                  add rdx, 3
+                  add rsi, 4
+                  add rdi, 5
+                  add r8, 6
+                  add r9, 7
+                  add r10, 8
+                  add r11, 9
+                  add r12, 10
+                  add r13, 11
+                  add r14, 12
+                  add r15, 13
+                  dec rcx
+                  jnz 2b"
+                 : "+{rax}" (r[0]), "+{rbx}" (r[1]), "+{rdx}" (r[2]), "+{rsi}" (r[3]), "+{rdi}" (r[4]),
+                   "+{r8}" (r[5]), "+{r9}" (r[6]), "+{r10}" (r[7]), "+{r11}" (r[8]), "+{r12}" (r[9]),
+                   "+{r13}" (r[10]), "+{r14}" (r[11]), "+{r15}" (r[12]), "+{rcx}" (counter)
+                 :: "cc" : "intel", "volatile");
+        }
+
+        r
+    }
+}
+
+impl Process for RegisterCounterProcess {
+    fn process_message(&mut self, message: Message) -> () {
+        if message.is::<StartCounting>() {
+            let result = self.count_in_registers();
+
+            for (i, value) in result.iter().enumerate() {
+                let step = i as u64 + 1;
+
+                assert_eq!(*value, self.start.wrapping_add(step.wrapping_mul(REGISTER_COUNTER_ITERATIONS)),
+                    "Register {} of process counting from {} was corrupted by process switch", i, self.start);
+            }
+
+            unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Process counting from {} kept its registers", self.start); }
+        }
+    }
+}
+
+fn context_switch_should_preserve_registers_of_processes() {
+    // both processes use the same registers with different values
+    for &start in [0, 1 << 40].iter() {
+        unsafe {
+            let id = PROCESS_EXECUTOR.create_process(Box::new(RegisterCounterProcess { start }));
+
+            PROCESS_EXECUTOR.post_message(id, Box::new(StartCounting {}));
+        }
+    }
+}
+
+// exit status of the child that exits by itself
+const CHILD_EXIT_CODE : i32 = 7;
+
+/// Forks two children, one exits by itself and the other one is killed, then checks exit notifications
+pub struct ParentProcess {
+    exited : Vec<(u64, process::ExitStatus)>,
+
+    children : Vec<u64>
+}
+
+/// Exits on the first message
+pub struct ExitingChild {}
+
+/// Never receives messages, waits until it is killed
+pub struct WaitingChild {}
+
+impl Process for ExitingChild {
+    fn process_message(&mut self, _message: Message) -> () {
+        unsafe { PROCESS_EXECUTOR.exit(process::ExitStatus::Exited(CHILD_EXIT_CODE)); }
+    }
+}
+
+impl Process for WaitingChild {
+    fn process_message(&mut self, _message: Message) -> () {
+        panic!("Waiting child received a message");
+    }
+}
+
+impl Process for ParentProcess {
+    fn process_message(&mut self, message: Message) -> () {
+        unsafe {
+            if message.is::<process::StartProcess>() {
+                let parent_id = PROCESS_EXECUTOR.current_process_id().unwrap();
+
+                let exiting = PROCESS_EXECUTOR.fork(parent_id, Box::new(ExitingChild {})).unwrap();
+                let waiting = PROCESS_EXECUTOR.fork(parent_id, Box::new(WaitingChild {})).unwrap();
+
+                PROCESS_EXECUTOR.post_message(exiting, Box::new(process::StartProcess {}));
+
+                assert!(PROCESS_EXECUTOR.kill(waiting), "Waiting child wasn't killed");
+                assert!(!PROCESS_EXECUTOR.kill(waiting), "Killed child was killed twice");
+
+                self.children.push(exiting);
+                self.children.push(waiting);
+            } else if message.is::<process::ProcessExited>() {
+                let msg = message.downcast::<process::ProcessExited>().unwrap();
+
+                self.exited.push((msg.id, msg.status));
+
+                if self.exited.len() == self.children.len() {
+                    // killed child is reported right away, exiting one when it handles its message
+                    assert_eq!(self.exited[..], [(self.children[1], process::ExitStatus::Killed),
+                                                  (self.children[0], process::ExitStatus::Exited(CHILD_EXIT_CODE))]);
+
+                    writeln!(VGA_WRITER.as_mut().unwrap(), "Parent process joined its children");
+
+                    PROCESS_EXECUTOR.exit(process::ExitStatus::Exited(0));
+                }
+            }
+        }
+    }
+}
+
+fn process_exit_and_kill_should_notify_parent() {
+    unsafe {
+        let id = PROCESS_EXECUTOR.create_process(Box::new(ParentProcess { exited : Vec::new(), children : Vec::new() }));
+
+        PROCESS_EXECUTOR.post_message(id, Box::new(process::StartProcess {}));
+    }
+}
+
 fn memory_allocator_should_properly_allocate_and_free_memory() {
+    let statistics_before = unsafe { HEAP_ALLOCATOR.statistics() };
+
     // everything inside inner block will get deleted after block exit
     {
         let mut test_array : [u64;6] = [0 ;6];
@@ -216,41 +394,33 @@ fn memory_allocator_should_properly_allocate_and_free_memory() {
         }
     }
 
-    let result = unsafe { HEAP_ALLOCATOR.is_fully_free() };
-
-    assert_eq!(result, true, "Allocator wasn't fully free after allocating memory in isolated block");
-}
-
-fn preallocate_memory_for_allocator_aux_data_structures(memory_start : usize, memory_end : usize) -> usize {
-    let aux_data_structures_size = SlabAllocator::total_aux_data_structures_size(memory_start, memory_end);
-
-    let premade_bump_end_address  = Frame::address_align_up(memory_start + aux_data_structures_size);
-    let mut premade_bump                = ConstSizeBumpAllocator::from_address(memory_start, premade_bump_end_address, FRAME_SIZE);
+    // freed objects are kept in quarantine until enough objects are freed after them
+    #[cfg(feature = "heap_debugging")]
+    {
+        let verification = unsafe { HEAP_ALLOCATOR.verify() };
 
-    // |aux structures page tables|aux structures working memory|allocator working memory|
-    // premap memory for memory allocator inner data structures
-    let aux_structures_start_address = premade_bump_end_address + FRAME_SIZE; // next frame
-    let aux_structures_end_address = Frame::address_align_up(aux_structures_start_address + aux_data_structures_size);
+        assert!(verification.is_ok(), "Heap is corrupted after allocating memory in isolated block");
 
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
-        let p4_table = paging::p4_table();
-        p4_table.map_page_1_to_1(frame, page_table::PRESENT | page_table::WRITABLE, &mut premade_bump);
+        unsafe { HEAP_ALLOCATOR.flush_quarantine() };
     }
 
-    test_allocator_aux_data_structures_memory(aux_structures_start_address, aux_structures_end_address);
-
-    aux_structures_start_address
-}
+    // frames of cached empty slabs would show up in the difference
+    unsafe { HEAP_ALLOCATOR.shrink() };
 
-fn test_allocator_aux_data_structures_memory(aux_structures_start_address : usize, aux_structures_end_address : usize) {
-    for frame in Frame::range_inclusive(aux_structures_start_address, aux_structures_end_address) {
-        let p4_table = paging::p4_table();
-        let present = p4_table.is_present(frame);
+    let result = unsafe { HEAP_ALLOCATOR.is_fully_free() };
+    let difference = unsafe { HEAP_ALLOCATOR.statistics().diff(&statistics_before) };
 
-        unsafe { writeln!(VGA_WRITER.as_mut().unwrap(), "Is present {}, val {}", frame, present); }
+    if !difference.is_empty() {
+        unsafe {
+            writeln!(VGA_WRITER.as_mut().unwrap(), "{}", HEAP_ALLOCATOR.statistics());
 
-        Frame::zero_frame(&frame);
+            #[cfg(feature = "leak_tracking")]
+            HEAP_ALLOCATOR.report_leaks(VGA_WRITER.as_mut().unwrap());
+        }
     }
+
+    assert_eq!(result, true, "Allocator wasn't fully free after allocating memory in isolated block");
+    assert!(difference.is_empty(), "Heap statistics changed after allocating memory in isolated block");
 }
 
 use core::panic::PanicInfo;
@@ -271,6 +441,12 @@ pub extern "C" fn oom(l: Layout) -> ! {
     loop {}
 }
 
+fn print_page_table_mappings(p4_table : &P4Table, vga_writer : &mut Writer) {
+    writeln!(vga_writer, "---Page table mappings---");
+    write!(vga_writer, "{}", p4_table.mappings_table());
+    writeln!(vga_writer, "Total mapped memory {} bytes", p4_table.total_mapped_memory());
+}
+
 fn print_multiboot_data(multiboot_header : &MultibootHeader, vga_writer : &mut Writer) {
     writeln!(vga_writer, "---Basic memory info---");
 
@@ -305,93 +481,315 @@ fn print_multiboot_data(multiboot_header : &MultibootHeader, vga_writer : &mut W
 
 unsafe fn paging_map_should_properly_map_pages(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator, vga_writer : &mut Writer) {
 
-    let virtual_frame = Frame::from_address(0x400000000000);
-    let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
+    let virtual_page = Page::containing_address(VirtAddr::new(0x400000000000));
+    let physical_frame = frame_alloc.allocate_frame().expect("No frames for paging test");
 
-    page_table.map_page(virtual_frame, physical_frame, page_table::PRESENT | page_table::WRITABLE, frame_alloc);
+    page_table.map_page(virtual_page, physical_frame, page_table::PRESENT | page_table::WRITABLE, frame_alloc);
 
     // try read whole frame from virtual address, if it succeeds without a segfault then
     // map function worked correctly
-    let virtual_frame_address = virtual_frame.address();
+    let virtual_page_address = virtual_page.start_address();
 
     for i in 0..FRAME_SIZE {
         unsafe {
             // reading into var is important to prevent compiler optimizing the read away
-            let _result = *((virtual_frame_address + i as usize) as *const u8);
+            let _result = *(virtual_page_address + i).as_ptr::<u8>();
         }
     }
 
-    frame_alloc.free(physical_frame.address());
-    page_table.unmap_page(virtual_frame);
+    frame_alloc.deallocate_frame(physical_frame);
+    page_table.unmap_page(virtual_page);
 }
 
 unsafe fn paging_translate_page_should_properly_translate_pages(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator) {
-    let virtual_frame = Frame::from_address(42 * 512 * 512 * 4096);
-    let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
+    let virtual_page = Page::containing_address(VirtAddr::new(42 * 512 * 512 * 4096));
+    let physical_frame = frame_alloc.allocate_frame().expect("No frames for paging test");
 
-    page_table.map_page(virtual_frame, physical_frame, page_table::PRESENT, frame_alloc);
+    page_table.map_page(virtual_page, physical_frame, page_table::PRESENT, frame_alloc);
 
-    let result = page_table.translate_page(virtual_frame);
+    let result = page_table.translate_page(virtual_page);
 
-    sanity_assert_translate_page_result(virtual_frame, physical_frame, result);
+    sanity_assert_translate_page_result(virtual_page, physical_frame, result);
 
-    frame_alloc.free(physical_frame.address());
-    page_table.unmap_page(virtual_frame);
+    frame_alloc.deallocate_frame(physical_frame);
+    page_table.unmap_page(virtual_page);
 }
 
 unsafe fn paging_translate_address_should_properly_translate_virtual_address(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator) {
-    let virtual_frame = Frame::from_address(42 * 512 * 512 * 4096);
-    let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
+    let virtual_page = Page::containing_address(VirtAddr::new(42 * 512 * 512 * 4096));
+    let physical_frame = frame_alloc.allocate_frame().expect("No frames for paging test");
 
-    page_table.map_page(virtual_frame, physical_frame, page_table::PRESENT, frame_alloc);
+    page_table.map_page(virtual_page, physical_frame, page_table::PRESENT, frame_alloc);
 
-    let virtual_frame_address = virtual_frame.address();
-    let physical_frame_address = physical_frame.address();
+    let virtual_page_address = virtual_page.start_address();
+    let physical_frame_address = physical_frame.start_address();
 
     for frame_offset in 0..FRAME_SIZE {
-        let virtual_address  = virtual_frame_address + frame_offset as usize;
-        let physical_address = physical_frame_address + frame_offset as usize;
+        let virtual_address  = virtual_page_address + frame_offset;
+        let physical_address = physical_frame_address + frame_offset;
         let result = page_table.translate(virtual_address);
 
         sanity_assert_translate_address_result(virtual_address, physical_address, result);
     }
 
-    frame_alloc.free(physical_frame.address());
-    page_table.unmap_page(virtual_frame);
+    frame_alloc.deallocate_frame(physical_frame);
+    page_table.unmap_page(virtual_page);
 }
 
 unsafe fn paging_unmap_should_properly_unmap_elements(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator) {
-    let virtual_frame = Frame::from_address(42 * 512 * 512 * 4096);
-    let physical_frame = Frame::from_address(frame_alloc.allocate(FRAME_SIZE).expect("No frames for paging test"));
+    let virtual_page = Page::containing_address(VirtAddr::new(42 * 512 * 512 * 4096));
+    let physical_frame = frame_alloc.allocate_frame().expect("No frames for paging test");
 
-    page_table.map_page(virtual_frame, physical_frame, page_table::PRESENT, frame_alloc);
-    page_table.unmap_page(virtual_frame);
+    page_table.map_page(virtual_page, physical_frame, page_table::PRESENT, frame_alloc);
+    page_table.unmap_page(virtual_page);
 
-    let result = page_table.translate_page(virtual_frame);
+    let result = page_table.translate_page(virtual_page);
 
     assert!(result.is_none(),
         "Translation of virtual page {} returned physical frame {} after unmap, but should return empty result",
-        virtual_frame,
+        virtual_page,
         result.unwrap());
 
-    frame_alloc.free(physical_frame.address());
+    frame_alloc.deallocate_frame(physical_frame);
+}
+
+unsafe fn paging_map_huge_2m_should_translate_and_split_on_partial_unmap(page_table : &mut page_table::P4Table, frame_alloc : &mut BuddyAllocator) {
+    // first 2 MiB of physical memory, holds kernel and is never freed, so its safe to map it read only
+    let virtual_page = Page::containing_address(VirtAddr::new(0x400000000000));
+    let physical_frame = PhysFrame::containing_address(PhysAddr::zero());
+
+    page_table.map_huge_2m(virtual_page, physical_frame, page_table::PRESENT, frame_alloc);
+
+    assert_eq!(page_table.page_size(virtual_page), Some(PageSize::Size2M), "Page {} should be mapped by 2 MiB page", virtual_page);
+
+    let offset = HUGE_PAGE_SIZE_2M - 1;
+    sanity_assert_translate_address_result(virtual_page.start_address() + offset, 
+        physical_frame.start_address() + offset, 
+        page_table.translate(virtual_page.start_address() + offset));
+
+    // unmap second 4 KiB page, huge page should be splitted and the rest stays mapped
+    let unmapped_page = Page::containing_address(virtual_page.start_address() + FRAME_SIZE);
+    page_table.unmap_page_split_huge(unmapped_page, frame_alloc);
+
+    assert!(page_table.translate_page(unmapped_page).is_none(), "Page {} should be unmapped after split", unmapped_page);
+    assert_eq!(page_table.page_size(virtual_page), Some(PageSize::Size4K), "Page {} should be mapped by 4 KiB page after split", virtual_page);
+    sanity_assert_translate_address_result(virtual_page.start_address() + offset, 
+        physical_frame.start_address() + offset, 
+        page_table.translate(virtual_page.start_address() + offset));
+
+    for page in Page::range_inclusive(virtual_page, Page::containing_address(virtual_page.start_address() + offset)) {
+        page_table.unmap_page(page);
+    }
+}
+
+fn paging_no_execute_should_page_fault_on_jump_into_data_page() {
+    // single `ret` instruction placed into .data section, which is mapped as NO_EXECUTE
+    static mut NOT_EXECUTABLE_CODE : [u8; 1] = [0xc3];
+
+    unsafe {
+        let code_address = NOT_EXECUTABLE_CODE.as_ptr() as u64;
+        let code_page = Page::containing_address(VirtAddr::new(code_address as usize));
+        let flags = paging::p4_table().page_flags(code_page).expect("Data page is not mapped");
+
+        assert!(flags.contains(page_table::NO_EXECUTE), "Data page {} is executable", code_page);
+
+        let faults_before = handlers::EXPECTED_FETCH_FAULT.handled;
+
+        handlers::EXPECTED_FETCH_FAULT.address = code_address;
+
+        // recovery address is taken right before the jump, page fault handler resumes execution at label 2
+        // with the same registers and stack, so nothing else has to be restored
+        asm!("lea 2f(%rip), %rax
+              movq %rax, ($0)
+              jmp *$1
+              2:"
+             :: "r" (&mut handlers::EXPECTED_FETCH_FAULT.recovery_address as *mut u64), "r" (code_address)
+             : "rax", "memory" : "volatile");
+
+        assert_eq!(handlers::EXPECTED_FETCH_FAULT.handled, faults_before + 1, "Jump into non executable data page didn't produce page fault");
+    }
+}
+
+unsafe fn address_space_should_isolate_private_memory(frame_alloc : &mut BuddyAllocator) {
+    use memory::paging::address_space::AddressSpace;
+
+    let kernel_p4_frame = PhysFrame::containing_address(PhysAddr::new(registers::cr3() as usize));
+    let private_page = Page::containing_address(VirtAddr::new(0x0000_1000_0000_0000));
+    let first = AddressSpace::new(paging::p4_table(), frame_alloc).expect("No memory for address space");
+    let second = AddressSpace::new(paging::p4_table(), frame_alloc).expect("No memory for address space");
+
+    first.map_private(paging::p4_table(), private_page, page_table::WRITABLE | page_table::NO_EXECUTE, frame_alloc);
+    second.map_private(paging::p4_table(), private_page, page_table::WRITABLE | page_table::NO_EXECUTE, frame_alloc);
+
+    let value = private_page.start_address().as_mut_ptr::<u64>();
+
+    first.activate();
+    *value = 1;
+
+    second.activate();
+    *value = 2;
+
+    first.activate();
+    assert_eq!(*value, 1, "Write to private memory of one address space is visible in another");
+
+    paging::switch_tables(kernel_p4_frame.start_address());
+
+    assert!(paging::p4_table().translate_page(private_page).is_none(), "Private memory of address space is visible in kernel table");
+
+    first.destroy(paging::p4_table(), frame_alloc);
+    second.destroy(paging::p4_table(), frame_alloc);
+}
+
+unsafe fn address_space_fork_should_copy_private_memory_on_write(frame_alloc : &mut BuddyAllocator) {
+    use memory::paging::address_space::AddressSpace;
+    use memory::allocator::SharedFrameAllocator;
+
+    let kernel_p4_frame = PhysFrame::containing_address(PhysAddr::new(registers::cr3() as usize));
+    let private_page = Page::containing_address(VirtAddr::new(0x0000_2000_0000_0000));
+    let parent = AddressSpace::new(paging::p4_table(), frame_alloc).expect("No memory for address space");
+
+    let shared_frame = parent
+        .map_private(paging::p4_table(), private_page, page_table::WRITABLE | page_table::NO_EXECUTE, frame_alloc)
+        .expect("No memory for private page");
+
+    let value = private_page.start_address().as_mut_ptr::<u64>();
+
+    parent.activate();
+    *value = 1;
+
+    let child = parent.fork(paging::p4_table(), frame_alloc).expect("No memory for forked address space");
+
+    assert_eq!(frame_alloc.frame_reference_count(shared_frame), 2, "Frame of forked private page is not shared");
+
+    child.activate();
+    assert_eq!(*value, 1, "Forked address space doesn't see parent memory");
+
+    *value = 2;
+
+    assert!(paging::p4_table().translate_page(private_page) != Some(shared_frame), "Write to shared page didn't copy the frame");
+    assert_eq!(frame_alloc.frame_reference_count(shared_frame), 1, "Copied frame is still referenced by child");
+
+    parent.activate();
+    assert_eq!(*value, 1, "Write of forked address space is visible in parent");
+
+    // parent holds the last reference, page is made writable without copy
+    *value = 3;
+
+    assert!(paging::p4_table().translate_page(private_page) == Some(shared_frame), "Frame with single reference was copied");
+
+    paging::switch_tables(kernel_p4_frame.start_address());
+
+    parent.destroy(paging::p4_table(), frame_alloc);
+    child.destroy(paging::p4_table(), frame_alloc);
+}
+
+unsafe fn demand_paging_should_back_reserved_pages_on_access(frame_alloc : &mut BuddyAllocator) {
+    use memory::paging::fault;
+    use memory::paging::virtual_region::{VirtualRegion, RegionKind};
+
+    let region_start = VirtAddr::new(0xffff_c000_0000_0000); // unused kernel memory (P4 entry 384)
+    let region = VirtualRegion::new(region_start, 4 * FRAME_SIZE, page_table::WRITABLE | page_table::NO_EXECUTE, RegionKind::Heap);
+
+    assert!(VIRTUAL_REGIONS.reserve(region), "Failed to reserve {}", region);
+    assert!(!VIRTUAL_REGIONS.reserve(region), "Overlapping {} was reserved twice", region);
+
+    let p4_table = paging::p4_table();
+
+    for page in region.pages() {
+        assert!(!p4_table.is_present(page), "Page {} of reserved region is present before access", page);
+
+        let value = page.start_address().as_mut_ptr::<u64>();
+
+        assert_eq!(ptr::read_volatile(value), 0, "Page {} wasn't zeroed on demand", page);
+
+        ptr::write_volatile(value, page.number() as u64);
+
+        assert_eq!(ptr::read_volatile(value), page.number() as u64, "Page {} lost written value", page);
+        assert!(p4_table.is_present(page), "Page {} is not present after access", page);
+    }
+
+    let released = VIRTUAL_REGIONS.release(region_start).expect("Reserved region is missing in registry");
+    fault::release_region(p4_table, &released, frame_alloc);
+
+    for page in released.pages() {
+        assert!(!p4_table.is_present(page), "Page {} is present after region release", page);
+    }
 }
 
-fn sanity_assert_translate_page_result(virtual_frame : Frame, physical_frame : Frame, result : Option<Frame>) {
+unsafe fn process_stack_should_be_backed_above_unmapped_guard_page(frame_alloc : &mut BuddyAllocator) {
+    use multiprocess::stack::{ProcessStack, PROCESS_STACKS_START};
+    use memory::paging::virtual_region::RegionKind;
+
+    // far above stacks of executor processes
+    let guard_address = VirtAddr::new(PROCESS_STACKS_START + 0x4000_0000);
+    let free_frames_before = frame_alloc.free_frames_count();
+    let p4_table = paging::p4_table();
+
+    let process_stack = ProcessStack::new(guard_address, stack::DEFAULT_STACK_SIZE, &mut VIRTUAL_REGIONS, p4_table, frame_alloc)
+        .expect("No memory for process stack");
+    let stack_pages = process_stack.region();
+
+    assert!(!p4_table.is_present(Page::containing_address(guard_address)), "Guard page of process stack is mapped");
+    assert_eq!(process_stack.guard().end_address() + 1, stack_pages.start_address(), "Guard page is not right below the stack");
+    assert_eq!(process_stack.top() % 16, 0, "Stack top is not aligned for calls");
+    assert!(VIRTUAL_REGIONS.find(guard_address).map(|r| r.kind()) == Some(RegionKind::Guard), "Guard page is not reserved");
+    assert!(VIRTUAL_REGIONS.find(stack_pages.start_address()).map(|r| r.kind()) == Some(RegionKind::Stack), "Stack is not reserved");
+
+    // stack is never backed on demand, page fault handler can't allocate frames while process is inside the allocator
+    for page in stack_pages.pages() {
+        assert!(p4_table.is_present(page), "Page {} of process stack is not mapped", page);
+    }
+
+    let top = (process_stack.top() - 8) as *mut u64;
+    let bottom = Page::containing_address(stack_pages.start_address()).start_address().as_mut_ptr::<u64>();
+
+    ptr::write_volatile(top, 0xdead_beef);
+    ptr::write_volatile(bottom, 0xdead_beef);
+    assert_eq!(ptr::read_volatile(top), 0xdead_beef, "Process stack lost written value");
+    assert_eq!(ptr::read_volatile(bottom), 0xdead_beef, "Process stack lost value written at its bottom");
+
+    process_stack.release(&mut VIRTUAL_REGIONS, p4_table, frame_alloc);
+
+    for page in stack_pages.pages() {
+        assert!(!p4_table.is_present(page), "Page {} of released process stack is mapped", page);
+    }
+
+    assert!(VIRTUAL_REGIONS.find(stack_pages.start_address()).is_none(), "Released stack is still reserved");
+
+    // P2 and P1 tables created for the stack are not freed
+    assert!(frame_alloc.free_frames_count() + 2 >= free_frames_before, "Frames of released process stack leaked");
+}
+
+fn zoned_frame_allocator_should_allocate_buffers_below_zone_end() {
+    use memory::allocator::zone::Zone;
+
+    let zoned_allocator = unsafe { ZONED_FRAME_ALLOCATOR.as_mut().expect("Zoned frame allocator wasn't created") };
+
+    for &zone in [Zone::Dma, Zone::Dma32].iter() {
+        let buffer = zoned_allocator.allocate_contiguous(4 * FRAME_SIZE, zone, FRAME_SIZE).expect("No memory for device buffer");
+        let buffer_end = buffer.offset(3).end_address();
+
+        assert!(buffer_end <= zone.end_address(), "Buffer of zone {} ends at {}", zone, buffer_end);
+
+        zoned_allocator.deallocate(buffer);
+    }
+}
+
+fn sanity_assert_translate_page_result(virtual_page : Page, physical_frame : PhysFrame, result : Option<PhysFrame>) {
     assert!(result.is_some(),
-        "Returned empty result for translation of virtual frame {}",
-        virtual_frame);
+        "Returned empty result for translation of virtual page {}",
+        virtual_page);
 
     let result_frame = result.unwrap();
 
     assert!(physical_frame == result_frame,
-        "Returned invalid translation result for virtual frame {}. Should be frame {} but was {}",
-        virtual_frame,
+        "Returned invalid translation result for virtual page {}. Should be frame {} but was {}",
+        virtual_page,
         physical_frame,
         result_frame);
 }
 
-fn sanity_assert_translate_address_result(virtual_address : usize, physical_address : usize, result : Option<usize>){
+fn sanity_assert_translate_address_result(virtual_address : VirtAddr, physical_address : PhysAddr, result : Option<PhysAddr>){
     assert!(result.is_some(),
         "Returned empty result for translation of virtual frame {}",
         virtual_address);
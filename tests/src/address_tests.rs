@@ -0,0 +1,139 @@
+use memory::address::{VirtAddr, PhysAddr, VirtAddrRange, PhysAddrRange};
+use memory::frame::{PhysFrame, FRAME_SIZE};
+use memory::paging::page::{Page, PageSize};
+
+#[test]
+pub fn virtual_address_should_accept_canonical_addresses() {
+    let lower_half = VirtAddr::try_new(0x0000_7fff_ffff_ffff);
+    let higher_half = VirtAddr::try_new(0xffff_8000_0000_0000);
+
+    assert!(lower_half.is_some(), "Address {:#x} is canonical, but was rejected", 0x0000_7fff_ffff_ffffusize);
+    assert!(higher_half.is_some(), "Address {:#x} is canonical, but was rejected", 0xffff_8000_0000_0000usize);
+}
+
+#[test]
+pub fn virtual_address_should_reject_non_canonical_addresses() {
+    let result = VirtAddr::try_new(0x0000_8000_0000_0000);
+
+    assert!(result.is_none(), "Address {:#x} is not canonical, but was accepted", 0x0000_8000_0000_0000usize);
+}
+
+#[test]
+#[should_panic]
+pub fn virtual_address_new_should_panic_for_non_canonical_address() {
+    VirtAddr::new(0x1000_0000_0000_0000);
+}
+
+#[test]
+pub fn virtual_address_new_truncate_should_sign_extend_bit_47() {
+    assert_eq!(VirtAddr::new_truncate(0x0000_8000_0000_0000).as_usize(), 0xffff_8000_0000_0000);
+    assert_eq!(VirtAddr::new_truncate(0xffff_7fff_ffff_f000).as_usize(), 0x0000_7fff_ffff_f000);
+}
+
+#[test]
+pub fn physical_address_should_reject_addresses_wider_than_52_bits() {
+    assert!(PhysAddr::try_new(0x000f_ffff_ffff_ffff).is_some());
+    assert!(PhysAddr::try_new(0x0010_0000_0000_0000).is_none());
+}
+
+#[test]
+pub fn addresses_should_align_up_and_down() {
+    let virtual_address = VirtAddr::new(0x1234);
+    let physical_address = PhysAddr::new(0x1234);
+
+    assert_eq!(virtual_address.align_up(FRAME_SIZE), VirtAddr::new(0x2000));
+    assert_eq!(virtual_address.align_down(FRAME_SIZE), VirtAddr::new(0x1000));
+    assert_eq!(physical_address.align_up(FRAME_SIZE), PhysAddr::new(0x2000));
+    assert_eq!(physical_address.align_down(FRAME_SIZE), PhysAddr::new(0x1000));
+    assert!(VirtAddr::new(0x2000).is_aligned(FRAME_SIZE));
+    assert!(!physical_address.is_aligned(FRAME_SIZE));
+}
+
+#[test]
+pub fn page_should_contain_address() {
+    let address = VirtAddr::new(0x0000_4000_0000_0123);
+    let page = Page::containing_address(address);
+
+    assert_eq!(page.start_address(), VirtAddr::new(0x0000_4000_0000_0000));
+    assert_eq!(page.end_address(), VirtAddr::new(0x0000_4000_0000_0fff));
+    assert_eq!(address.page_offset(), 0x123);
+}
+
+#[test]
+pub fn higher_half_page_should_keep_canonical_start_address() {
+    let address = VirtAddr::new(0xffff_ff00_0010_0000);
+    let page = Page::containing_address(address);
+
+    assert_eq!(page.start_address(), address);
+}
+
+#[test]
+pub fn identity_mapped_page_should_have_frame_address() {
+    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    let page = Page::identity_mapped(frame);
+
+    assert_eq!(page.start_address().as_usize(), frame.start_address().as_usize());
+}
+
+#[test]
+pub fn page_range_should_be_inclusive() {
+    let start = Page::containing_address(VirtAddr::new(0x1000));
+    let end = Page::containing_address(VirtAddr::new(0x3000));
+
+    assert_eq!(Page::range_inclusive(start, end).count(), 3);
+}
+
+#[test]
+pub fn huge_page_sizes_should_match_page_table_levels() {
+    assert_eq!(PageSize::Size4K.size(), 4096);
+    assert_eq!(PageSize::Size2M.size(), 512 * 4096);
+    assert_eq!(PageSize::Size1G.size(), 512 * 512 * 4096);
+}
+
+#[test]
+pub fn frame_should_cover_addresses_of_its_frame() {
+    let frame = PhysFrame::containing_address(PhysAddr::new(0x1234));
+
+    assert_eq!(frame.start_address(), PhysAddr::new(0x1000));
+    assert_eq!(frame.end_address(), PhysAddr::new(0x1fff));
+    assert_eq!(frame.offset(2).start_address(), PhysAddr::new(0x3000));
+}
+
+#[test]
+pub fn virtual_range_should_contain_its_inclusive_end() {
+    let range = VirtAddrRange::with_size(VirtAddr::new(0x1000), 0x2000);
+
+    assert_eq!(range.end_address(), VirtAddr::new(0x2fff));
+    assert_eq!(range.size(), 0x2000);
+    assert!(range.contains(VirtAddr::new(0x2fff)));
+    assert!(!range.contains(VirtAddr::new(0x3000)));
+    assert_eq!(range.pages().count(), 2);
+}
+
+#[test]
+pub fn physical_range_should_iterate_over_partially_covered_frames() {
+    let range = PhysAddrRange::new(PhysAddr::new(0x1800), PhysAddr::new(0x3000));
+    let frames : Vec<PhysFrame> = range.frames().collect();
+
+    assert_eq!(frames, vec![
+        PhysFrame::containing_address(PhysAddr::new(0x1000)),
+        PhysFrame::containing_address(PhysAddr::new(0x2000)),
+        PhysFrame::containing_address(PhysAddr::new(0x3000))
+    ]);
+}
+
+#[test]
+pub fn ranges_should_overlap_when_they_share_an_address() {
+    let first = PhysAddrRange::new(PhysAddr::new(0x1000), PhysAddr::new(0x1fff));
+    let second = PhysAddrRange::new(PhysAddr::new(0x1fff), PhysAddr::new(0x2fff));
+    let third = PhysAddrRange::new(PhysAddr::new(0x2000), PhysAddr::new(0x2fff));
+
+    assert!(first.overlaps(&second));
+    assert!(!first.overlaps(&third));
+}
+
+#[test]
+#[should_panic]
+pub fn range_should_not_end_below_its_start() {
+    VirtAddrRange::new(VirtAddr::new(0x2000), VirtAddr::new(0x1000));
+}
@@ -1,4 +1,4 @@
-use memory::frame::Frame;
+use memory::frame::{PhysFrame, FrameSource};
 use memory::frame::FRAME_SIZE;
 use stdx::iterator::IteratorExt;
 use stdx::Sequence;
@@ -7,6 +7,9 @@ use stdx_memory::collections::double_linked_list::{DoubleLinkedList, DoubleLinke
 use memory::allocator::bump::BumpAllocator;
 use memory::allocator::buddy::BuddyAllocator;
 use memory::allocator::free_list::FreeListAllocator;
+use memory::frame::regions::MemoryRegions;
+use memory::address::PhysAddr;
+use memory_regions_tests::{memory_map_tag, memory_map};
 use std::mem;
 use alloc::heap;
 
@@ -34,7 +37,7 @@ macro_rules! init_buddy_map {
 #[test]
 #[should_panic]
 pub fn should_not_create_allocator_if_there_is_no_memory() {    
-    let mut allocator = BuddyAllocator::new(0, 0);    
+    let mut allocator = buddy_allocator(0, 0);    
 }
 
 #[test]
@@ -43,14 +46,14 @@ pub fn should_not_create_allocator_if_there_is_no_memory2() {
     let heap : [u8;40960] = [0;40960];
     let heap_addr = heap.as_ptr() as usize;
 
-    let mut allocator = BuddyAllocator::new(heap_addr, 0);    
+    let mut allocator = buddy_allocator(heap_addr, 0);    
 }
 
 #[test]
 pub fn should_return_none_if_requested_more_then_available_memory() {
     let heap = unsafe { heap::allocate_zeroed(4096, 4096) as usize } ;
 
-    let mut allocator = BuddyAllocator::new(heap, heap + 4095);
+    let mut allocator = buddy_allocator(heap, 4096);
     
     let result = allocator.allocate(100000000);
     
@@ -72,7 +75,7 @@ fn should_complete_request_for_all_available_memory0() -> (BuddyAllocator, usize
     
     let heap = unsafe { heap::allocate_zeroed(size, 4096) as usize } ;
     let heap_end_address = heap + size - 1;
-    let mut allocator = BuddyAllocator::new(heap, heap_end_address);
+    let mut allocator = buddy_allocator(heap, size);
     
     let result = allocator.allocate(size);
 
@@ -111,7 +114,7 @@ pub fn should_return_buddy() {
     
     let heap = unsafe { heap::allocate_zeroed(size, 4096) as usize } ;
     let heap_end_address = heap + size - 1;
-    let mut allocator = BuddyAllocator::new(heap, heap_end_address);
+    let mut allocator = buddy_allocator(heap, size);
     
     allocator.allocate(size / 2);
     let result = allocator.allocate(size / 2);
@@ -125,7 +128,7 @@ pub fn should_merge_buddy_if_possible() {
     
     let heap = unsafe { heap::allocate_zeroed(size, 4096) as usize } ;
     let heap_end_address = heap + size - 1;
-    let mut allocator = BuddyAllocator::new(heap, heap_end_address);
+    let mut allocator = buddy_allocator(heap, size);
     
     let left = allocator.allocate(size / 2);
     let right = allocator.allocate(size / 2);
@@ -144,7 +147,7 @@ pub fn should_return_distinct_addresses() {
     
     let heap = unsafe { heap::allocate_zeroed(size, 4096) as usize } ;
     let heap_end_address = heap + size - 1;
-    let mut allocator = BuddyAllocator::new(heap, heap_end_address);
+    let mut allocator = buddy_allocator(heap, size);
     let mut vec : Vec<usize> = Vec::new();
 
     for i in 0..16 {
@@ -166,7 +169,7 @@ pub fn should_merge_all_buddies_to_the_upper_level() {
     
     let heap = unsafe { heap::allocate_zeroed(size, 4096) as usize } ;
     let heap_end_address = heap + size - 1;
-    let mut allocator = BuddyAllocator::new(heap, heap_end_address);
+    let mut allocator = buddy_allocator(heap, size);
     let mut allocated : [usize;16] = [0;16];
 
     for i in 0..16 {
@@ -188,7 +191,7 @@ pub fn should_satisfy_buddy_request_when_left_block_is_fully_taken() {
     
     let heap = unsafe { heap::allocate_zeroed(size, 4096) as usize } ;
     let heap_end_address = heap + size - 1;
-    let mut allocator = BuddyAllocator::new(heap, heap_end_address);
+    let mut allocator = buddy_allocator(heap, size);
     let mut allocated : [usize;8] = [0;8];
 
     for i in 0..8 {
@@ -206,9 +209,302 @@ pub fn should_return_none_if_allocation_request_is_zero() {
     
     let heap = unsafe { heap::allocate_zeroed(size, 4096) as usize } ;
     let heap_end_address = heap + size - 1;
-    let mut allocator = BuddyAllocator::new(heap, heap_end_address);    
+    let mut allocator = buddy_allocator(heap, size);    
 
     let result = allocator.allocate(0);
 
     assert!(result.is_none(), "Buddy allocator allocated memory from unknown source for request of size {}", 0)
-}
\ No newline at end of file
+}
+// allocator of memory [0, size), addresses are shifted by heap address
+fn buddy_allocator(heap : usize, size : usize) -> BuddyAllocator {
+    let mut regions = MemoryRegions::new();
+    regions.add(PhysAddr::new(0), size);
+
+    buddy_allocator_for(&regions, heap)
+}
+
+fn buddy_allocator_for(regions : &MemoryRegions, address_offset : usize) -> BuddyAllocator {
+    let aux_data_structures_size = BuddyAllocator::aux_data_structures_size_for(regions);
+    let aux_start_address = unsafe { heap::allocate_zeroed(aux_data_structures_size, 4096) as usize };
+
+    BuddyAllocator::from_regions(regions, aux_start_address, address_offset)
+}
+
+// allocates frames until memory is exhausted
+fn allocate_all_frames(allocator : &mut BuddyAllocator) -> Vec<usize> {
+    let mut frames = Vec::new();
+
+    while let Some(frame) = allocator.allocate(FRAME_SIZE) {
+        frames.push(frame);
+    }
+
+    frames
+}
+
+#[test]
+pub fn should_allocate_every_frame_of_128_mib_memory_map() {
+    let size = 128 * 1024 * 1024;
+    let tag = memory_map_tag(&[(0, size as u64, 1)]);
+    let mut allocator = buddy_allocator_for(&MemoryRegions::from_memory_map(memory_map(&tag)), 0);
+
+    let frames = allocate_all_frames(&mut allocator);
+
+    let mut distinct = frames.clone();
+    distinct.sort();
+    distinct.dedup();
+
+    assert_eq!(distinct.len(), size / FRAME_SIZE, "Allocator didn't return every frame of 128 MiB exactly once");
+
+    for frame in frames {
+        allocator.free(frame);
+    }
+
+    assert_eq!(allocator.allocate(size), Some(0), "Freed frames weren't merged back into 128 MiB block");
+}
+
+#[test]
+pub fn should_allocate_whole_1_gib_memory_map() {
+    let size = 1024 * 1024 * 1024;
+    let tag = memory_map_tag(&[(0, size as u64, 1)]);
+    let mut allocator = buddy_allocator_for(&MemoryRegions::from_memory_map(memory_map(&tag)), 0);
+
+    let result = allocator.allocate(size);
+
+    assert_eq!(result, Some(0));
+    assert_eq!(allocator.allocate(FRAME_SIZE), None, "Allocator returned frame after all memory was allocated");
+
+    allocator.free(result.unwrap());
+
+    assert_eq!(allocator.allocate(size / 2), Some(0));
+    assert_eq!(allocator.allocate(size / 2), Some(size / 2));
+}
+
+#[test]
+pub fn should_allocate_only_frames_inside_memory_map_regions() {
+    let tag = memory_map_tag(&[
+        (0x0, 0x9fc00, 1),
+        (0x9fc00, 0x60400, 2),              // reserved, e.g. BIOS area
+        (0x100000, 0x7ee0000, 1),
+        (0x7fe0000, 0x20000, 2),
+        (0x1_0000_0000, 0x400_0000, 1)      // memory above 4 GiB
+    ]);
+    let regions = MemoryRegions::from_memory_map(memory_map(&tag));
+    let mut allocator = buddy_allocator_for(&regions, 0);
+
+    let frames = allocate_all_frames(&mut allocator);
+
+    for &frame in frames.iter() {
+        assert!(regions.iter().any(|r| r.contains(PhysAddr::new(frame)) && r.contains(PhysAddr::new(frame + FRAME_SIZE - 1))),
+                "Allocator returned frame {:#x} from memory hole", frame);
+    }
+
+    let mut distinct = frames.clone();
+    distinct.sort();
+    distinct.dedup();
+
+    assert_eq!(distinct.len(), regions.total_size() / FRAME_SIZE, "Allocator didn't return every available frame exactly once");
+    assert!(frames.iter().any(|&frame| frame >= 0x1_0000_0000), "Allocator didn't use memory above 4 GiB");
+
+    for frame in frames {
+        allocator.free(frame);
+    }
+
+    // the only aligned 64 MiB block without holes is above 4 GiB
+    assert_eq!(allocator.allocate(0x400_0000), Some(0x1_0000_0000));
+}
+
+#[test]
+pub fn should_free_last_block_of_level_without_buddy() {
+    let mut allocator = buddy_allocator(0, 3 * FRAME_SIZE);
+
+    let frames = allocate_all_frames(&mut allocator);
+
+    assert_eq!(frames.len(), 3);
+
+    for frame in frames {
+        allocator.free(frame);
+    }
+
+    assert_eq!(allocator.allocate(2 * FRAME_SIZE), Some(0));
+    assert_eq!(allocator.allocate(FRAME_SIZE), Some(2 * FRAME_SIZE));
+}
+
+#[test]
+pub fn should_align_blocks_for_every_size_and_alignment() {
+    let mut allocator = buddy_allocator(0, 64 * FRAME_SIZE);
+
+    for size in (1 .. 9).map(|frames| frames * FRAME_SIZE - 1) {
+        for align in (0 .. 17).map(|power| 1 << power) {
+            let result = allocator.allocate_aligned(size, align);
+
+            assert!(result.is_some(), "Buddy allocator failed to allocate {} bytes aligned by {}", size, align);
+            assert_eq!(result.unwrap() % align, 0, "Buddy allocator returned {:#x} for {} bytes aligned by {}", result.unwrap(), size, align);
+
+            allocator.free(result.unwrap());
+        }
+    }
+}
+
+#[test]
+pub fn should_align_block_inside_bigger_block_when_address_offset_is_less_aligned() {
+    let mut allocator = buddy_allocator(FRAME_SIZE, 64 * FRAME_SIZE);
+
+    // every block is shifted by one frame, so aligned address is taken inside a bigger block
+    let result = allocator.allocate_aligned(FRAME_SIZE, 2 * FRAME_SIZE).unwrap();
+
+    assert_eq!(result % (2 * FRAME_SIZE), 0);
+    assert!(allocator.allocation_size(result) >= FRAME_SIZE, "Aligned block is smaller than requested size");
+
+    allocator.free(result);
+
+    assert!(allocator.allocate(64 * FRAME_SIZE).is_some(), "Aligned block wasn't returned to allocator");
+}
+
+#[test]
+pub fn should_grow_block_in_place_when_right_buddies_are_free() {
+    let mut allocator = buddy_allocator(0, 64 * FRAME_SIZE);
+
+    let block = allocator.allocate(FRAME_SIZE).unwrap();
+
+    assert!(allocator.grow_in_place(block, 5 * FRAME_SIZE), "Block wasn't merged with free buddies");
+    assert_eq!(allocator.allocation_size(block), 8 * FRAME_SIZE);
+
+    let frames = allocate_all_frames(&mut allocator);
+
+    assert_eq!(frames.len(), 56);
+    assert!(frames.iter().all(|&frame| frame >= block + 8 * FRAME_SIZE), "Frame of grown block was allocated");
+}
+
+#[test]
+pub fn should_not_grow_block_in_place_when_buddy_is_in_use() {
+    let mut allocator = buddy_allocator(0, 64 * FRAME_SIZE);
+
+    let block = allocator.allocate(FRAME_SIZE).unwrap();
+    let buddy = allocator.allocate(FRAME_SIZE).unwrap();
+
+    assert_eq!(buddy, block + FRAME_SIZE);
+    assert!(!allocator.grow_in_place(block, 2 * FRAME_SIZE));
+    assert!(!allocator.grow_in_place(buddy, 2 * FRAME_SIZE), "Right block was grown over the next block");
+    assert_eq!(allocator.allocation_size(block), FRAME_SIZE);
+}
+
+#[test]
+pub fn should_free_whole_grown_block() {
+    let mut allocator = buddy_allocator(0, 64 * FRAME_SIZE);
+
+    let block = allocator.allocate(FRAME_SIZE).unwrap();
+    assert!(allocator.grow_in_place(block, 16 * FRAME_SIZE));
+
+    allocator.free(block);
+
+    assert_eq!(allocator.allocate(64 * FRAME_SIZE), Some(0), "Grown block wasn't merged back after free");
+}
+
+#[test]
+pub fn should_count_free_blocks_of_every_level() {
+    let mut allocator = buddy_allocator(0, 64 * FRAME_SIZE);
+
+    let free_blocks = |allocator : &BuddyAllocator| (0 .. allocator.levels_count()).map(|level| allocator.free_blocks_count(level)).collect::<Vec<usize>>();
+
+    assert_eq!(free_blocks(&allocator), vec![0, 0, 0, 0, 0, 0, 1]);
+
+    let block = allocator.allocate(FRAME_SIZE).unwrap();
+
+    assert_eq!(free_blocks(&allocator), vec![1, 1, 1, 1, 1, 1, 0]);
+
+    allocator.free(block);
+
+    assert_eq!(free_blocks(&allocator), vec![0, 0, 0, 0, 0, 0, 1]);
+}
+
+#[test]
+pub fn should_allocate_physical_frames_as_frame_source() {
+    let heap = unsafe { heap::allocate_zeroed(64 * FRAME_SIZE, 64 * FRAME_SIZE) as usize };
+    let mut allocator = buddy_allocator(heap, 64 * FRAME_SIZE);
+
+    assert_eq!(allocator.free_frames_count(), 64);
+
+    let frame = allocator.allocate_frame().unwrap();
+
+    assert!(frame == PhysFrame::containing_address(PhysAddr::zero()), "Frame source returned frame {} shifted by address offset", frame);
+    assert_eq!(allocator.free_frames_count(), 63);
+
+    allocator.deallocate_frame(frame);
+
+    assert_eq!(allocator.free_frames_count(), 64);
+}
+
+#[test]
+pub fn should_allocate_aligned_contiguous_frames() {
+    let mut allocator = buddy_allocator(0, 64 * FRAME_SIZE);
+
+    let frame = allocator.allocate_frame().unwrap();
+    let block = allocator.allocate_contiguous(3, 8).unwrap();
+
+    assert_eq!(block.number() % 8, 0, "Block starts at misaligned frame {}", block);
+    assert_eq!(allocator.free_frames_count(), 64 - 1 - 4);
+
+    allocator.deallocate_frame(block);
+    allocator.deallocate_frame(frame);
+
+    assert_eq!(allocator.free_frames_count(), 64);
+    assert!(allocator.allocate_contiguous(64, 1) == Some(PhysFrame::containing_address(PhysAddr::zero())), "Freed frames weren't merged back into the whole memory");
+}
+
+#[test]
+pub fn should_take_back_reserved_frames_of_managed_memory_above_free_regions() {
+    let mut managed = MemoryRegions::new();
+    let mut free = MemoryRegions::new();
+    managed.add(PhysAddr::new(0), 16 * FRAME_SIZE);
+    free.add(PhysAddr::new(0), 8 * FRAME_SIZE);
+
+    let aux_data_structures_size = BuddyAllocator::aux_data_structures_size_for(&managed);
+    let aux_start_address = unsafe { heap::allocate_zeroed(aux_data_structures_size, 4096) as usize };
+    let mut allocator = BuddyAllocator::from_managed_regions(&managed, &free, aux_start_address, 0);
+
+    assert_eq!(allocator.free_frames_count(), 8, "Reserved frames of managed memory are free right away");
+
+    // e.g. multiboot information at the top of memory
+    for number in 8 .. 16 {
+        allocator.deallocate_frame(PhysFrame::containing_address(PhysAddr::new(number * FRAME_SIZE)));
+    }
+
+    assert_eq!(allocator.free_frames_count(), 16);
+    assert!(allocator.allocate_contiguous(16, 1) == Some(PhysFrame::containing_address(PhysAddr::zero())), "Reserved frames weren't merged with free memory");
+}
+
+static mut RECLAIMED_ALLOCATOR : Option<*mut BuddyAllocator> = None;
+static mut RECLAIMED_BLOCK : Option<usize> = None;
+
+// frees block that is cached by allocator user
+fn reclaim_cached_block() -> usize {
+    unsafe {
+        match (RECLAIMED_ALLOCATOR, RECLAIMED_BLOCK.take()) {
+            (Some(allocator), Some(block)) => {
+                (*allocator).free(block);
+                1
+            },
+            _ => 0
+        }
+    }
+}
+
+#[test]
+pub fn frame_source_should_reclaim_cached_frames_before_reporting_no_memory() {
+    let mut allocator = buddy_allocator(0, 4 * FRAME_SIZE);
+    let frames = allocate_all_frames(&mut allocator);
+
+    assert!(allocator.allocate_frame().is_none());
+
+    unsafe {
+        RECLAIMED_ALLOCATOR = Some(&mut allocator as *mut BuddyAllocator);
+        RECLAIMED_BLOCK = Some(frames[2]);
+    }
+
+    allocator.set_reclaim_handler(reclaim_cached_block);
+
+    let frame = allocator.allocate_frame();
+
+    assert!(frame == Some(PhysFrame::containing_address(PhysAddr::new(frames[2]))), "Reclaimed frame wasn't allocated");
+    assert!(allocator.allocate_frame().is_none(), "Frame was allocated when nothing could be reclaimed");
+}
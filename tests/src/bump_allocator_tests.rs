@@ -0,0 +1,44 @@
+use stdx_memory::MemoryAllocator;
+use memory::allocator::bump::{BumpAllocator, ConstSizeBumpAllocator};
+
+// bump allocators never touch memory, so any address can be used
+
+#[test]
+pub fn bump_allocator_should_align_allocations() {
+    let mut allocator = BumpAllocator::from_address(0x1001, 0x1000);
+
+    assert_eq!(allocator.allocate_aligned(10, 16), Some(0x1010));
+    assert_eq!(allocator.allocate_aligned(1, 1), Some(0x101a));
+    assert_eq!(allocator.allocate_aligned(1, 256), Some(0x1100));
+}
+
+#[test]
+pub fn bump_allocator_should_align_allocations_for_every_size_and_alignment() {
+    for size in 1 .. 65 {
+        for align in (0 .. 8).map(|power| 1 << power) {
+            let mut allocator = BumpAllocator::from_address(0x1001, 0x1000);
+            allocator.allocate(size);
+
+            let result = allocator.allocate_aligned(size, align);
+
+            assert!(result.is_some(), "Bump allocator failed to allocate {} bytes aligned by {}", size, align);
+            assert_eq!(result.unwrap() % align, 0, "Bump allocator returned {:#x} for {} bytes aligned by {}", result.unwrap(), size, align);
+        }
+    }
+}
+
+#[test]
+pub fn bump_allocator_should_not_allocate_when_padding_exceeds_memory() {
+    let mut allocator = BumpAllocator::from_address(0x1001, 0x100);
+
+    assert_eq!(allocator.allocate_aligned(1, 0x1000), None);
+}
+
+#[test]
+pub fn const_size_bump_allocator_should_return_aligned_block() {
+    let mut allocator = ConstSizeBumpAllocator::from_size(0x1000, 0x100, 0x20);
+
+    assert_eq!(allocator.allocate_aligned(0x20, 0x20), Some(0x1000));
+    assert_eq!(allocator.allocate_aligned(0x20, 0x40), None);
+    assert_eq!(allocator.allocate_aligned(0x20, 0x20), Some(0x1020));
+}
@@ -1,4 +1,4 @@
-use memory::frame::Frame;
+use memory::frame::PhysFrame;
 use memory::frame::FRAME_SIZE;
 use stdx::iterator::IteratorExt;
 use stdx::Iterable;
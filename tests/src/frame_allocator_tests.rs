@@ -1,6 +1,7 @@
 use memory::frame::frame_allocator::FrameAllocator;
 use multiboot::multiboot_header::MultibootHeader;
-use memory::frame::Frame;
+use memory::frame::PhysFrame;
+use memory::address::PhysAddr;
 use memory::frame::FRAME_SIZE;
 use memory::allocator::bump::*;
 use stdx_memory::collections::frame_bitmap::FrameBitMap;
@@ -198,8 +199,8 @@ fn should_properly_determine_kernel_start_and_end_address() {
     let KERNEL_BASIC_HEAP_ALLOCATOR = ConstSizeBumpAllocator::from_address(kernel_heap_addr, 256, 20);
 
     let frame_allocator = FrameAllocator::new_test(multiboot_header1, KERNEL_BASIC_HEAP_ALLOCATOR);
-    let kernel_start_valid_result = Frame::from_address(0);
-    let kernel_end_valid_result = Frame::from_address(50 + 50);
+    let kernel_start_valid_result = PhysFrame::containing_address(PhysAddr::zero());
+    let kernel_end_valid_result = PhysFrame::containing_address(PhysAddr::new(50 + 50));
 
     assert!(frame_allocator.kernel_start_frame() == kernel_start_valid_result,
         "Frame allocator failed to determine kernel start frame. Start frame was {}, but should be {}. Frame allocator fields {}",
@@ -292,8 +293,8 @@ fn should_make_allocation_in_current_memory_area() {
             "Failed second allocation at address 14096. Frame allocator fields {}",
             frame_allocator);
 
-        let result1 = Frame::from_address(0);
-        let result2 = Frame::from_address(0 + FRAME_SIZE);
+        let result1 = PhysFrame::containing_address(PhysAddr::zero());
+        let result2 = PhysFrame::containing_address(PhysAddr::new(0 + FRAME_SIZE));
 
         assert!(allocation_result1.unwrap() == result1,
             "Invalid returned frame for allocation starting at address 10000. Returned frame {}, but should be {}. Frame allocator fields {}",
@@ -385,7 +386,7 @@ fn should_move_to_next_memory_area() {
             "Failed first allocation at address 14096. Frame allocator fields {}",
             frame_allocator);        
 
-        let result1 = Frame::from_address(14096);        
+        let result1 = PhysFrame::containing_address(PhysAddr::new(14096));        
 
         assert!(allocation_result.unwrap() == result1,
             "Invalid returned frame for allocation starting at address 14096. Returned frame {}, but should be {}. Frame allocator fields {}",
@@ -462,7 +463,7 @@ fn should_return_last_freed_frame() {
         let KERNEL_BASIC_HEAP_ALLOCATOR = ConstSizeBumpAllocator::from_address(kernel_heap_addr, 1024, 20);
         
         let mut frame_allocator = FrameAllocator::new_test(multiboot_header1, KERNEL_BASIC_HEAP_ALLOCATOR);        
-        let mut allocated_frames = Vec::<Option<Frame>>::new();
+        let mut allocated_frames = Vec::<Option<PhysFrame>>::new();
         let frame_count = 100000 / FRAME_SIZE;
 
         for i in 0..frame_count {
@@ -478,7 +479,7 @@ fn should_return_last_freed_frame() {
             frame_allocator.deallocate(allocated_frames[i].unwrap());
         }
 
-        let mut allocated_frames1 = Vec::<Option<Frame>>::new();
+        let mut allocated_frames1 = Vec::<Option<PhysFrame>>::new();
 
         for i in 0..frame_count {
             allocated_frames1.push(frame_allocator.allocate());
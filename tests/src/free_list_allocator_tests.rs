@@ -80,4 +80,32 @@ pub fn allocator_should_not_allocate_if_there_is_no_more_place_to_allocate() {
     assert!(result.is_none(), "FreeList allocator allocated memory from unknown source. Test buffer has size = {}, when block size is {}",
         0,
         10);
-}
\ No newline at end of file
+}
+#[test]
+pub fn allocator_should_return_aligned_blocks() {
+    let heap : Vec<u64> = vec![0; 128];
+    let mut allocator = FreeListAllocator::from_size(heap.as_ptr() as usize, 256, 16);
+
+    for align in [1, 2, 4, 8, 16, 32].iter() {
+        if let Some(result) = allocator.allocate_aligned(16, *align) {
+            assert_eq!(result % align, 0, "FreeList allocator returned {:#x} aligned by {}", result, align);
+        }
+    }
+
+    assert!(allocator.allocate_aligned(16, 1).is_some());
+}
+
+#[test]
+pub fn allocator_should_keep_misaligned_block_for_further_allocations() {
+    let heap : Vec<u64> = vec![0; 128];
+    let mut allocator = FreeListAllocator::from_size(heap.as_ptr() as usize, 256, 16);
+
+    let block = allocator.allocate(16).unwrap();
+    allocator.free(block);
+
+    // the smallest alignment the freed block doesn't have
+    let align = (block & block.wrapping_neg()) << 1;
+
+    assert_eq!(allocator.allocate_aligned(16, align), None);
+    assert_eq!(allocator.allocate(16), Some(block), "Misaligned block wasn't returned to allocator");
+}
@@ -0,0 +1,135 @@
+use memory::allocator::debugging::{CorruptionKind, RED_ZONE_SIZE};
+use stdx_memory::MemoryAllocator;
+use slab_allocator_tests::slab_allocator;
+
+const FRAMES_COUNT : usize = 64;
+
+fn write(address : usize, value : u8) {
+    unsafe { *(address as *mut u8) = value; }
+}
+
+#[test]
+pub fn should_keep_heap_consistent_when_objects_stay_inside_their_memory() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointers : Vec<(usize, usize)> = [1, 10, 64, 500, 5000]
+        .iter()
+        .map(|&size| (allocator.allocate(size).unwrap(), size))
+        .collect();
+
+    for &(pointer, size) in pointers.iter() {
+        for i in 0 .. size {
+            write(pointer + i, 0xAB);
+        }
+    }
+
+    assert_eq!(allocator.verify(), Ok(()));
+
+    for (pointer, _) in pointers {
+        allocator.free(pointer);
+    }
+
+    assert_eq!(allocator.verify(), Ok(()));
+}
+
+#[test]
+pub fn should_keep_alignment_of_guarded_objects() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    for align in (0 .. 13).map(|power| 1 << power) {
+        let pointer = allocator.allocate_aligned(24, align).unwrap();
+
+        assert_eq!(pointer % align, 0, "Guarded object {:#x} isn't aligned by {}", pointer, align);
+
+        allocator.free(pointer);
+    }
+}
+
+#[test]
+pub fn should_report_write_after_the_end_of_object() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(10).unwrap();
+    write(pointer + 10, 0);
+
+    let corruption = allocator.verify().unwrap_err();
+
+    assert_eq!(corruption.kind, CorruptionKind::RedZoneOverwritten);
+    assert_eq!(corruption.address, pointer + 10);
+    assert_eq!(corruption.object, pointer);
+    // 10 bytes with two red zones are allocated from 128 bytes slab
+    assert_eq!(corruption.size_class, 128);
+}
+
+#[test]
+pub fn should_report_write_before_the_start_of_object() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(10).unwrap();
+    write(pointer - RED_ZONE_SIZE, 0);
+
+    let corruption = allocator.verify().unwrap_err();
+
+    assert_eq!(corruption.kind, CorruptionKind::RedZoneOverwritten);
+    assert_eq!(corruption.address, pointer - RED_ZONE_SIZE);
+}
+
+#[test]
+pub fn should_report_write_to_freed_object_on_verify() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(100).unwrap();
+    allocator.free(pointer);
+
+    write(pointer + 5, 0);
+
+    let corruption = allocator.verify().unwrap_err();
+
+    assert_eq!(corruption.kind, CorruptionKind::UseAfterFree);
+    assert_eq!(corruption.address, pointer + 5);
+    assert_eq!(corruption.object, pointer);
+    assert_eq!(corruption.size_class, 256);
+}
+
+#[test]
+#[should_panic(expected = "UseAfterFree")]
+pub fn should_report_write_to_freed_object_on_next_allocation() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(100).unwrap();
+    allocator.free(pointer);
+
+    write(pointer, 0);
+
+    allocator.allocate(100);
+}
+
+#[test]
+#[should_panic(expected = "DoubleFree")]
+pub fn should_report_double_free() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(100).unwrap();
+
+    allocator.free(pointer);
+    allocator.free(pointer);
+}
+
+#[test]
+pub fn should_return_quarantined_objects_to_allocator_on_flush() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let before = allocator.statistics();
+
+    let pointers : Vec<usize> = (0 .. 10).map(|_| allocator.allocate(100).unwrap()).collect();
+
+    for pointer in pointers {
+        allocator.free(pointer);
+    }
+
+    assert!(!allocator.statistics().diff(&before).is_empty(), "Freed objects weren't kept in quarantine");
+
+    allocator.flush_quarantine();
+    allocator.shrink();
+
+    assert!(allocator.statistics().diff(&before).is_empty());
+}
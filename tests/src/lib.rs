@@ -14,3 +14,13 @@ mod double_linked_list_tests;
 mod free_list_allocator_tests;
 mod buddy_free_list_tests;
 mod buddy_allocator_tests;
+mod address_tests;
+mod page_mapping_tests;
+mod memory_regions_tests;
+mod memory_handover_tests;
+mod zone_allocator_tests;
+mod slab_allocator_tests;
+mod bump_allocator_tests;
+mod object_cache_tests;
+#[cfg(feature = "heap_debugging")]
+mod heap_debugging_tests;
@@ -1,7 +1,8 @@
 use memory::allocator::bump::BumpAllocator;
 use stdx_memory::collections::linked_list::{LinkedList, LinkedListIterator};
 use stdx_memory::heap::Box;
-use memory::frame::Frame;
+use memory::frame::PhysFrame;
+use memory::address::PhysAddr;
 use std::ops::Deref;
 use memory::frame::FRAME_SIZE;
 
@@ -115,14 +116,14 @@ pub fn add_should_create_a_new_cell_with_reference_to_the_old_one_nil_case() {
 fn adding_elems_should_work_properly() {
     let mut bump_allocator = bump_alloc!(256);
     let test_values  = [
-        Frame::from_address(0), 
-        Frame::from_address(FRAME_SIZE * 2), 
-        Frame::from_address(FRAME_SIZE * 3),
-        Frame::from_address(FRAME_SIZE * 4), 
-        Frame::from_address(FRAME_SIZE * 12), 
-        Frame::from_address(FRAME_SIZE * 20), 
-        Frame::from_address(FRAME_SIZE * 44), 
-        Frame::from_address(FRAME_SIZE * 10)
+        PhysFrame::containing_address(PhysAddr::zero()), 
+        PhysFrame::containing_address(PhysAddr::new(FRAME_SIZE * 2)), 
+        PhysFrame::containing_address(PhysAddr::new(FRAME_SIZE * 3)),
+        PhysFrame::containing_address(PhysAddr::new(FRAME_SIZE * 4)), 
+        PhysFrame::containing_address(PhysAddr::new(FRAME_SIZE * 12)), 
+        PhysFrame::containing_address(PhysAddr::new(FRAME_SIZE * 20)), 
+        PhysFrame::containing_address(PhysAddr::new(FRAME_SIZE * 44)), 
+        PhysFrame::containing_address(PhysAddr::new(FRAME_SIZE * 10))
     ];
     let test_values_len = test_values.len();
     let mut head = LinkedList::new(test_values[0], &mut bump_allocator);
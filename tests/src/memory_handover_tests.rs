@@ -0,0 +1,231 @@
+use memory::address::{PhysAddr, PhysAddrRange};
+use memory::allocator::bump::ConstSizeBumpAllocator;
+use memory::allocator::buddy::BuddyAllocator;
+use memory::allocator::zone::Zone;
+use memory::frame::{PhysFrame, FrameSource, FRAME_SIZE};
+use memory::frame::frame_allocator::FrameAllocator;
+use memory::frame::regions::MemoryRegion;
+use multiboot::multiboot_header::MultibootHeader;
+use stdx_memory::collections::linked_list::LinkedList;
+
+const AVAILABLE : u64 = 1;
+const RESERVED : u64 = 2;
+
+const ELF_SECTION_SIZE : usize = 64;
+
+// builds multiboot information with memory map and a single kernel elf section,
+// tags are written byte by byte, because elf section entries are not aligned
+fn multiboot_information(memory : &[(u64, u64, u64)], kernel_start : u64, kernel_size : u64) -> Vec<u64> {
+    let mut bytes = Vec::new();
+
+    push(&mut bytes, 0, 4);                                  // total size, written below
+    push(&mut bytes, 0, 4);                                  // reserved
+
+    push(&mut bytes, 6, 4);                                  // memory map tag
+    push(&mut bytes, 16 + 24 * memory.len() as u64, 4);
+    push(&mut bytes, 24, 4);                                 // entry size
+    push(&mut bytes, 0, 4);                                  // entry version
+
+    for &(base_address, length, entry_type) in memory {
+        push(&mut bytes, base_address, 8);
+        push(&mut bytes, length, 8);
+        push(&mut bytes, entry_type, 4);
+        push(&mut bytes, 0, 4);
+    }
+
+    push(&mut bytes, 9, 4);                                  // elf sections tag
+    push(&mut bytes, 20 + ELF_SECTION_SIZE as u64, 4);
+    push(&mut bytes, 1, 4);                                  // entries count
+    push(&mut bytes, ELF_SECTION_SIZE as u64, 4);
+    push(&mut bytes, 0, 4);                                  // string table index
+
+    push(&mut bytes, 0, 4);                                  // name
+    push(&mut bytes, 1, 4);                                  // section type
+    push(&mut bytes, 0x2, 8);                                // flags, allocated
+    push(&mut bytes, kernel_start, 8);
+    push(&mut bytes, 0, 8);                                  // offset
+    push(&mut bytes, kernel_size, 8);
+    push(&mut bytes, 0, 4);                                  // link
+    push(&mut bytes, 0, 4);                                  // info
+    push(&mut bytes, 0, 8);                                  // address align
+    push(&mut bytes, 0, 8);                                  // entry size
+
+    while bytes.len() % 8 != 0 {
+        bytes.push(0);
+    }
+
+    push(&mut bytes, 0, 4);                                  // end tag
+    push(&mut bytes, 8, 4);
+
+    let total_size = bytes.len() as u64;
+
+    (0 .. bytes.len() / 8)
+        .map(|i| (0 .. 8).fold(0, |word, b| word | (bytes[i * 8 + b] as u64) << (8 * b)))
+        .enumerate()
+        .map(|(i, word)| if i == 0 { word | total_size } else { word })
+        .collect()
+}
+
+fn push(bytes : &mut Vec<u8>, value : u64, size : usize) {
+    for i in 0 .. size {
+        bytes.push((value >> (8 * i)) as u8);
+    }
+}
+
+// replaces memory map entry of already built multiboot information, e.g. to make it cover information itself
+fn set_memory_area(information : &mut Vec<u64>, index : usize, base_address : u64, length : u64) {
+    information[3 + 3 * index] = base_address;
+    information[4 + 3 * index] = length;
+}
+
+fn frame_allocator(information : &Vec<u64>, frame_list : &mut Vec<u64>) -> FrameAllocator {
+    let multiboot_header = MultibootHeader::load(information.as_ptr() as usize);
+    let frame_list_allocator = ConstSizeBumpAllocator::from_address_for_type::<LinkedList<PhysFrame>>(frame_list.as_mut_ptr() as usize, frame_list.len() * 8);
+
+    FrameAllocator::new_test(multiboot_header, frame_list_allocator)
+}
+
+fn region(start_address : usize, end_address : usize) -> MemoryRegion {
+    MemoryRegion::new(PhysAddr::new(start_address), PhysAddr::new(end_address))
+}
+
+// frame source that only remembers returned frames
+struct ReclaimedFrames {
+    frames : Vec<PhysFrame>
+}
+
+impl FrameSource for ReclaimedFrames {
+
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.frames.pop()
+    }
+
+    fn allocate_contiguous(&mut self, _count : usize, _align : usize) -> Option<PhysFrame> {
+        None
+    }
+
+    fn deallocate_frame(&mut self, frame : PhysFrame) {
+        self.frames.push(frame)
+    }
+
+    fn free_frames_count(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+#[test]
+pub fn should_hand_over_available_memory_without_kernel_and_allocated_frames() {
+    let information = multiboot_information(&[
+        (0x1000, 0x9e000, AVAILABLE),
+        (0x9f000, 0x61000, RESERVED),
+        (0x100000, 0x400000, AVAILABLE)
+    ], 0x100000, 0x50000);
+    let mut frame_list = vec![0; 512];
+    let mut allocator = frame_allocator(&information, &mut frame_list);
+
+    let frames : Vec<PhysFrame> = (0 .. 3).map(|_| allocator.allocate_frame().unwrap()).collect();
+
+    allocator.deallocate_frame(frames[1]);
+
+    let handover = allocator.handover(PhysFrame::containing_address(PhysAddr::new(0x120000)));
+    let free : Vec<MemoryRegion> = handover.free_regions().iter().collect();
+
+    assert_eq!(free, vec![
+        region(0x2000, 0x2fff),         // freed during boot
+        region(0x4000, 0x9efff),
+        region(0x120000, 0x120fff),     // boot page table inside kernel memory
+        region(0x150000, 0x4fffff)
+    ]);
+    assert!(handover.multiboot_regions().is_empty(), "Multiboot information outside of memory map is reclaimable");
+}
+
+#[test]
+pub fn should_return_frames_skipped_by_aligned_boot_allocation() {
+    let information = multiboot_information(&[(0x1000, 0x1ff000, AVAILABLE)], 0x100000, 0x10000);
+    let mut frame_list = vec![0; 512];
+    let mut allocator = frame_allocator(&information, &mut frame_list);
+
+    let block = allocator.allocate_contiguous(2, 4).unwrap();
+
+    assert_eq!(block.start_address(), PhysAddr::new(0x4000));
+
+    let handover = allocator.handover(PhysFrame::containing_address(PhysAddr::new(0x100000)));
+    let free : Vec<MemoryRegion> = handover.free_regions().iter().collect();
+
+    // boot page table frame is the first frame of kernel memory
+    assert_eq!(free, vec![region(0x1000, 0x3fff), region(0x6000, 0x100fff), region(0x110000, 0x1fffff)]);
+}
+
+#[test]
+pub fn should_reserve_multiboot_information_until_it_is_reclaimed() {
+    let mut information = multiboot_information(&[(0x1000, 0x10000, AVAILABLE), (0x20000, 0x1000, AVAILABLE)], 0x2000, 0x1000);
+    let information_address = information.as_ptr() as u64;
+    let information_end_address = information_address + information.len() as u64 * 8 - 1;
+    let area_start = (information_address / FRAME_SIZE as u64 - 4) * FRAME_SIZE as u64;
+
+    set_memory_area(&mut information, 1, area_start, 16 * FRAME_SIZE as u64);
+
+    let mut frame_list = vec![0; 512];
+    let allocator = frame_allocator(&information, &mut frame_list);
+    let mut handover = allocator.handover(PhysFrame::containing_address(PhysAddr::new(0x2000)));
+
+    let multiboot_frames : Vec<PhysFrame> = PhysAddrRange::new(PhysAddr::new(information_address as usize), PhysAddr::new(information_end_address as usize)).frames().collect();
+    let is_free = |regions : &Vec<MemoryRegion>, frame : &PhysFrame| regions.iter().any(|r| r.contains(frame.start_address()));
+
+    let free : Vec<MemoryRegion> = handover.free_regions().iter().collect();
+
+    assert!(multiboot_frames.iter().all(|f| !is_free(&free, f)), "Multiboot information is handed over as free memory");
+    assert_eq!(handover.multiboot_regions().total_size(), multiboot_frames.len() * FRAME_SIZE);
+    assert_eq!(handover.free_regions().total_size() + handover.multiboot_regions().total_size(), 0x10000 + 16 * FRAME_SIZE);
+
+    let mut reclaimed = ReclaimedFrames { frames : Vec::new() };
+
+    assert_eq!(handover.reclaim_multiboot(&mut reclaimed), multiboot_frames.len());
+    assert!(reclaimed.frames == multiboot_frames, "Reclaimed frames are not frames of multiboot information");
+    assert!(handover.multiboot_regions().is_empty());
+}
+
+#[test]
+pub fn should_manage_multiboot_information_at_the_top_of_the_last_area() {
+    let mut information = multiboot_information(&[(0x1000, 0x10000, AVAILABLE), (0x20000, 0x1000, AVAILABLE)], 0x2000, 0x1000);
+    let information_address = information.as_ptr() as u64;
+    let information_end_address = information_address + information.len() as u64 * 8 - 1;
+    let area_start = (information_address / FRAME_SIZE as u64 - 4) * FRAME_SIZE as u64;
+    let area_end = (information_end_address / FRAME_SIZE as u64 + 1) * FRAME_SIZE as u64;
+
+    // the last area ends with the last frame of multiboot information
+    set_memory_area(&mut information, 1, area_start, area_end - area_start);
+
+    let mut frame_list = vec![0; 512];
+    let allocator = frame_allocator(&information, &mut frame_list);
+    let handover = allocator.handover(PhysFrame::containing_address(PhysAddr::new(0x2000)));
+
+    let last_multiboot_frame = PhysFrame::containing_address(PhysAddr::new(information_end_address as usize));
+    let free_span = handover.free_regions().span().unwrap();
+    let managed_span = handover.managed_regions().span().unwrap();
+
+    assert!(free_span.end_address() < last_multiboot_frame.start_address(), "Multiboot information isn't above free regions");
+    assert!(managed_span.contains(last_multiboot_frame.start_address()), "Managed memory doesn't cover multiboot information");
+    assert!(BuddyAllocator::frames_count_for(&handover.managed_regions()) > last_multiboot_frame.number(),
+            "Frame allocator of managed memory can't index the last frame of multiboot information");
+    assert_eq!(handover.managed_regions().total_size(), handover.free_regions().total_size() + handover.multiboot_regions().total_size());
+}
+
+#[test]
+pub fn should_take_zone_memory_from_the_biggest_free_region_of_the_zone() {
+    let information = multiboot_information(&[(0x1000, 0x9e000, AVAILABLE), (0x100000, 0x400000, AVAILABLE)], 0x100000, 0x50000);
+    let mut frame_list = vec![0; 512];
+    let allocator = frame_allocator(&information, &mut frame_list);
+    let mut handover = allocator.handover(PhysFrame::containing_address(PhysAddr::new(0x100000)));
+
+    let free_before = handover.free_regions().total_size();
+    let dma = handover.take_zone(Zone::Dma, 0x10001).unwrap();
+
+    assert_eq!(dma, PhysAddrRange::new(PhysAddr::new(0x150000), PhysAddr::new(0x160fff)));
+    assert_eq!(handover.free_regions().total_size(), free_before - dma.size());
+    assert!(handover.free_regions().iter().all(|r| !r.contains(dma.start_address()) && !r.contains(dma.end_address())),
+            "Zone memory is still free");
+
+    // memory map has no memory above 16 MiB
+    assert!(handover.take_zone(Zone::Dma32, FRAME_SIZE).is_none());
+}
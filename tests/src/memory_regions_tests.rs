@@ -0,0 +1,188 @@
+use memory::address::{PhysAddr, PhysAddrRange};
+use memory::frame::FRAME_SIZE;
+use memory::frame::regions::{MemoryRegion, MemoryRegions, MAX_MEMORY_REGIONS};
+use multiboot::multiboot_header::tags::memory_map::MemoryMap;
+use std::mem;
+
+const AVAILABLE : u64 = 1;
+const RESERVED : u64 = 2;
+
+// builds memory map tag, u64 words keep entries properly aligned
+pub fn memory_map_tag(entries : &[(u64, u64, u64)]) -> Vec<u64> {
+    let entry_size = 24;
+    let tag_size = 16 + entry_size * entries.len() as u64;
+
+    let mut tag = vec![
+        6 | (tag_size << 32),   // tag type, tag size
+        entry_size              // entry size, version
+    ];
+
+    for &(base_address, length, entry_type) in entries {
+        tag.push(base_address);
+        tag.push(length);
+        tag.push(entry_type);  // entry type, reserved
+    }
+
+    tag
+}
+
+pub fn memory_map(tag : &Vec<u64>) -> &MemoryMap {
+    assert!(mem::size_of::<MemoryMap>() <= tag.len() * 8);
+
+    unsafe { &*(tag.as_ptr() as *const MemoryMap) }
+}
+
+fn region(start_address : usize, end_address : usize) -> MemoryRegion {
+    MemoryRegion::new(PhysAddr::new(start_address), PhysAddr::new(end_address))
+}
+
+fn regions_of(memory : &[(usize, usize)]) -> MemoryRegions {
+    let mut regions = MemoryRegions::new();
+
+    for &(start_address, size) in memory {
+        assert!(regions.add(PhysAddr::new(start_address), size));
+    }
+
+    regions
+}
+
+#[test]
+pub fn should_keep_regions_sorted() {
+    let regions = regions_of(&[(0x100000, 0x1000), (0x0, 0x1000), (0x10000, 0x2000)]);
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![region(0x0, 0xfff), region(0x10000, 0x11fff), region(0x100000, 0x100fff)]);
+}
+
+#[test]
+pub fn should_merge_adjacent_and_overlapping_regions() {
+    let regions = regions_of(&[(0x0, 0x2000), (0x2000, 0x1000), (0x10000, 0x4000), (0x11000, 0x8000)]);
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![region(0x0, 0x2fff), region(0x10000, 0x18fff)]);
+}
+
+#[test]
+pub fn should_merge_region_that_fills_the_hole() {
+    let regions = regions_of(&[(0x0, 0x1000), (0x3000, 0x1000), (0x1000, 0x2000)]);
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![region(0x0, 0x3fff)]);
+}
+
+#[test]
+pub fn should_shrink_region_to_frame_boundaries() {
+    let regions = regions_of(&[(0x10, 0x3000)]);
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![region(0x1000, 0x2fff)]);
+}
+
+#[test]
+pub fn should_ignore_memory_smaller_than_frame() {
+    let regions = regions_of(&[(0x10, FRAME_SIZE), (0x5000, 100)]);
+
+    assert!(regions.is_empty(), "Memory without a whole frame was added to regions");
+}
+
+#[test]
+pub fn should_not_add_more_regions_than_max() {
+    let mut regions = MemoryRegions::new();
+
+    for i in 0 .. MAX_MEMORY_REGIONS {
+        assert!(regions.add(PhysAddr::new(i * 2 * FRAME_SIZE), FRAME_SIZE));
+    }
+
+    let result = regions.add(PhysAddr::new(MAX_MEMORY_REGIONS * 2 * FRAME_SIZE), FRAME_SIZE);
+
+    assert!(!result, "Region was added to full regions set");
+    assert_eq!(regions.count(), MAX_MEMORY_REGIONS);
+}
+
+#[test]
+pub fn should_split_region_when_reserved_memory_is_inside() {
+    let mut regions = regions_of(&[(0x0, 0x10000)]);
+
+    assert!(regions.reserve(PhysAddrRange::new(PhysAddr::new(0x4000), PhysAddr::new(0x5fff))));
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![region(0x0, 0x3fff), region(0x6000, 0xffff)]);
+}
+
+#[test]
+pub fn should_extend_reserved_memory_to_frame_boundaries() {
+    let mut regions = regions_of(&[(0x0, 0x10000)]);
+
+    assert!(regions.reserve(PhysAddrRange::new(PhysAddr::new(0x4010), PhysAddr::new(0x5010))));
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![region(0x0, 0x3fff), region(0x6000, 0xffff)]);
+}
+
+#[test]
+pub fn should_reserve_memory_across_regions() {
+    let mut regions = regions_of(&[(0x0, 0x4000), (0x8000, 0x4000), (0x10000, 0x4000)]);
+
+    assert!(regions.reserve(PhysAddrRange::new(PhysAddr::new(0x2000), PhysAddr::new(0x11fff))));
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![region(0x0, 0x1fff), region(0x12000, 0x13fff)]);
+}
+
+#[test]
+pub fn should_take_memory_from_first_fitting_region() {
+    let mut regions = regions_of(&[(0x0, 0x2000), (0x10000, 0x10000)]);
+
+    let result = regions.take(0x3000);
+
+    assert_eq!(result, Some(PhysAddr::new(0x10000)));
+    assert_eq!(regions.iter().collect::<Vec<MemoryRegion>>(), vec![region(0x0, 0x1fff), region(0x13000, 0x1ffff)]);
+}
+
+#[test]
+pub fn should_remove_region_that_was_taken_entirely() {
+    let mut regions = regions_of(&[(0x0, 0x2000), (0x10000, 0x10000)]);
+
+    let result = regions.take(0x1001);
+
+    assert_eq!(result, Some(PhysAddr::new(0x0)));
+    assert_eq!(regions.iter().collect::<Vec<MemoryRegion>>(), vec![region(0x10000, 0x1ffff)]);
+}
+
+#[test]
+pub fn should_not_take_memory_bigger_than_any_region() {
+    let mut regions = regions_of(&[(0x0, 0x2000), (0x10000, 0x2000)]);
+
+    assert_eq!(regions.take(0x3000), None);
+    assert_eq!(regions.total_size(), 0x4000);
+}
+
+#[test]
+pub fn should_read_available_regions_from_memory_map() {
+    let tag = memory_map_tag(&[
+        (0x0, 0x9fc00, AVAILABLE),
+        (0x9fc00, 0x400, RESERVED),
+        (0xf0000, 0x10000, RESERVED),
+        (0x100000, 0x7ee0000, AVAILABLE),
+        (0x1_0000_0000, 0x4000_0000, AVAILABLE)
+    ]);
+
+    let regions = MemoryRegions::from_memory_map(memory_map(&tag));
+
+    let result : Vec<MemoryRegion> = regions.iter().collect();
+
+    assert_eq!(result, vec![
+        region(0x0, 0x9efff),
+        region(0x100000, 0x7fdffff),
+        region(0x1_0000_0000, 0x1_3fff_ffff)
+    ]);
+
+    assert_eq!(regions.span(), Some(region(0x0, 0x1_3fff_ffff)));
+}
@@ -0,0 +1,136 @@
+use memory::allocator::slab::ObjectCache;
+use stdx_memory::MemoryAllocator;
+use memory::frame::FRAME_SIZE;
+use slab_allocator_tests::slab_allocator;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const FRAMES_COUNT : usize = 64;
+
+#[derive(Debug, PartialEq)]
+struct Descriptor {
+    id       : u64,
+    parent   : u64,
+    priority : u64,
+    state    : u64,
+    flags    : u64,
+}
+
+impl Descriptor {
+    fn empty() -> Self {
+        Descriptor { id : 7, parent : 0, priority : 1, state : 0, flags : 0 }
+    }
+}
+
+#[repr(align(64))]
+struct AlignedBlock {
+    value : u8
+}
+
+static DESTROYED_DESCRIPTORS : AtomicUsize = AtomicUsize::new(0);
+
+fn destroy_descriptor(descriptor : &mut Descriptor) {
+    descriptor.state = 0xdead;
+    DESTROYED_DESCRIPTORS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+pub fn should_use_exact_object_size() {
+    assert_eq!(ObjectCache::<Descriptor>::new("descriptor", None, None).object_size(), 40);
+    assert_eq!(ObjectCache::<u8>::new("byte", None, None).object_size(), 32);
+    assert_eq!(ObjectCache::<AlignedBlock>::new("aligned", None, None).object_size(), 64);
+}
+
+#[test]
+pub fn should_create_object_with_constructor() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut cache = ObjectCache::new("descriptor", Some(Descriptor::empty), None);
+
+    let descriptor = cache.allocate(&mut allocator).unwrap();
+
+    assert_eq!(unsafe { descriptor.as_ref() }, &Descriptor::empty());
+}
+
+#[test]
+pub fn should_call_destructor_on_free() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut cache = ObjectCache::new("descriptor", None, Some(destroy_descriptor));
+
+    let descriptor = cache.allocate_value(Descriptor { id : 1, parent : 0, priority : 2, state : 1, flags : 0 }, &mut allocator).unwrap();
+    cache.free(descriptor, &mut allocator);
+
+    assert_eq!(DESTROYED_DESCRIPTORS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+pub fn should_pack_objects_without_rounding_their_size() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut cache = ObjectCache::<Descriptor>::new("descriptor", Some(Descriptor::empty), None);
+    let objects_per_slab = FRAME_SIZE / 40;
+
+    let mut objects : Vec<usize> = (0 .. objects_per_slab)
+        .map(|_| cache.allocate(&mut allocator).unwrap().as_ptr() as usize)
+        .collect();
+
+    assert_eq!(cache.statistics().slabs, 1, "Objects of exact size didn't fit a single slab");
+
+    objects.sort();
+    objects.dedup();
+
+    assert_eq!(objects.len(), objects_per_slab);
+    assert!(objects.windows(2).all(|pair| pair[1] - pair[0] == 40));
+
+    cache.allocate(&mut allocator).unwrap();
+
+    assert_eq!(cache.statistics().slabs, 2);
+}
+
+#[test]
+pub fn should_align_objects_by_type_alignment() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut cache = ObjectCache::new("aligned", None, None);
+
+    for _ in 0 .. 100 {
+        let object = cache.allocate_value(AlignedBlock { value : 1 }, &mut allocator).unwrap();
+
+        assert_eq!(object.as_ptr() as usize % 64, 0);
+    }
+}
+
+#[test]
+pub fn should_cache_empty_slab_and_return_it_on_shrink() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut cache = ObjectCache::<Descriptor>::new("descriptor", Some(Descriptor::empty), None);
+
+    let objects : Vec<_> = (0 .. 10).map(|_| cache.allocate(&mut allocator).unwrap()).collect();
+
+    for object in objects {
+        cache.free(object, &mut allocator);
+    }
+
+    let statistics = cache.statistics();
+
+    assert_eq!((statistics.live_objects, statistics.peak_live_objects, statistics.slabs, statistics.cached_slabs), (0, 10, 0, 1));
+    assert_eq!(cache.shrink(&mut allocator), 2);
+    assert_eq!(cache.statistics().cached_slabs, 0);
+}
+
+#[test]
+pub fn should_show_cache_name_in_statistics() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut cache = ObjectCache::<Descriptor>::new("descriptor", Some(Descriptor::empty), None);
+
+    cache.allocate(&mut allocator).unwrap();
+
+    assert_eq!(format!("{}", cache), "descriptor (40 B): live 1, peak 1, slabs 1, cached 0, failed 0");
+}
+
+#[test]
+#[should_panic]
+pub fn slab_allocator_should_refuse_to_free_object_of_cache() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut cache = ObjectCache::new("descriptor", Some(Descriptor::empty), None);
+
+    let descriptor = cache.allocate(&mut allocator).unwrap();
+
+    allocator.free(descriptor.as_ptr() as usize);
+}
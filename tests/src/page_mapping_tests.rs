@@ -0,0 +1,49 @@
+use memory::address::{VirtAddr, PhysAddr};
+use memory::paging::page::PageSize;
+use memory::paging::mapping::Mapping;
+use memory::paging::page_table;
+
+#[test]
+pub fn mapping_should_merge_contiguous_mapping_with_same_flags() {
+    let flags = page_table::PRESENT | page_table::WRITABLE;
+    let first = Mapping::new(VirtAddr::new(0x1000), PhysAddr::new(0x5000), PageSize::Size4K, flags);
+    let second = Mapping::new(VirtAddr::new(0x2000), PhysAddr::new(0x6000), PageSize::Size4K, flags);
+
+    let result = first.try_merge(&second);
+
+    assert!(result.is_some(), "Contiguous mappings with the same flags weren't merged");
+
+    let merged = result.unwrap();
+
+    assert_eq!(merged.virtual_start, VirtAddr::new(0x1000));
+    assert_eq!(merged.physical_start, PhysAddr::new(0x5000));
+    assert_eq!(merged.size, 0x2000);
+    assert_eq!(merged.virtual_end(), VirtAddr::new(0x2fff));
+}
+
+#[test]
+pub fn mapping_should_ignore_accessed_and_dirty_flags_when_merging() {
+    let first = Mapping::new(VirtAddr::new(0x1000), PhysAddr::new(0x1000), PageSize::Size4K, page_table::PRESENT | page_table::ACCESSED);
+    let second = Mapping::new(VirtAddr::new(0x2000), PhysAddr::new(0x2000), PageSize::Size4K, page_table::PRESENT | page_table::DIRTY);
+
+    assert!(first.try_merge(&second).is_some(), "Accessed and dirty flags should not prevent merging");
+}
+
+#[test]
+pub fn mapping_should_not_merge_mappings_with_physical_gap() {
+    let flags = page_table::PRESENT;
+    let first = Mapping::new(VirtAddr::new(0x1000), PhysAddr::new(0x5000), PageSize::Size4K, flags);
+    let second = Mapping::new(VirtAddr::new(0x2000), PhysAddr::new(0x9000), PageSize::Size4K, flags);
+
+    assert!(first.try_merge(&second).is_none(), "Mappings that are not contiguous in physical memory were merged");
+}
+
+#[test]
+pub fn mapping_should_not_merge_mappings_with_different_flags_or_page_sizes() {
+    let first = Mapping::new(VirtAddr::new(0x0), PhysAddr::new(0x0), PageSize::Size2M, page_table::PRESENT);
+    let writable = Mapping::new(VirtAddr::new(0x200000), PhysAddr::new(0x200000), PageSize::Size2M, page_table::PRESENT | page_table::WRITABLE);
+    let small = Mapping::new(VirtAddr::new(0x200000), PhysAddr::new(0x200000), PageSize::Size4K, page_table::PRESENT);
+
+    assert!(first.try_merge(&writable).is_none(), "Mappings with different flags were merged");
+    assert!(first.try_merge(&small).is_none(), "Mappings with different page sizes were merged");
+}
@@ -0,0 +1,386 @@
+use memory::frame::FRAME_SIZE;
+use memory::frame::regions::MemoryRegions;
+use memory::address::PhysAddr;
+use memory::allocator::slab::SlabAllocator;
+#[cfg(not(feature = "heap_debugging"))]
+use memory::allocator::slab::MAX_CACHED_SLABS;
+#[cfg(not(feature = "heap_debugging"))]
+use memory::allocator::statistics::{ReallocStatistics, SizeClassStatistics};
+#[cfg(not(feature = "heap_debugging"))]
+use stdx_memory::MemoryAllocator;
+use alloc::heap;
+
+// Tests below expect freed objects to return to allocator right away and objects without red zones,
+// heap debugging quarantines freed objects and surrounds them by red zones, see heap_debugging_tests
+#[cfg(not(feature = "heap_debugging"))]
+const FRAMES_COUNT : usize = 64;
+
+// allocator of host memory, physical frame N is the frame N of the heap.
+// Heap is aligned by its size, so the biggest block is as aligned as the physical one
+pub fn slab_allocator(frames_count : usize) -> SlabAllocator {
+    let heap_size = frames_count * FRAME_SIZE;
+    let heap = unsafe { heap::allocate_zeroed(heap_size, heap_size.next_power_of_two()) as usize };
+
+    let mut regions = MemoryRegions::new();
+    regions.add(PhysAddr::new(0), frames_count * FRAME_SIZE);
+
+    let aux_data_structures_size = SlabAllocator::aux_data_structures_size_for(&regions);
+    let aux_start_address = unsafe { heap::allocate_zeroed(aux_data_structures_size, FRAME_SIZE) as usize };
+
+    SlabAllocator::from_regions(&regions, aux_start_address, heap)
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_be_fully_free_after_freeing_small_objects_by_pointer() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointers : Vec<usize> = [32, 48, 100, 256, 2000, 32, 64]
+        .iter()
+        .map(|&size| allocator.allocate(size).expect("Slab allocator failed to allocate small object"))
+        .collect();
+
+    for pointer in pointers {
+        allocator.free(pointer);
+    }
+
+    assert!(allocator.is_fully_free(), "Slab allocator isn't fully free after every object was freed");
+}
+
+// every iteration needs frames, so leaked frames exhaust memory long before the loop ends
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_return_frames_of_empty_slab_to_frame_allocator() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    for _ in 0 .. FRAMES_COUNT * 4 {
+        let object = allocator.allocate(64).expect("Frames of empty slab weren't returned to frame allocator");
+        allocator.free(object);
+    }
+
+    assert!(allocator.is_fully_free());
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_free_frame_sized_allocations_by_pointer() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    for _ in 0 .. FRAMES_COUNT * 4 {
+        let block = allocator.allocate(8 * FRAME_SIZE).expect("Freed block wasn't returned to frame allocator");
+        allocator.free(block);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_free_frames_that_were_used_by_slab_as_blocks() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    for _ in 0 .. FRAMES_COUNT * 4 {
+        let object = allocator.allocate(128).unwrap();
+        allocator.free(object);
+
+        // frames of the freed slab can be a part of these blocks now
+        let blocks : Vec<usize> = (0 .. 4)
+            .map(|_| allocator.allocate(FRAME_SIZE).expect("Frames that were used by slab weren't freed as blocks"))
+            .collect();
+
+        for block in blocks {
+            allocator.free(block);
+        }
+    }
+
+    assert!(allocator.is_fully_free());
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_align_allocations_for_every_size_and_alignment() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let sizes = [1, 7, 16, 31, 33, 100, 512, 1000, 2048, 4095, FRAME_SIZE, FRAME_SIZE + 1, 3 * FRAME_SIZE];
+
+    for &size in sizes.iter() {
+        for align in (0 .. 14).map(|power| 1 << power) {
+            let result = allocator.allocate_aligned(size, align);
+
+            assert!(result.is_some(), "Slab allocator failed to allocate {} bytes aligned by {}", size, align);
+            assert_eq!(result.unwrap() % align, 0, "Slab allocator returned {:#x} for {} bytes aligned by {}", result.unwrap(), size, align);
+
+            allocator.free(result.unwrap());
+        }
+    }
+
+    assert!(allocator.is_fully_free());
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_align_live_allocations_of_different_sizes() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut pointers = Vec::new();
+
+    for &(size, align) in [(8, 64), (24, 8), (100, 128), (16, 16), (64, 4096), (40, 8), (4096, 8192)].iter() {
+        let pointer = allocator.allocate_aligned(size, align).unwrap();
+
+        assert_eq!(pointer % align, 0, "Slab allocator returned {:#x} for {} bytes aligned by {}", pointer, size, align);
+
+        pointers.push(pointer);
+    }
+
+    for pointer in pointers {
+        allocator.free(pointer);
+    }
+
+    assert!(allocator.is_fully_free());
+}
+
+#[cfg(not(feature = "heap_debugging"))]
+fn fill(pointer : usize, size : usize) {
+    for i in 0 .. size {
+        unsafe { *((pointer + i) as *mut u8) = i as u8; }
+    }
+}
+
+#[cfg(not(feature = "heap_debugging"))]
+fn assert_filled(pointer : usize, size : usize) {
+    for i in 0 .. size {
+        assert_eq!(unsafe { *((pointer + i) as *const u8) }, i as u8, "Data at offset {} wasn't preserved by reallocation", i);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_reallocate_in_place_inside_size_class() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(40).unwrap();
+    fill(pointer, 40);
+
+    assert_eq!(allocator.reallocate(pointer, 40, 64, 1), Some(pointer));
+    assert_eq!(allocator.reallocate(pointer, 64, 33, 1), Some(pointer));
+    assert_filled(pointer, 33);
+
+    assert_eq!(allocator.realloc_statistics(), ReallocStatistics { in_place : 2, moved : 0 });
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_move_data_when_reallocated_beyond_size_class() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(64).unwrap();
+    fill(pointer, 64);
+
+    let result = allocator.reallocate(pointer, 64, 1000, 1).unwrap();
+
+    assert_filled(result, 64);
+    assert_eq!(allocator.realloc_statistics(), ReallocStatistics { in_place : 0, moved : 1 });
+
+    allocator.free(result);
+
+    assert!(allocator.is_fully_free(), "Old block wasn't freed by reallocation");
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_move_block_of_frames_to_slab_when_shrunk() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointer = allocator.allocate(2 * FRAME_SIZE).unwrap();
+    fill(pointer, 100);
+
+    let result = allocator.reallocate(pointer, 2 * FRAME_SIZE, 100, 1).unwrap();
+
+    assert_filled(result, 100);
+
+    allocator.free(result);
+
+    assert!(allocator.is_fully_free());
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_grow_blocks_of_frames_preserving_data() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let mut size = FRAME_SIZE;
+
+    let mut pointer = allocator.allocate(size).unwrap();
+    fill(pointer, size);
+
+    while size < 16 * FRAME_SIZE {
+        pointer = allocator.reallocate(pointer, size, size * 2, 1).expect("Block of frames wasn't reallocated");
+        size *= 2;
+
+        assert_filled(pointer, FRAME_SIZE);
+    }
+
+    let statistics = allocator.realloc_statistics();
+
+    assert_eq!(statistics.in_place + statistics.moved, 4);
+
+    allocator.free(pointer);
+
+    assert!(allocator.is_fully_free());
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_count_live_objects_and_slabs_of_size_class() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let pointers : Vec<usize> = (0 .. 3).map(|_| allocator.allocate(64).unwrap()).collect();
+    allocator.free(pointers[0]);
+
+    let statistics = allocator.statistics();
+
+    // 64 bytes is the second size class after 32 bytes
+    assert_eq!(statistics.size_classes[1], SizeClassStatistics { live_objects : 2, slabs : 1, cached_slabs : 0, peak_live_objects : 3, failed_allocations : 0 });
+    assert_eq!(statistics.live_objects(), 2);
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_count_blocks_of_frames_and_failed_allocations() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let block = allocator.allocate(2 * FRAME_SIZE).unwrap();
+
+    assert_eq!(allocator.allocate(2 * FRAMES_COUNT * FRAME_SIZE), None);
+
+    let statistics = allocator.statistics();
+
+    assert_eq!(statistics.frame_blocks, SizeClassStatistics { live_objects : 1, slabs : 0, cached_slabs : 0, peak_live_objects : 1, failed_allocations : 1 });
+
+    allocator.free(block);
+
+    assert_eq!(allocator.statistics().frame_blocks.live_objects, 0);
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_have_empty_statistics_diff_after_every_object_was_freed() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let before = allocator.statistics();
+
+    let pointers : Vec<usize> = [32, 100, 2000, 3 * FRAME_SIZE]
+        .iter()
+        .map(|&size| allocator.allocate(size).unwrap())
+        .collect();
+
+    for pointer in pointers {
+        allocator.free(pointer);
+    }
+
+    // empty slabs are cached, but their frames are still taken from frame allocator
+    allocator.shrink();
+
+    let difference = allocator.statistics().diff(&before);
+
+    assert!(difference.is_empty(), "Statistics diff isn't empty: {:?}", difference);
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_show_live_objects_and_taken_frames_in_statistics_diff() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let before = allocator.statistics();
+
+    allocator.allocate(100).unwrap();
+
+    let difference = allocator.statistics().diff(&before);
+
+    assert!(!difference.is_empty());
+    assert_eq!(difference.live_objects[2], 1);
+    assert_eq!(difference.slabs[2], 1);
+    assert!(difference.free_blocks.iter().any(|&d| d != 0), "Frames taken by slab aren't visible in free blocks diff");
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_cache_empty_slab_and_reuse_it() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+
+    let object = allocator.allocate(64).unwrap();
+    allocator.free(object);
+
+    let cached = allocator.statistics();
+
+    assert_eq!(cached.size_classes[1].slabs, 0);
+    assert_eq!(cached.size_classes[1].cached_slabs, 1);
+
+    allocator.allocate(64).unwrap();
+
+    let difference = allocator.statistics().diff(&cached);
+
+    assert_eq!(difference.slabs[1], 1);
+    assert!(difference.free_blocks.iter().all(|&d| d == 0), "Slab wasn't created from cached frames");
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_not_cache_more_empty_slabs_than_limit() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    allocator.set_cached_slabs_limit(2);
+
+    // two objects of 2048 bytes fit a slab
+    let objects : Vec<usize> = (0 .. 8).map(|_| allocator.allocate(2048).unwrap()).collect();
+
+    assert_eq!(allocator.statistics().size_classes[6].slabs, 4);
+
+    for object in objects {
+        allocator.free(object);
+    }
+
+    assert_eq!(allocator.statistics().size_classes[6].cached_slabs, 2);
+
+    allocator.set_cached_slabs_limit(0);
+
+    assert_eq!(allocator.statistics().size_classes[6].cached_slabs, 0);
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_return_cached_slabs_to_frame_allocator_on_shrink() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    let before = allocator.statistics();
+
+    let objects : Vec<usize> = [32, 64, 128].iter().map(|&size| allocator.allocate(size).unwrap()).collect();
+
+    for object in objects {
+        allocator.free(object);
+    }
+
+    assert_eq!(allocator.shrink(), 6);
+    assert_eq!(allocator.shrink(), 0);
+    assert!(allocator.statistics().diff(&before).is_empty());
+}
+
+#[test]
+#[cfg(not(feature = "heap_debugging"))]
+pub fn should_reclaim_cached_slabs_before_running_out_of_memory() {
+    let mut allocator = slab_allocator(FRAMES_COUNT);
+    allocator.set_cached_slabs_limit(MAX_CACHED_SLABS);
+
+    let mut objects = Vec::new();
+
+    while let Some(object) = allocator.allocate(2048) {
+        objects.push(object);
+    }
+
+    for object in objects {
+        allocator.free(object);
+    }
+
+    assert_eq!(allocator.statistics().size_classes[6].cached_slabs, MAX_CACHED_SLABS);
+
+    let mut blocks = 0;
+
+    while allocator.allocate(FRAME_SIZE).is_some() {
+        blocks += 1;
+    }
+
+    // every frame but the one with slab array is handed out, including frames of cached slabs
+    assert_eq!(blocks, FRAMES_COUNT - 1);
+    assert_eq!(allocator.statistics().size_classes[6].cached_slabs, 0);
+}
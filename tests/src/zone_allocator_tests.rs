@@ -0,0 +1,120 @@
+use memory::address::PhysAddr;
+use memory::allocator::zone::{Zone, ZonedFrameAllocator};
+use memory::frame::{PhysFrame, FRAME_SIZE};
+use memory::frame::regions::{MemoryRegion, MemoryRegions};
+use memory_regions_tests::{memory_map_tag, memory_map};
+use alloc::heap;
+
+const AVAILABLE : u64 = 1;
+const RESERVED : u64 = 2;
+
+const MIB : u64 = 1024 * 1024;
+const GIB : u64 = 1024 * MIB;
+
+fn regions(memory : &[(u64, u64, u64)]) -> MemoryRegions {
+    let tag = memory_map_tag(memory);
+
+    MemoryRegions::from_memory_map(memory_map(&tag))
+}
+
+// memory itself is never touched by buddy allocators, only aux data structures must exist
+fn zoned_allocator(memory : &[(u64, u64, u64)]) -> ZonedFrameAllocator {
+    let regions = regions(memory);
+    let aux_data_structures_size = ZonedFrameAllocator::aux_data_structures_size_for(&regions);
+    let aux_start_address = unsafe { heap::allocate_zeroed(aux_data_structures_size, 4096) as usize };
+
+    ZonedFrameAllocator::from_regions(&regions, aux_start_address, 0)
+}
+
+fn region(start_address : u64, end_address : u64) -> MemoryRegion {
+    MemoryRegion::new(PhysAddr::new(start_address as usize), PhysAddr::new(end_address as usize))
+}
+
+fn zone_of(frame : PhysFrame) -> Zone {
+    Zone::containing_address(frame.start_address())
+}
+
+#[test]
+pub fn should_split_memory_map_between_zones() {
+    let regions = regions(&[
+        (0x1000, 0x9e000, AVAILABLE),
+        (0x9f000, 0x61000, RESERVED),
+        (0x100000, 0xff00000, AVAILABLE),
+        (4 * GIB, 0x100000, AVAILABLE)
+    ]);
+
+    let dma : Vec<MemoryRegion> = Zone::Dma.regions(&regions).iter().collect();
+    let dma32 : Vec<MemoryRegion> = Zone::Dma32.regions(&regions).iter().collect();
+    let normal : Vec<MemoryRegion> = Zone::Normal.regions(&regions).iter().collect();
+
+    assert_eq!(dma, vec![region(0x1000, 0x9efff), region(0x100000, 16 * MIB - 1)]);
+    assert_eq!(dma32, vec![region(16 * MIB, 0xfffffff)]);
+    assert_eq!(normal, vec![region(4 * GIB, 4 * GIB + 0xfffff)]);
+}
+
+#[test]
+pub fn normal_zone_should_end_at_highest_physical_address() {
+    assert_eq!(Zone::Normal.end_address(), PhysAddr::max());
+    assert!(Zone::containing_address(PhysAddr::max()) == Zone::Normal);
+}
+
+#[test]
+pub fn should_allocate_aligned_dma_buffer_below_16_mib() {
+    let mut allocator = zoned_allocator(&[(0x100000, 63 * MIB, AVAILABLE)]);
+
+    let buffer = allocator.allocate_contiguous(0x10000, Zone::Dma, 0x10000).unwrap();
+
+    assert!(zone_of(buffer) == Zone::Dma, "Buffer {} is above 16 MiB", buffer.start_address());
+    assert!(buffer.start_address().is_aligned(0x10000), "Buffer {} is misaligned", buffer.start_address());
+    assert!(!allocator.has_zone(Zone::Normal));
+}
+
+#[test]
+pub fn should_fall_back_to_lower_zone_when_zone_is_exhausted() {
+    let mut allocator = zoned_allocator(&[(0x100000, 15 * MIB, AVAILABLE), (16 * MIB, 4 * FRAME_SIZE as u64, AVAILABLE)]);
+
+    let frames : Vec<PhysFrame> = (0 .. 5).map(|_| allocator.allocate_contiguous(FRAME_SIZE, Zone::Normal, FRAME_SIZE).unwrap()).collect();
+
+    // there is no memory above 4 GiB, so DMA32 is used before DMA
+    assert!(frames[.. 4].iter().all(|f| zone_of(*f) == Zone::Dma32), "Frames of DMA zone are used while DMA32 has free memory");
+    assert!(zone_of(frames[4]) == Zone::Dma, "Exhausted DMA32 zone didn't fall back to DMA");
+    assert_eq!(allocator.free_frames_count(Zone::Dma32), 0);
+}
+
+#[test]
+pub fn should_not_fall_back_to_higher_zone() {
+    let mut allocator = zoned_allocator(&[(16 * MIB, 16 * MIB, AVAILABLE)]);
+
+    assert!(allocator.allocate_contiguous(FRAME_SIZE, Zone::Dma, FRAME_SIZE).is_none(), "DMA allocation was served above 16 MiB");
+    assert!(allocator.allocate_contiguous(FRAME_SIZE, Zone::Dma32, FRAME_SIZE).is_some());
+}
+
+#[test]
+pub fn should_keep_dma32_allocations_below_4_gib() {
+    let mut allocator = zoned_allocator(&[(0x100000, 0x100000, AVAILABLE), (4 * GIB, 0x10000, AVAILABLE)]);
+
+    let dma32 = allocator.allocate_contiguous(0x2000, Zone::Dma32, FRAME_SIZE).unwrap();
+    let normal = allocator.allocate_contiguous(0x2000, Zone::Normal, FRAME_SIZE).unwrap();
+
+    assert!(zone_of(dma32) == Zone::Dma, "DMA32 allocation was served from {}", zone_of(dma32));
+    assert!(zone_of(normal) == Zone::Normal, "Normal allocation was served from {}", zone_of(normal));
+}
+
+#[test]
+pub fn should_return_freed_block_to_its_zone() {
+    let mut allocator = zoned_allocator(&[(0x100000, 0x100000, AVAILABLE), (16 * MIB, 0x100000, AVAILABLE)]);
+    let dma_free_frames = allocator.free_frames_count(Zone::Dma);
+    let dma32_free_frames = allocator.free_frames_count(Zone::Dma32);
+
+    let dma = allocator.allocate_contiguous(0x8000, Zone::Dma, FRAME_SIZE).unwrap();
+    let dma32 = allocator.allocate_contiguous(0x8000, Zone::Dma32, FRAME_SIZE).unwrap();
+
+    assert_eq!(allocator.free_frames_count(Zone::Dma), dma_free_frames - 8);
+    assert_eq!(allocator.free_frames_count(Zone::Dma32), dma32_free_frames - 8);
+
+    allocator.deallocate(dma);
+    allocator.deallocate(dma32);
+
+    assert_eq!(allocator.free_frames_count(Zone::Dma), dma_free_frames);
+    assert_eq!(allocator.free_frames_count(Zone::Dma32), dma32_free_frames);
+}